@@ -0,0 +1,157 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use nostr::{
+    event::{Event, EventId, Kind, TagKind},
+    key::PublicKey,
+    types::Timestamp,
+};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::warn;
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Failed to open audit log at {0}: {1}")]
+    FileOpen(PathBuf, #[source] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditDirection {
+    In,
+    Out,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditRecord {
+    timestamp: Timestamp,
+    direction: AuditDirection,
+    id: EventId,
+    kind: Kind,
+    pubkey: PublicKey,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recipient: Option<PublicKey>,
+}
+
+/// Shared handle to the audit log, a no-op when no path is configured.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    writer: Option<Arc<Mutex<BufWriter<File>>>>,
+}
+
+impl AuditLog {
+    pub fn open(path: Option<&str>) -> Result<Self, AuditError> {
+        let Some(path) = path.map(PathBuf::from) else {
+            return Ok(Self::default());
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| AuditError::FileOpen(path, e))?;
+
+        Ok(Self {
+            writer: Some(Arc::new(Mutex::new(BufWriter::new(file)))),
+        })
+    }
+
+    pub fn record(&self, direction: AuditDirection, event: &Event) {
+        let Some(log) = &self.writer else {
+            return;
+        };
+
+        let recipient = event
+            .tags
+            .iter()
+            .find(|t| t.kind() == TagKind::p())
+            .and_then(|t| t.content())
+            .and_then(|pk| PublicKey::parse(pk).ok());
+
+        let record = AuditRecord {
+            timestamp: Timestamp::now(),
+            direction,
+            id: event.id,
+            kind: event.kind,
+            pubkey: event.pubkey,
+            recipient,
+        };
+
+        let Ok(mut writer) = log.lock() else {
+            return;
+        };
+        let written = serde_json::to_writer(&mut *writer, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"));
+        if let Err(e) = written {
+            warn!("Failed to write audit record for {}: {e}", event.id);
+        }
+    }
+
+    pub fn flush(&self) {
+        let Some(log) = &self.writer else {
+            return;
+        };
+
+        if let Ok(mut writer) = log.lock() {
+            if let Err(e) = writer.flush() {
+                warn!("Failed to flush audit log: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{
+        Keys,
+        event::{EventBuilder, Tag},
+    };
+
+    #[test]
+    fn record_appends_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let path_str = path.to_str().unwrap();
+
+        let audit = AuditLog::open(Some(path_str)).unwrap();
+
+        let recipient = Keys::generate().public_key();
+        let event = EventBuilder::new(Kind::JobRequest(5300), "")
+            .tag(Tag::public_key(recipient))
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        audit.record(AuditDirection::In, &event);
+        audit.clone().record(AuditDirection::Out, &event);
+        audit.flush();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["direction"], "in");
+        assert_eq!(lines[1]["direction"], "out");
+        assert_eq!(lines[0]["id"], event.id.to_hex());
+        assert_eq!(lines[0]["kind"], 5300);
+        assert_eq!(lines[0]["pubkey"], event.pubkey.to_hex());
+        assert_eq!(lines[0]["recipient"], recipient.to_hex());
+    }
+
+    #[test]
+    fn unconfigured_log_is_noop() {
+        let audit = AuditLog::open(None).unwrap();
+        let event = EventBuilder::new(Kind::JobRequest(5300), "")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        audit.record(AuditDirection::In, &event);
+        audit.flush();
+    }
+}
@@ -1,28 +1,323 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
 use anyhow::Result;
 use config::{Config, ConfigError, File};
-use nostr::Metadata;
-use serde::{Deserialize, Serialize};
+use nostr::{Kind, Metadata, PublicKey};
+use radroots_common::KIND_JOB_REQUEST;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use thiserror::Error;
 use tracing::{error, warn};
 
+use crate::utils::{price::approx_eq, unit::Unit};
+
 #[derive(Debug, Error)]
 pub enum SettingsError {
     #[error("Configuration loading failed: {0}")]
     Load(#[from] ConfigError),
+
+    #[error("No relays configured, pass --relays or set relays in the config file")]
+    MissingRelays,
+
+    #[error("Invalid NIP-05 identifier in metadata: {0}")]
+    InvalidNip05(String),
+
+    #[error("Invalid lightning address in metadata lud16: {0}")]
+    InvalidLud16(String),
+
+    #[error("Invalid public key in requester list: {0}")]
+    InvalidPubkey(String),
+
+    #[error("No job kinds configured, set job_kinds to at least one request kind")]
+    EmptyJobKinds,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayRole {
+    Read,
+    Write,
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoundingMode {
+    #[default]
+    HalfUp,
+    HalfEven,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverloadPolicy {
+    #[default]
+    Queue,
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TierSelection {
+    #[default]
+    Exact,
+    BestFit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PricePolicy {
+    #[default]
+    Exact,
+    AtMost,
+    AtLeast,
+}
+
+impl PricePolicy {
+    pub fn accepts(&self, requested: f64, listed: f64, rel_tol: f64) -> bool {
+        if approx_eq(requested, listed, rel_tol) {
+            return true;
+        }
+        match self {
+            Self::Exact => false,
+            Self::AtMost => requested < listed,
+            Self::AtLeast => requested > listed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlInputs {
+    #[default]
+    Disabled,
+    Public,
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Compact,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RelaySettingsEntry")]
+pub struct RelaySettings {
+    pub url: String,
+    pub role: RelayRole,
+    /// Global rather than per relay: any entry with `auth` set makes the client answer NIP-42
+    /// challenges from every configured relay.
+    pub auth: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RelaySettingsEntry {
+    Url(String),
+    Entry {
+        url: String,
+        #[serde(default)]
+        role: RelayRole,
+        #[serde(default)]
+        auth: bool,
+    },
+}
+
+impl From<RelaySettingsEntry> for RelaySettings {
+    fn from(entry: RelaySettingsEntry) -> Self {
+        match entry {
+            RelaySettingsEntry::Url(url) => Self {
+                url,
+                role: RelayRole::Both,
+                auth: false,
+            },
+            RelaySettingsEntry::Entry { url, role, auth } => Self { url, role, auth },
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectSettings {
+    pub base_ms: u64,
+    pub max_ms: u64,
+    pub multiplier: f64,
+    pub reset_after_secs: u64,
+}
+
+impl Default for ReconnectSettings {
+    fn default() -> Self {
+        Self {
+            base_ms: 1_000,
+            max_ms: 60_000,
+            multiplier: 2.0,
+            reset_after_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    pub capacity: usize,
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            ttl_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListingIndexSettings {
+    pub kind: u16,
+    pub relays: Vec<String>,
+    pub capacity: usize,
+}
+
+impl Default for ListingIndexSettings {
+    fn default() -> Self {
+        Self {
+            kind: Kind::ClassifiedListing.as_u16(),
+            relays: Vec::new(),
+            capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAreaSettings {
+    pub lat: f64,
+    pub lng: f64,
+    pub radius_km: f64,
+    #[serde(default)]
+    pub require_location: bool,
+    #[serde(default)]
+    pub min_geohash_precision: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RequesterPolicy {
+    allow: HashSet<PublicKey>,
+    deny: HashSet<PublicKey>,
+}
+
+impl RequesterPolicy {
+    pub fn allows(&self, public_key: &PublicKey) -> bool {
+        !self.deny.contains(public_key)
+            && (self.allow.is_empty() || self.allow.contains(public_key))
+    }
+}
+
+impl TryFrom<&Settings> for RequesterPolicy {
+    type Error = SettingsError;
+
+    fn try_from(settings: &Settings) -> Result<Self, Self::Error> {
+        let parse = |keys: &[String]| {
+            keys.iter()
+                .map(|key| {
+                    PublicKey::parse(key.trim())
+                        .map_err(|_| SettingsError::InvalidPubkey(key.clone()))
+                })
+                .collect::<Result<HashSet<_>, _>>()
+        };
+
+        Ok(Self {
+            allow: parse(&settings.allow_pubkeys)?,
+            deny: parse(&settings.deny_pubkeys)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub metadata: Metadata,
+    pub relays: Vec<RelaySettings>,
+    pub job_kinds: Vec<u16>,
+    pub respond_to_unaddressed: bool,
+    pub reconnect: ReconnectSettings,
+    pub dedup: CacheSettings,
+    pub event_cache: CacheSettings,
+    pub quote_cache: CacheSettings,
+    pub fetch_timeout_ms: u64,
+    pub job_timeout_ms: u64,
+    pub connect_timeout_ms: u64,
+    pub min_connected_relays: usize,
+    pub encrypt_results: bool,
+    pub dry_run: bool,
+    pub health_addr: Option<String>,
+    pub shutdown_grace_secs: u64,
+    pub max_concurrent_jobs: usize,
+    pub overload_policy: OverloadPolicy,
+    pub store_path: Option<String>,
+    pub store: CacheSettings,
+    pub audit_log: Option<String>,
+    pub result_webhook: Option<String>,
+    pub url_inputs: UrlInputs,
+    pub unsigned_inline_listings: bool,
+    pub backfill_secs: u64,
+    pub min_bid_msat: Option<u64>,
+    pub msat_rates: HashMap<String, f64>,
+    pub service_area: Option<ServiceAreaSettings>,
+    pub listing_index: Option<ListingIndexSettings>,
+    pub tax_rate_percent: Option<f64>,
+    pub rounding: RoundingMode,
+    pub price_policy: PricePolicy,
+    pub tier_selection: TierSelection,
+    pub default_currency: Option<String>,
+    pub default_unit: Option<Unit>,
+    pub log_format: LogFormat,
+    pub allow_pubkeys: Vec<String>,
+    pub deny_pubkeys: Vec<String>,
 }
 
 impl Settings {
+    pub fn fetch_timeout(&self) -> Duration {
+        Duration::from_millis(self.fetch_timeout_ms)
+    }
+
+    pub fn job_timeout(&self) -> Duration {
+        Duration::from_millis(self.job_timeout_ms)
+    }
+
+    /// The kind accepted as a listing: the indexed kind when a listing index is configured.
+    pub fn listing_kind(&self) -> Kind {
+        self.listing_index
+            .as_ref()
+            .map_or(Kind::ClassifiedListing, |index| Kind::from(index.kind))
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    pub fn backfill(&self) -> Duration {
+        Duration::from_secs(self.backfill_secs)
+    }
+
     pub fn load(config_path: &Option<String>) -> Result<Self, SettingsError> {
         let default = Self::default();
 
         match Self::load_from_file(config_path) {
             Ok(settings) => Ok(settings),
-            Err(err) if config_path.is_none() => {
+            Err(err @ SettingsError::Load(_)) if config_path.is_none() => {
                 warn!("Could not read config file: {err}. Using default configuration.",);
                 Ok(default)
             }
@@ -38,8 +333,135 @@ impl Settings {
             .build()?
             .try_deserialize::<Settings>()?;
 
+        let config = config
+            .with_metadata_defaults()
+            .with_nip05_normalized()?
+            .with_lud16_validated()?;
+        if config.job_kinds.is_empty() {
+            return Err(SettingsError::EmptyJobKinds);
+        }
+        RequesterPolicy::try_from(&config)?;
         Ok(config)
     }
+
+    /// Only the first key profile answers unaddressed requests, so each is answered once.
+    pub fn for_profile(&self, index: usize) -> Self {
+        Self {
+            respond_to_unaddressed: self.respond_to_unaddressed && index == 0,
+            ..self.clone()
+        }
+    }
+
+    pub fn relays(&self, cli_relays: &[String]) -> Result<Vec<RelaySettings>, SettingsError> {
+        let mut relays: Vec<RelaySettings> = cli_relays
+            .iter()
+            .map(|url| RelaySettings {
+                url: url.clone(),
+                role: RelayRole::Both,
+                auth: false,
+            })
+            .collect();
+
+        for relay in &self.relays {
+            if !relays.iter().any(|r| r.url == relay.url) {
+                relays.push(relay.clone());
+            }
+        }
+
+        if relays.is_empty() {
+            return Err(SettingsError::MissingRelays);
+        }
+
+        Ok(relays)
+    }
+
+    fn with_metadata_defaults(mut self) -> Self {
+        let default = Self::default().metadata;
+        let metadata = &mut self.metadata;
+
+        metadata.name = non_empty(metadata.name.take()).or(default.name);
+        metadata.display_name = non_empty(metadata.display_name.take()).or(default.display_name);
+        metadata.about = non_empty(metadata.about.take()).or(default.about);
+        metadata.picture = non_empty(metadata.picture.take()).or(default.picture);
+        metadata.banner = non_empty(metadata.banner.take());
+        metadata.website = non_empty(metadata.website.take());
+        metadata.lud06 = non_empty(metadata.lud06.take());
+        metadata.lud16 = non_empty(metadata.lud16.take());
+
+        self
+    }
+
+    fn with_nip05_normalized(mut self) -> Result<Self, SettingsError> {
+        if let Some(nip05) = non_empty(self.metadata.nip05.take()) {
+            self.metadata.nip05 = Some(nip05_normalize(&nip05, self.metadata.name.as_deref())?);
+        }
+        Ok(self)
+    }
+
+    fn with_lud16_validated(mut self) -> Result<Self, SettingsError> {
+        if let Some(lud16) = self.metadata.lud16.take() {
+            let lud16 = lud16.trim().to_lowercase();
+            let valid = lud16
+                .split_once('@')
+                .is_some_and(|(name, domain)| internet_identifier_is_valid(name, domain));
+            if !valid {
+                return Err(SettingsError::InvalidLud16(lud16));
+            }
+            self.metadata.lud16 = Some(lud16);
+        }
+        Ok(self)
+    }
+}
+
+fn nip05_normalize(value: &str, default_name: Option<&str>) -> Result<String, SettingsError> {
+    let value = value.trim();
+
+    let (name, domain) = match value.rsplit_once('@') {
+        Some((name, domain)) => (name.trim(), domain),
+        None => (default_name.unwrap_or("_"), value),
+    };
+
+    let domain = domain.trim();
+    let domain = domain
+        .strip_prefix("https://")
+        .or_else(|| domain.strip_prefix("http://"))
+        .unwrap_or(domain)
+        .trim_end_matches('/')
+        .to_lowercase();
+
+    let name = name.to_lowercase();
+    if !internet_identifier_is_valid(&name, &domain) {
+        return Err(SettingsError::InvalidNip05(value.to_string()));
+    }
+
+    Ok(format!("{name}@{domain}"))
+}
+
+fn internet_identifier_is_valid(name: &str, domain: &str) -> bool {
+    let name_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    let domain_valid = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    name_valid && domain_valid
+}
+
+pub fn config_file_load<T: DeserializeOwned>(path: &str) -> Result<T, SettingsError> {
+    Ok(Config::builder()
+        .add_source(File::with_name(path))
+        .build()?
+        .try_deserialize()?)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
 }
 
 impl Default for Settings {
@@ -47,8 +469,322 @@ impl Default for Settings {
         Self {
             metadata: Metadata {
                 name: Some("rhi".to_string()),
+                display_name: Some("rhizome".to_string()),
+                about: Some(env!("CARGO_PKG_DESCRIPTION").to_string()),
                 ..Default::default()
             },
+            relays: vec![],
+            job_kinds: vec![KIND_JOB_REQUEST],
+            respond_to_unaddressed: true,
+            reconnect: ReconnectSettings::default(),
+            dedup: CacheSettings::default(),
+            event_cache: CacheSettings {
+                capacity: 1_000,
+                ttl_secs: 60,
+            },
+            quote_cache: CacheSettings {
+                capacity: 1_000,
+                ttl_secs: 30,
+            },
+            fetch_timeout_ms: 5_000,
+            job_timeout_ms: 60_000,
+            connect_timeout_ms: 10_000,
+            min_connected_relays: 1,
+            encrypt_results: false,
+            dry_run: false,
+            health_addr: None,
+            shutdown_grace_secs: 10,
+            max_concurrent_jobs: 64,
+            overload_policy: OverloadPolicy::default(),
+            store_path: None,
+            store: CacheSettings {
+                capacity: 100_000,
+                ttl_secs: 604_800,
+            },
+            audit_log: None,
+            result_webhook: None,
+            url_inputs: UrlInputs::default(),
+            unsigned_inline_listings: false,
+            backfill_secs: 0,
+            min_bid_msat: None,
+            msat_rates: HashMap::new(),
+            service_area: None,
+            listing_index: None,
+            tax_rate_percent: None,
+            rounding: RoundingMode::default(),
+            price_policy: PricePolicy::default(),
+            tier_selection: TierSelection::default(),
+            default_currency: None,
+            default_unit: None,
+            log_format: LogFormat::default(),
+            allow_pubkeys: vec![],
+            deny_pubkeys: vec![],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use nostr::{Keys, ToBech32};
+
+    use super::*;
+
+    fn settings_load_toml(toml: &str) -> Result<Settings, SettingsError> {
+        let mut file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        file.write_all(toml.as_bytes()).unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        Settings::load(&Some(path))
+    }
+
+    fn settings_from_toml(toml: &str) -> Settings {
+        settings_load_toml(toml).unwrap()
+    }
+
+    #[test]
+    fn metadata_defaults_fill_empty_fields() {
+        let settings = settings_from_toml(
+            r#"
+            [metadata]
+            display_name = ""
+            about = "  "
+            picture = "https://rhi.test/picture.png"
+            "#,
+        );
+
+        let default = Settings::default().metadata;
+        assert_eq!(settings.metadata.display_name, default.display_name);
+        assert_eq!(settings.metadata.about, default.about);
+        assert_eq!(
+            settings.metadata.picture.as_deref(),
+            Some("https://rhi.test/picture.png")
+        );
+    }
+
+    #[test]
+    fn log_format_parsed() {
+        for (value, format) in [
+            ("pretty", LogFormat::Pretty),
+            ("compact", LogFormat::Compact),
+            ("json", LogFormat::Json),
+        ] {
+            let settings = settings_from_toml(&format!("log_format = \"{value}\""));
+            assert_eq!(settings.log_format, format);
+        }
+    }
+
+    #[test]
+    fn nip05_normalized() {
+        let nip05 = |value: &str| nip05_normalize(value, Some("rhi")).unwrap();
+
+        assert_eq!(nip05(" Bob@Example.COM "), "bob@example.com");
+        assert_eq!(nip05("https://example.com/"), "rhi@example.com");
+        assert_eq!(nip05("_@sub.example.com"), "_@sub.example.com");
+        assert_eq!(
+            nip05_normalize("example.com", None).unwrap(),
+            "_@example.com"
+        );
+    }
+
+    #[test]
+    fn nip05_invalid() {
+        for value in [
+            "bob@localhost",
+            "b ob@example.com",
+            "@example.com",
+            "bob@-x.com",
+        ] {
+            assert!(matches!(
+                nip05_normalize(value, Some("rhi")),
+                Err(SettingsError::InvalidNip05(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn nip05_normalized_on_load() {
+        let settings = settings_from_toml(
+            r#"
+            [metadata]
+            name = "rhi"
+            nip05 = "Example.com"
+            "#,
+        );
+
+        assert_eq!(settings.metadata.nip05.as_deref(), Some("rhi@example.com"));
+    }
+
+    #[test]
+    fn metadata_optional_fields_on_load() {
+        let settings = settings_from_toml(
+            r#"
+            [metadata]
+            banner = "https://rhi.test/banner.png"
+            website = " "
+            lud06 = "lnurl1rhi"
+            lud16 = " Tips@Rhi.TEST "
+            "#,
+        );
+
+        let metadata = &settings.metadata;
+        assert_eq!(
+            metadata.banner.as_deref(),
+            Some("https://rhi.test/banner.png")
+        );
+        assert_eq!(metadata.website, None);
+        assert_eq!(metadata.lud06.as_deref(), Some("lnurl1rhi"));
+        assert_eq!(metadata.lud16.as_deref(), Some("tips@rhi.test"));
+    }
+
+    #[test]
+    fn lud16_invalid() {
+        for value in ["rhi.test", "tips@localhost", "ti ps@rhi.test"] {
+            let toml = format!("[metadata]\nlud16 = \"{value}\"");
+
+            assert!(matches!(
+                settings_load_toml(&toml),
+                Err(SettingsError::InvalidLud16(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn job_kinds_empty_rejected() {
+        assert!(matches!(
+            settings_load_toml("job_kinds = []"),
+            Err(SettingsError::EmptyJobKinds)
+        ));
+        assert_eq!(
+            settings_from_toml("job_kinds = [5300, 5301]").job_kinds,
+            vec![5300, 5301]
+        );
+    }
+
+    #[test]
+    fn price_policy_accepts() {
+        let accepts = |policy: PricePolicy, requested| policy.accepts(requested, 12.0, 1e-9);
+
+        assert!(accepts(PricePolicy::Exact, 12.0));
+        assert!(!accepts(PricePolicy::Exact, 11.0));
+        assert!(accepts(PricePolicy::AtMost, 11.0));
+        assert!(!accepts(PricePolicy::AtMost, 13.0));
+        assert!(accepts(PricePolicy::AtLeast, 13.0));
+        assert!(!accepts(PricePolicy::AtLeast, 11.0));
+    }
+
+    #[test]
+    fn price_policy_from_config() {
+        let settings = settings_from_toml(r#"price_policy = "at-most""#);
+
+        assert_eq!(settings.price_policy, PricePolicy::AtMost);
+        assert_eq!(settings_from_toml("").price_policy, PricePolicy::Exact);
+    }
+
+    fn relay_urls(relays: &[RelaySettings]) -> Vec<&str> {
+        relays.iter().map(|r| r.url.as_str()).collect()
+    }
+
+    #[test]
+    fn relays_from_config_only() {
+        let settings = settings_from_toml(r#"relays = ["wss://a.test", "wss://b.test"]"#);
+
+        let relays = settings.relays(&[]).unwrap();
+
+        assert_eq!(relay_urls(&relays), ["wss://a.test", "wss://b.test"]);
+    }
+
+    #[test]
+    fn relays_from_cli_only() {
+        let settings = settings_from_toml("");
+
+        let relays = settings.relays(&["wss://cli.test".to_string()]).unwrap();
+
+        assert_eq!(relay_urls(&relays), ["wss://cli.test"]);
+    }
+
+    #[test]
+    fn relays_merged_cli_first() {
+        let settings = settings_from_toml(r#"relays = ["wss://a.test", "wss://cli.test"]"#);
+
+        let relays = settings.relays(&["wss://cli.test".to_string()]).unwrap();
+
+        assert_eq!(relay_urls(&relays), ["wss://cli.test", "wss://a.test"]);
+    }
+
+    #[test]
+    fn relays_auth_from_entries() {
+        let settings = settings_from_toml(
+            r#"relays = ["wss://a.test", { url = "wss://private.test", auth = true }]"#,
+        );
+
+        let relays = settings.relays(&["wss://cli.test".to_string()]).unwrap();
+
+        let auth: Vec<_> = relays.iter().map(|r| (r.url.as_str(), r.auth)).collect();
+        assert_eq!(
+            auth,
+            [
+                ("wss://cli.test", false),
+                ("wss://a.test", false),
+                ("wss://private.test", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn relays_missing() {
+        let settings = settings_from_toml("");
+
+        assert!(matches!(
+            settings.relays(&[]),
+            Err(SettingsError::MissingRelays)
+        ));
+    }
+
+    #[test]
+    fn requester_policy_allow_and_deny() {
+        let (allowed, denied, other) = (Keys::generate(), Keys::generate(), Keys::generate());
+        let settings = Settings {
+            allow_pubkeys: vec![
+                allowed.public_key().to_bech32().unwrap(),
+                denied.public_key().to_hex(),
+            ],
+            deny_pubkeys: vec![format!(" {} ", denied.public_key().to_hex())],
+            ..Settings::default()
+        };
+
+        let policy = RequesterPolicy::try_from(&settings).unwrap();
+
+        assert!(policy.allows(&allowed.public_key()));
+        assert!(!policy.allows(&denied.public_key()));
+        assert!(!policy.allows(&other.public_key()));
+        assert!(RequesterPolicy::default().allows(&other.public_key()));
+    }
+
+    #[test]
+    fn requester_policy_rejects_invalid_pubkey() {
+        let settings = Settings {
+            deny_pubkeys: vec!["npub-nope".into()],
+            ..Settings::default()
+        };
+
+        assert!(matches!(
+            RequesterPolicy::try_from(&settings),
+            Err(SettingsError::InvalidPubkey(_))
+        ));
+    }
+
+    #[test]
+    fn only_first_profile_answers_unaddressed() {
+        let settings = Settings::default();
+
+        assert!(settings.for_profile(0).respond_to_unaddressed);
+        assert!(!settings.for_profile(1).respond_to_unaddressed);
+
+        let settings = Settings {
+            respond_to_unaddressed: false,
+            ..Settings::default()
+        };
+        assert!(!settings.for_profile(0).respond_to_unaddressed);
+    }
+}
@@ -1,22 +1,49 @@
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use nostr::event::{Event, EventId, Tag, TagKind};
+use futures::future::BoxFuture;
+use nostr::event::{Event, EventBuilder, EventId, Tag, TagKind};
 use nostr::filter::{Alphabet, SingleLetterTag};
-use nostr::{event::Kind, key::Keys};
+use nostr::types::Timestamp;
+use nostr::{
+    event::Kind,
+    key::{Keys, PublicKey},
+    nips::nip90::DataVendingMachineStatus,
+};
 use nostr_sdk::Client;
 use nostr_sdk::RelayPoolNotification;
 use radroots_common::KIND_JOB_REQUEST;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
 use tokio::time::sleep;
-use tracing::{info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, debug, info, info_span, warn};
 
-use crate::handlers::job_request_order::{JobRequestOrderError, handle_job_request_order};
+use crate::audit::{AuditDirection, AuditLog};
+use crate::config::{OverloadPolicy, RelaySettings, RequesterPolicy, Settings};
+use crate::events::job_request_params::JobRequestParams;
+use crate::events::job_request_state::JobRequestState;
+use crate::events::job_request_store::JobRequestStoreEntry;
+use crate::events::job_request_tasks::JobRequestTasks;
+use crate::handlers::job_request_order::{
+    JobRequestOrderError, OrderUnsatisfiable, handle_job_request_order,
+};
 use crate::handlers::job_request_preview::handle_job_request_preview;
 use crate::handlers::job_request_quote::handle_job_request_quote;
+use crate::health::HealthState;
+use crate::metrics;
+use crate::utils::cache::TtlCache;
 use crate::utils::nostr::{
-    NostrTagsResolveError, NostrUtilsError, nostr_event_job_feedback, nostr_filter_kind,
-    nostr_filter_new_events, nostr_tag_at_value, nostr_tag_first_value, nostr_tag_relays_parse,
-    nostr_tag_slice, nostr_tags_resolve,
+    EventCache, NostrTagsResolveError, NostrUtilsError, Payment, nostr_client_add_relays,
+    nostr_client_connect, nostr_client_new, nostr_event_job_feedback, nostr_fetch_event_by_id,
+    nostr_fetch_job_result, nostr_filter_deletions, nostr_filter_kinds, nostr_filter_new_events,
+    nostr_filter_recent_events, nostr_send_event, nostr_send_event_with_relays, nostr_tag_at_value,
+    nostr_tag_first_value, nostr_tag_relays_parse, nostr_tag_slice, nostr_tags_resolve,
 };
 use crate::utils::unit::MassUnitError;
 
@@ -37,16 +64,102 @@ pub enum JobRequestError {
     #[error("Invalid job request input type: {0}")]
     InvalidInputType(String),
 
+    #[error("Unsupported job request output format: {0}")]
+    UnsupportedOutput(String),
+
+    #[error("Unsupported job request kind: {0}")]
+    UnsupportedKind(u16),
+
     #[error("Invalid job request input marker: {0}")]
     InvalidInputMarker(String),
 
+    #[error("Missing job request input marker (order, quote, preview): {0}")]
+    MissingInputMarker(String),
+
     #[error("Deserialization error: {0}")]
     Serde(#[from] serde_json::Error),
 
+    #[error("Invalid job input: {0}")]
+    InvalidJobInput(String),
+
+    #[error("Job request bid below the minimum of {0} msat")]
+    BidBelowMinimum(u64),
+
+    #[error("Job request canceled by requester")]
+    Canceled,
+
+    #[error("Service overloaded, try again later")]
+    Overloaded,
+
+    #[error("Processing timed out after {0:?}")]
+    TimedOut(Duration),
+
     #[error("Failure to process request")]
     Failure,
 }
 
+impl JobRequestError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::NostrUtilsError(_) => "NOSTR_ERROR",
+            Self::MassUnit(_) => "INVALID_UNIT",
+            Self::NostrTagsResolve(_) => "DECRYPTION_FAILED",
+            Self::JobRequestOrder(e) => e.error_code(),
+            Self::InvalidInputType(_) => "INVALID_INPUT_TYPE",
+            Self::UnsupportedOutput(_) => "UNSUPPORTED_OUTPUT",
+            Self::UnsupportedKind(_) => "UNSUPPORTED_KIND",
+            Self::InvalidInputMarker(_) => "INVALID_INPUT_MARKER",
+            Self::MissingInputMarker(_) => "MISSING_INPUT_MARKER",
+            Self::Serde(_) => "SERIALIZATION_ERROR",
+            Self::InvalidJobInput(_) => "INVALID_JOB_INPUT",
+            Self::BidBelowMinimum(_) => "PAYMENT_REQUIRED",
+            Self::Canceled => "CANCELED",
+            Self::Overloaded => "OVERLOADED",
+            Self::TimedOut(_) => "TIMEOUT",
+            Self::Failure => "FAILURE",
+        }
+    }
+
+    pub fn error_field(&self) -> Option<&'static str> {
+        match self {
+            Self::JobRequestOrder(JobRequestOrderError::InvalidParam(_)) => Some("param"),
+            Self::InvalidInputType(_)
+            | Self::InvalidInputMarker(_)
+            | Self::MissingInputMarker(_)
+            | Self::InvalidJobInput(_) => Some("i"),
+            Self::UnsupportedOutput(_) => Some("output"),
+            Self::BidBelowMinimum(_) => Some("bid"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobRequestFeedbackError {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<OrderUnsatisfiable>,
+}
+
+impl From<&JobRequestError> for JobRequestFeedbackError {
+    fn from(error: &JobRequestError) -> Self {
+        Self {
+            code: error.error_code(),
+            message: error.to_string(),
+            field: error.error_field(),
+            detail: match error {
+                JobRequestError::JobRequestOrder(JobRequestOrderError::Unsatisfiable(reason)) => {
+                    Some(reason.clone())
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum JobRequestInputType {
     Url,
@@ -55,6 +168,17 @@ pub enum JobRequestInputType {
     Text,
 }
 
+impl JobRequestInputType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Url => "url",
+            Self::Event => "event",
+            Self::Job => "job",
+            Self::Text => "text",
+        }
+    }
+}
+
 impl TryFrom<&str> for JobRequestInputType {
     type Error = JobRequestError;
 
@@ -76,6 +200,16 @@ pub enum JobRequestInputMarker {
     Preview,
 }
 
+impl JobRequestInputMarker {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Order => "order",
+            Self::Quote => "quote",
+            Self::Preview => "preview",
+        }
+    }
+}
+
 impl TryFrom<&str> for JobRequestInputMarker {
     type Error = JobRequestError;
 
@@ -89,6 +223,119 @@ impl TryFrom<&str> for JobRequestInputMarker {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Csv => "text/csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = JobRequestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "application/json" => Ok(Self::Json),
+            "text/csv" => Ok(Self::Csv),
+            _ => Err(JobRequestError::UnsupportedOutput(s.to_string())),
+        }
+    }
+}
+
+pub type JobResultSent = Box<dyn FnOnce(EventId) -> BoxFuture<'static, ()> + Send>;
+
+/// A job result built by a handler, sent by `process_job_request` outside the job timeout.
+pub struct JobResultPending {
+    pub event: EventBuilder,
+    pub on_sent: Option<JobResultSent>,
+}
+
+impl JobResultPending {
+    pub fn new(event: EventBuilder) -> Self {
+        Self {
+            event,
+            on_sent: None,
+        }
+    }
+}
+
+pub async fn job_result_send(
+    client: Client,
+    pending: JobResultPending,
+    job_req: &JobRequest,
+    audit: &AuditLog,
+    dry_run: bool,
+) -> Result<EventId, JobRequestError> {
+    let sent = nostr_send_event_with_relays(
+        client,
+        pending.event,
+        &job_req.result_relays(),
+        audit,
+        dry_run,
+    )
+    .await?;
+    info!("job request {} result sent: {:?}", job_req.id, sent);
+
+    // Follow-up work such as webhooks runs detached, holding neither the job timeout nor its permit.
+    if let Some(on_sent) = pending.on_sent {
+        tokio::spawn(on_sent(sent.val));
+    }
+
+    Ok(sent.val)
+}
+
+pub trait JobResultRender: Serialize {
+    /// Whether `to_csv` renders this type, so a handler can refuse CSV before doing any work.
+    const CSV: bool = false;
+
+    fn to_csv(&self) -> Option<String> {
+        None
+    }
+}
+
+pub fn render_supported<T: JobResultRender>(format: OutputFormat) -> Result<(), JobRequestError> {
+    match format {
+        OutputFormat::Csv if !T::CSV => Err(JobRequestError::UnsupportedOutput(
+            format.as_str().to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn render_result<T: JobResultRender>(
+    result: &T,
+    format: OutputFormat,
+) -> Result<(String, &'static str), JobRequestError> {
+    render_supported::<T>(format)?;
+    let payload = match format {
+        OutputFormat::Json => serde_json::to_string(result)?,
+        OutputFormat::Csv => result
+            .to_csv()
+            .ok_or_else(|| JobRequestError::UnsupportedOutput(format.as_str().to_string()))?,
+    };
+    Ok((payload, format.as_str()))
+}
+
+pub fn job_result_output_tag(mimetype: &str) -> Tag {
+    Tag::custom(TagKind::custom("output"), [mimetype])
+}
+
+pub fn job_result_event_tag(event_id: &EventId, marker: &str) -> Tag {
+    Tag::custom(
+        TagKind::e(),
+        [event_id.to_hex(), String::new(), marker.to_string()],
+    )
+}
+
 #[derive(Debug, Clone)]
 pub struct JobRequestInput {
     pub data: String,
@@ -100,66 +347,366 @@ pub struct JobRequestInput {
 #[derive(Debug, Clone)]
 pub struct JobRequest {
     pub id: EventId,
+    pub requester: PublicKey,
+    pub created_at: Timestamp,
     pub inputs: Vec<JobRequestInput>,
-    pub output: Option<String>,
+    pub output: Option<OutputFormat>,
     pub bid_msat: Option<u64>,
     pub relays: Vec<String>,
+    pub provider_relays: Vec<String>,
     pub service_providers: Vec<String>,
     pub params: Vec<(String, String)>,
     pub hashtags: Vec<String>,
+    pub expires_at: Option<Timestamp>,
+    pub alt: Option<String>,
     pub tags: Vec<Tag>,
 }
 
-pub async fn subscriber(keys: Keys, relays: Vec<String>) -> Result<()> {
-    info!("Starting subscriber for kind {}", KIND_JOB_REQUEST);
-    let client = Client::new(keys.clone());
+impl JobRequest {
+    pub fn is_expired(&self, now: Timestamp) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn job_params(&self) -> JobRequestParams<'_> {
+        JobRequestParams::new(&self.params)
+    }
+
+    pub fn idempotency_key(&self) -> Option<String> {
+        let params = self.job_params();
+        params
+            .idempotency_key()
+            .or_else(|| params.nonce())
+            .map(|key| format!("{}:{}", self.requester.to_hex(), key))
+    }
+
+    pub fn store_key(&self) -> String {
+        self.idempotency_key().unwrap_or_else(|| self.id.to_hex())
+    }
 
-    for relay in &relays {
-        client.add_relay(relay).await?;
+    pub fn result_relays(&self) -> Vec<String> {
+        let mut relays = self.relays.clone();
+        for relay in &self.provider_relays {
+            if !relays.contains(relay) {
+                relays.push(relay.clone());
+            }
+        }
+        relays
     }
+}
+
+/// Checks the outer `p` tags, so encrypted requests are matched before any decryption.
+pub fn job_request_addressed_to(event: &Event, public_key: &PublicKey) -> Option<bool> {
+    let public_key = public_key.to_hex();
+    let mut providers = event
+        .tags
+        .iter()
+        .filter(|t| t.kind() == TagKind::p())
+        .filter_map(|t| t.content())
+        .peekable();
+
+    providers.peek()?;
+    Some(providers.any(|pk| pk == public_key))
+}
 
-    let filter = nostr_filter_new_events(nostr_filter_kind(KIND_JOB_REQUEST));
+pub async fn subscriber(
+    keys: Keys,
+    relays: Vec<RelaySettings>,
+    settings: Settings,
+    health: HealthState,
+    shutdown: CancellationToken,
+    state: JobRequestState,
+    seen: TtlCache<EventId, ()>,
+) -> Result<()> {
+    info!("Starting subscriber for kinds {:?}", settings.job_kinds);
+    let client = nostr_client_new(keys.clone(), &relays);
+
+    nostr_client_add_relays(&client, &relays).await?;
+
+    let filter =
+        nostr_filter_recent_events(nostr_filter_kinds(&settings.job_kinds), settings.backfill());
+    let filter_cancel = nostr_filter_new_events(nostr_filter_deletions(&settings.job_kinds));
 
-    client.connect().await;
+    nostr_client_connect(
+        &client,
+        settings.connect_timeout(),
+        settings.min_connected_relays,
+    )
+    .await?;
+    health.set_client(keys.public_key(), client.clone());
     client.subscribe(filter, None).await?;
+    client.subscribe(filter_cancel, None).await?;
+
+    job_request_loop(
+        &keys,
+        &client,
+        &settings,
+        &shutdown,
+        &state,
+        &seen,
+        |event, keys, client, settings, state| async move {
+            handle_event(event, keys, client, &settings, &state).await
+        },
+    )
+    .await?;
+    client.disconnect().await;
+
+    Ok(())
+}
+
+/// Requests waiting on a handler under the queue policy, beyond which they are refused.
+const JOB_REQUEST_QUEUE_MAX: usize = 1024;
+
+enum JobRequestWake {
+    Notification(RelayPoolNotification),
+    Permit(OwnedSemaphorePermit),
+}
 
+async fn job_request_loop<H, Fut>(
+    keys: &Keys,
+    client: &Client,
+    settings: &Settings,
+    shutdown: &CancellationToken,
+    state: &JobRequestState,
+    seen: &TtlCache<EventId, ()>,
+    handle: H,
+) -> Result<()>
+where
+    H: Fn(Event, Keys, Client, Settings, JobRequestState) -> Fut,
+    Fut: std::future::Future<Output = Result<(), JobRequestError>> + Send + 'static,
+{
     let mut notifications = client.notifications();
+    let tasks = JobRequestTasks::default();
+    let requesters = RequesterPolicy::try_from(settings)?;
+    let permits = Arc::new(Semaphore::new(settings.max_concurrent_jobs.max(1)));
 
-    while let Ok(n) = notifications.recv().await {
-        if let RelayPoolNotification::Event { event, .. } = n {
-            if event.kind == Kind::Custom(KIND_JOB_REQUEST) {
-                let event = (*event).clone();
+    let mut handlers = JoinSet::new();
+    // Requests wait here for a permit while the loop keeps receiving, so deletions reach them.
+    let mut queued: VecDeque<(Event, CancellationToken)> = VecDeque::new();
+
+    loop {
+        let wake = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            Some(_) = handlers.join_next(), if !handlers.is_empty() => continue,
+            Ok(permit) = permits.clone().acquire_owned(), if !queued.is_empty() => {
+                JobRequestWake::Permit(permit)
+            }
+            n = notifications.recv() => match n {
+                Ok(n) => JobRequestWake::Notification(n),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("job request subscriber lagged, {skipped} notifications skipped");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        let n = match wake {
+            JobRequestWake::Notification(n) => n,
+            JobRequestWake::Permit(permit) => {
+                let (event, cancel) = queued
+                    .pop_front()
+                    .expect("permits are only awaited for queued requests");
+                let job = handle(
+                    event.clone(),
+                    keys.clone(),
+                    client.clone(),
+                    settings.clone(),
+                    state.clone(),
+                );
                 let keys = keys.clone();
                 let client = client.clone();
+                let audit = state.audit.clone();
+                let dry_run = settings.dry_run;
+                let tasks = tasks.clone();
+                let span = job_request_span(&event);
+
+                handlers.spawn(
+                    async move {
+                        let _permit = permit;
+                        let result = job_request_run(job, cancel).await;
+                        tasks.finish(&event.id);
 
-                tokio::spawn(async move {
-                    if let Err(err) =
-                        handle_event(event.clone(), keys.clone(), client.clone()).await
-                    {
-                        let _ = handle_error(err, event, keys, client, None).await;
+                        if let Err(err) = result {
+                            let _ =
+                                handle_error(err, event, keys, client, &audit, dry_run, None).await;
+                        }
                     }
-                });
+                    .instrument(span),
+                );
+                continue;
+            }
+        };
+
+        if let RelayPoolNotification::Event { event, .. } = n {
+            if settings.job_kinds.contains(&event.kind.as_u16()) {
+                if !seen.insert_if_absent(event.id, ()) {
+                    debug!("job request {} already seen, skipping", event.id);
+                    continue;
+                }
+
+                match job_request_addressed_to(&event, &keys.public_key()) {
+                    Some(false) => {
+                        debug!(
+                            "job request {} addressed to other providers, skipping",
+                            event.id
+                        );
+                        continue;
+                    }
+                    None if !settings.respond_to_unaddressed => {
+                        debug!(
+                            "job request {} not addressed to any provider, skipping",
+                            event.id
+                        );
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                state.audit.record(AuditDirection::In, &event);
+
+                if !requesters.allows(&event.pubkey) {
+                    debug!(
+                        "job request {} from {} not allowed, skipping",
+                        event.id, event.pubkey
+                    );
+                    continue;
+                }
+
+                let event = (*event).clone();
+
+                if job_request_saturated(&permits, queued.len(), settings.overload_policy) {
+                    warn!("job request {} dropped, handlers saturated", event.id);
+                    job_request_reject(
+                        &mut handlers,
+                        JobRequestError::Overloaded,
+                        event,
+                        keys,
+                        client,
+                        state,
+                        settings.dry_run,
+                    );
+                    continue;
+                }
+
+                let cancel = tasks.start(&event);
+                queued.push_back((event, cancel));
+            } else if event.kind == Kind::EventDeletion {
+                for id in tasks.cancel(&event) {
+                    info!("job request {} canceled by requester", id);
+                }
+
+                // A queued request has no handler yet to report its cancellation.
+                let (canceled, waiting): (VecDeque<_>, VecDeque<_>) = queued
+                    .drain(..)
+                    .partition(|(_, cancel)| cancel.is_cancelled());
+                queued = waiting;
+                for (event, _) in canceled {
+                    tasks.finish(&event.id);
+                    job_request_reject(
+                        &mut handlers,
+                        JobRequestError::Canceled,
+                        event,
+                        keys,
+                        client,
+                        state,
+                        settings.dry_run,
+                    );
+                }
             }
         }
     }
 
-    client.disconnect().await;
+    drain_handlers(&mut handlers, settings.shutdown_grace()).await;
 
     Ok(())
 }
 
+/// Whether a new request finds no free handler and no room to wait for one.
+fn job_request_saturated(permits: &Semaphore, queued: usize, policy: OverloadPolicy) -> bool {
+    match policy {
+        OverloadPolicy::Queue => queued >= JOB_REQUEST_QUEUE_MAX,
+        OverloadPolicy::Drop => queued >= permits.available_permits(),
+    }
+}
+
+/// Reports a request that never reached a handler, without holding up the loop.
+fn job_request_reject(
+    handlers: &mut JoinSet<()>,
+    error: JobRequestError,
+    event: Event,
+    keys: &Keys,
+    client: &Client,
+    state: &JobRequestState,
+    dry_run: bool,
+) {
+    let keys = keys.clone();
+    let client = client.clone();
+    let audit = state.audit.clone();
+    let span = job_request_span(&event);
+    handlers.spawn(
+        async move {
+            let _ = handle_error(error, event, keys, client, &audit, dry_run, None).await;
+        }
+        .instrument(span),
+    );
+}
+
+async fn job_request_run<F>(job: F, cancel: CancellationToken) -> Result<(), JobRequestError>
+where
+    F: std::future::Future<Output = Result<(), JobRequestError>>,
+{
+    tokio::select! {
+        result = job => result,
+        _ = cancel.cancelled() => Err(JobRequestError::Canceled),
+    }
+}
+
+async fn drain_handlers(handlers: &mut JoinSet<()>, grace: Duration) {
+    let pending = handlers.len();
+    if pending == 0 {
+        return;
+    }
+
+    info!("Draining {pending} job request handlers");
+
+    let drained = tokio::time::timeout(grace, async {
+        while handlers.join_next().await.is_some() {}
+    })
+    .await;
+
+    if drained.is_ok() {
+        info!("Drained {pending} job request handlers");
+    } else {
+        let remaining = handlers.len();
+        handlers.abort_all();
+        warn!(
+            "Drained {} job request handlers, cancelled {remaining} after {:?}",
+            pending - remaining,
+            grace
+        );
+    }
+}
+
 async fn handle_error(
     error: JobRequestError,
     event: Event,
     _keys: Keys,
     client: Client,
+    audit: &AuditLog,
+    dry_run: bool,
     _job_req: Option<JobRequest>,
 ) -> Result<()> {
     warn!("job_request handle_error error {}", error);
     warn!("job_request handle_error event {:?}", { event.clone() });
 
-    let builder = nostr_event_job_feedback(&event, error, "error", None)?;
-    let event_id = client.send_event_builder(builder).await?;
+    let builder = nostr_event_job_feedback(
+        &event,
+        DataVendingMachineStatus::Error,
+        Some(error),
+        None,
+        None,
+    )?;
+    let event_id = nostr_send_event(client, builder, audit, dry_run).await?;
 
     warn!("job_request handle_error sent feedback {:?}", {
         event_id.clone()
@@ -167,13 +714,79 @@ async fn handle_error(
     Ok(())
 }
 
-async fn handle_event(event: Event, keys: Keys, client: Client) -> Result<(), JobRequestError> {
+async fn handle_event(
+    event: Event,
+    keys: Keys,
+    client: Client,
+    settings: &Settings,
+    state: &JobRequestState,
+) -> Result<(), JobRequestError> {
+    // Every configured kind is served by the marketplace handlers, which answer with kind + 1000.
+    let kind = event.kind.as_u16();
+    if !settings.job_kinds.contains(&kind) {
+        return Err(JobRequestError::UnsupportedKind(kind));
+    }
+
     let job_req = parse_event(&event, &keys)?;
+
+    if let Some(alt) = &job_req.alt {
+        info!("job request {} alt: {alt}", job_req.id);
+    }
+
+    // Held until every input is dispatched, so a repeat arriving meanwhile is not computed again.
+    let _reservation = match state.store.reserve(&job_req.store_key()) {
+        Ok(reservation) => reservation,
+        Err(entry) => {
+            let (status, tags) = match entry {
+                JobRequestStoreEntry::Pending => {
+                    info!("job request {} already being processed", job_req.id);
+                    (DataVendingMachineStatus::Processing, None)
+                }
+                JobRequestStoreEntry::Answered(result_id) => {
+                    info!(
+                        "job request {} already answered with result {}",
+                        job_req.id, result_id
+                    );
+                    (
+                        DataVendingMachineStatus::Success,
+                        Some(vec![job_result_event_tag(&result_id, "result")]),
+                    )
+                }
+            };
+            let builder = nostr_event_job_feedback(&event, status, None, None, tags)?;
+            nostr_send_event(client, builder, &state.audit, settings.dry_run).await?;
+            return Ok(());
+        }
+    };
+
+    if job_req.is_expired(Timestamp::now()) {
+        warn!("job request {} expired, skipping", job_req.id);
+        return Ok(());
+    }
+
+    if let Some(min_bid_msat) = settings.min_bid_msat {
+        if job_req.bid_msat.is_none_or(|bid| bid < min_bid_msat) {
+            info!(
+                "job request {} bid {:?} below minimum {}, requesting payment",
+                job_req.id, job_req.bid_msat, min_bid_msat
+            );
+            let builder = nostr_event_job_feedback(
+                &event,
+                DataVendingMachineStatus::PaymentRequired,
+                Some(JobRequestError::BidBelowMinimum(min_bid_msat)),
+                Some((min_bid_msat, Payment::None)),
+                None,
+            )?;
+            nostr_send_event(client, builder, &state.audit, settings.dry_run).await?;
+            return Ok(());
+        }
+    }
+
     for job_req_input in &job_req.inputs {
         let marker = job_req_input
             .marker
             .as_ref()
-            .ok_or_else(|| JobRequestError::InvalidInputMarker(job_req.id.to_string()))?;
+            .ok_or_else(|| JobRequestError::MissingInputMarker(job_req.id.to_string()))?;
 
         match marker {
             JobRequestInputMarker::Order => {
@@ -182,6 +795,8 @@ async fn handle_event(event: Event, keys: Keys, client: Client) -> Result<(), Jo
                     event.clone(),
                     keys.clone(),
                     client.clone(),
+                    state.clone(),
+                    settings.clone(),
                     job_req.clone(),
                     job_req_input.clone(),
                 )
@@ -193,6 +808,8 @@ async fn handle_event(event: Event, keys: Keys, client: Client) -> Result<(), Jo
                     event.clone(),
                     keys.clone(),
                     client.clone(),
+                    state.clone(),
+                    settings.clone(),
                     job_req.clone(),
                     job_req_input.clone(),
                 )
@@ -204,6 +821,8 @@ async fn handle_event(event: Event, keys: Keys, client: Client) -> Result<(), Jo
                     event.clone(),
                     keys.clone(),
                     client.clone(),
+                    state.clone(),
+                    settings.clone(),
                     job_req.clone(),
                     job_req_input.clone(),
                 )
@@ -215,34 +834,114 @@ async fn handle_event(event: Event, keys: Keys, client: Client) -> Result<(), Jo
     Ok(())
 }
 
-fn parse_event(event: &Event, keys: &Keys) -> Result<JobRequest, JobRequestError> {
+pub async fn job_request_input_job_result(
+    client: &Client,
+    event_cache: &EventCache,
+    settings: &Settings,
+    input: &JobRequestInput,
+) -> Result<Event, JobRequestError> {
+    let event_id = EventId::parse(&input.data).map_err(|_| {
+        JobRequestError::InvalidJobInput(format!("invalid event id {}", input.data))
+    })?;
+
+    let event = nostr_fetch_event_by_id(
+        client.clone(),
+        event_cache,
+        &event_id,
+        input.relay.as_deref(),
+        settings.fetch_timeout(),
+    )
+    .await?
+    .ok_or_else(|| JobRequestError::InvalidJobInput(format!("event {event_id} not found")))?;
+
+    if event.id != event_id || event.verify().is_err() {
+        return Err(JobRequestError::InvalidJobInput(format!(
+            "event {event_id} failed signature verification"
+        )));
+    }
+
+    let event = if event.kind == Kind::Custom(KIND_JOB_REQUEST) {
+        let result = nostr_fetch_job_result(client.clone(), &event_id, settings.fetch_timeout())
+            .await?
+            .ok_or_else(|| {
+                JobRequestError::InvalidJobInput(format!("job {event_id} has no result"))
+            })?;
+        if result.verify().is_err() || !result.tags.event_ids().any(|id| id == &event_id) {
+            return Err(JobRequestError::InvalidJobInput(format!(
+                "result {} is not a verified answer to job {event_id}",
+                result.id
+            )));
+        }
+        result
+    } else {
+        event
+    };
+
+    if !event.kind.is_job_result() {
+        return Err(JobRequestError::InvalidJobInput(format!(
+            "event {} is kind {}, not a job result",
+            event.id, event.kind
+        )));
+    }
+
+    Ok(event)
+}
+
+fn job_request_span(event: &Event) -> Span {
+    info_span!(
+        "job_request",
+        id = %event.id,
+        kind = %event.kind,
+        requester = %event.pubkey
+    )
+}
+
+pub fn parse_event(event: &Event, keys: &Keys) -> Result<JobRequest, JobRequestError> {
     let tags = nostr_tags_resolve(event, keys)?;
     let mut inputs = vec![];
     let mut output = None;
     let mut bid_msat = None;
     let mut relays = vec![];
     let mut providers = vec![];
+    let mut provider_relays = vec![];
     let mut params = vec![];
     let mut hashtags = vec![];
+    let mut expires_at = None;
+    let mut alt = None;
+    let mut malformed_inputs = vec![];
+
+    if event.tags.iter().any(|t| t.kind() == TagKind::Encrypted) {
+        providers.extend(
+            event
+                .tags
+                .iter()
+                .filter(|t| t.kind() == TagKind::p())
+                .filter_map(|t| nostr_tag_at_value(t, 1)),
+        );
+    }
 
     for tag in &tags {
         match tag.kind() {
             TagKind::SingleLetter(l) if l == SingleLetterTag::lowercase(Alphabet::I) => {
                 if let Some(vals) = nostr_tag_slice(tag, 1) {
                     match &vals[..] {
-                        [data, input_type, relay, marker, ..] => {
+                        // Only the type is required, NIP-90 lets the relay and marker be left off.
+                        [data, input_type, rest @ ..] => {
                             let data = data.clone();
                             let input_type = JobRequestInputType::try_from(input_type.as_str())?;
-                            let relay = relay.clone();
-                            let marker = JobRequestInputMarker::try_from(marker.as_str())?;
+                            let relay = rest.first().filter(|r| !r.is_empty()).cloned();
+                            let marker = rest
+                                .get(1)
+                                .map(|m| JobRequestInputMarker::try_from(m.as_str()))
+                                .transpose()?;
                             inputs.push(JobRequestInput {
                                 data,
                                 input_type,
-                                relay: Some(relay),
-                                marker: Some(marker),
+                                relay,
+                                marker,
                             });
                         }
-                        _ => continue,
+                        _ => malformed_inputs.push(format!("{vals:?}")),
                     }
                 }
             }
@@ -254,7 +953,9 @@ fn parse_event(event: &Event, keys: &Keys) -> Result<JobRequest, JobRequestError
             }
 
             TagKind::Custom(ref k) if k == "output" => {
-                output = nostr_tag_first_value(tag, k);
+                output = nostr_tag_first_value(tag, k)
+                    .map(|s| s.parse::<OutputFormat>())
+                    .transpose()?;
             }
 
             TagKind::Custom(ref k) if k == "bid" => {
@@ -269,6 +970,17 @@ fn parse_event(event: &Event, keys: &Keys) -> Result<JobRequest, JobRequestError
                 }
             }
 
+            TagKind::Expiration => {
+                expires_at = tag
+                    .content()
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .map(Timestamp::from);
+            }
+
+            TagKind::Alt => {
+                alt = tag.content().map(|s| s.to_string());
+            }
+
             TagKind::Relays => {
                 if let Some(urls) = nostr_tag_relays_parse(tag) {
                     relays = urls.into_iter().map(|u| u.to_string()).collect();
@@ -276,25 +988,44 @@ fn parse_event(event: &Event, keys: &Keys) -> Result<JobRequest, JobRequestError
             }
 
             TagKind::SingleLetter(l) if l == SingleLetterTag::lowercase(Alphabet::P) => {
-                if let Some(pk) = nostr_tag_at_value(tag, 1) {
-                    providers.push(pk);
+                let Some(pk) = nostr_tag_at_value(tag, 1) else {
+                    continue;
+                };
+                // Only the hint next to our own key says where the requester expects our result.
+                if pk.eq_ignore_ascii_case(&keys.public_key().to_hex()) {
+                    if let Some(relay) = nostr_tag_at_value(tag, 2).filter(|r| !r.is_empty()) {
+                        provider_relays.push(relay);
+                    }
                 }
+                providers.push(pk);
             }
 
             _ => {}
         }
     }
 
+    if !malformed_inputs.is_empty() {
+        return Err(JobRequestError::InvalidJobInput(format!(
+            "expected [\"i\", <data>, <input-type>, <relay>, <marker>], got {}",
+            malformed_inputs.join(", ")
+        )));
+    }
+
     Ok(JobRequest {
         id: event.id,
+        requester: event.pubkey,
+        created_at: event.created_at,
         inputs,
         output,
         bid_msat,
         relays,
+        provider_relays,
         service_providers: providers,
         tags,
         params,
         hashtags,
+        expires_at,
+        alt,
     })
 }
 
@@ -303,11 +1034,13 @@ async fn process_job_request<F, Fut>(
     event: Event,
     keys: Keys,
     client: Client,
+    state: JobRequestState,
+    settings: Settings,
     job_req: JobRequest,
     job_req_input: JobRequestInput,
 ) where
-    F: FnOnce(Event, Keys, Client, JobRequest, JobRequestInput) -> Fut,
-    Fut: std::future::Future<Output = Result<(), JobRequestError>>,
+    F: FnOnce(Event, Keys, Client, JobRequestState, Settings, JobRequest, JobRequestInput) -> Fut,
+    Fut: std::future::Future<Output = Result<Option<JobResultPending>, JobRequestError>>,
 {
     if cfg!(debug_assertions) {
         sleep(Duration::from_millis(500)).await;
@@ -317,23 +1050,1471 @@ async fn process_job_request<F, Fut>(
     let error_job_req = job_req.clone();
     let error_keys = keys.clone();
     let error_client = client.clone();
+    let audit = state.audit.clone();
+    let dry_run = settings.dry_run;
+    let marker = job_req_input.marker.as_ref().map_or("none", |m| m.as_str());
+    let timeout = settings.job_timeout();
+    let store = state.store.clone();
 
-    if let Err(err) = handler(
+    metrics::job_received(marker);
+    let started = Instant::now();
+
+    let job = handler(
         event,
         keys.clone(),
         client.clone(),
+        state,
+        settings,
         job_req.clone(),
         job_req_input.clone(),
-    )
-    .await
+    );
+    let result = match tokio::time::timeout(timeout, job).await {
+        Ok(result) => result,
+        Err(_) => Err(JobRequestError::TimedOut(timeout)),
+    };
+    // Only the handler is timed, so a sent result is always recorded and never followed by an error.
+    let result = match result {
+        Ok(Some(pending)) => job_result_send(client, pending, &job_req, &audit, dry_run)
+            .await
+            .map(Some),
+        Ok(None) => Ok(None),
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(result_id) => {
+            metrics::job_succeeded(marker, started.elapsed());
+
+            if let Some(result_id) = result_id.filter(|_| !dry_run) {
+                if let Err(e) = store.record(job_req.store_key(), result_id).await {
+                    warn!("job request {} result not stored: {e}", job_req.id);
+                }
+            }
+        }
+        Err(err) => {
+            metrics::job_failed(marker, started.elapsed());
+            let _ = handle_error(
+                err,
+                error_event,
+                error_keys,
+                error_client,
+                &audit,
+                dry_run,
+                Some(error_job_req),
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use nostr::event::{EventBuilder, TagStandard};
+    use nostr::filter::Filter;
+    use nostr_relay_builder::MockRelay;
+
+    use super::*;
+    use crate::config::RelayRole;
+    use crate::events::job_request_store::JobRequestStore;
+    use crate::logging::LogBuffer;
+    use crate::models::{
+        event_classified::EventClassifiedPrice,
+        order_result::{OrderResult, OrderResultEnvelope, OrderResultReceipt, OrderResultTotal},
+        quote_result::{QuoteResult, QuoteResultPrice},
+    };
+    use crate::utils::unit::Unit;
+
+    const KIND_JOB_RESULT: u16 = KIND_JOB_REQUEST + 1000;
+
+    fn job_request_event(keys: &Keys, tags: Vec<Tag>) -> Event {
+        EventBuilder::new(Kind::Custom(KIND_JOB_REQUEST), "")
+            .tags(tags)
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    fn i_tag(values: &[&str]) -> Tag {
+        Tag::custom(
+            TagKind::SingleLetter(SingleLetterTag::lowercase(Alphabet::I)),
+            values.iter().copied(),
+        )
+    }
+
+    #[test]
+    fn seen_cache_survives_reconnect() {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![]);
+        let seen: TtlCache<EventId, ()> = TtlCache::from(&Settings::default().dedup);
+
+        let first_run = seen.clone();
+        assert!(first_run.insert_if_absent(event.id, ()));
+        drop(first_run);
+
+        let second_run = seen.clone();
+        assert!(!second_run.insert_if_absent(event.id, ()));
+    }
+
+    #[test]
+    fn error_codes_identify_variant() {
+        let order = JobRequestError::JobRequestOrder(JobRequestOrderError::MissingReference(
+            "listing".into(),
+        ));
+        assert_eq!(order.error_code(), "MISSING_REFERENCE");
+        assert_eq!(order.error_field(), None);
+
+        let marker = JobRequestError::InvalidInputMarker("bid".into());
+        assert_eq!(marker.error_code(), "INVALID_INPUT_MARKER");
+        assert_eq!(marker.error_field(), Some("i"));
+
+        let output = JobRequestError::UnsupportedOutput("text/html".into());
+        assert_eq!(output.error_code(), "UNSUPPORTED_OUTPUT");
+        assert_eq!(output.error_field(), Some("output"));
+
+        assert_eq!(JobRequestError::Canceled.error_code(), "CANCELED");
+    }
+
+    #[test]
+    fn feedback_error_serializes_code_and_message() {
+        let error = JobRequestError::MissingInputMarker("id".into());
+        let json = serde_json::to_value(JobRequestFeedbackError::from(&error)).unwrap();
+        assert_eq!(json["code"], "MISSING_INPUT_MARKER");
+        assert_eq!(json["message"], error.to_string());
+        assert_eq!(json["field"], "i");
+
+        let json =
+            serde_json::to_value(JobRequestFeedbackError::from(&JobRequestError::Failure)).unwrap();
+        assert!(json.get("field").is_none());
+    }
+
+    #[test]
+    fn feedback_error_carries_unsatisfiable_reason() {
+        let error = JobRequestError::from(JobRequestOrderError::from(
+            OrderUnsatisfiable::PriceMismatch {
+                expected: 12.0,
+                got: 11.0,
+            },
+        ));
+
+        let json = serde_json::to_value(JobRequestFeedbackError::from(&error)).unwrap();
+
+        assert_eq!(json["detail"]["reason"], "price_mismatch");
+        assert_eq!(json["detail"]["expected"], 12.0);
+        assert_eq!(json["detail"]["got"], 11.0);
+
+        let json =
+            serde_json::to_value(JobRequestFeedbackError::from(&JobRequestError::Failure)).unwrap();
+        assert!(json.get("detail").is_none());
+    }
+
+    #[test]
+    fn parse_event_i_tag_without_marker() {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![i_tag(&["abc", "event", "wss://relay.test"])]);
+
+        let job_req = parse_event(&event, &keys).unwrap();
+
+        assert_eq!(job_req.inputs.len(), 1);
+        assert_eq!(job_req.inputs[0].data, "abc");
+        assert_eq!(job_req.inputs[0].input_type, JobRequestInputType::Event);
+        assert_eq!(job_req.inputs[0].relay.as_deref(), Some("wss://relay.test"));
+        assert_eq!(job_req.inputs[0].marker, None);
+    }
+
+    #[test]
+    fn parse_event_i_tag_with_marker() {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![i_tag(&["abc", "event", "", "quote"])]);
+
+        let job_req = parse_event(&event, &keys).unwrap();
+
+        assert_eq!(job_req.inputs.len(), 1);
+        assert_eq!(job_req.inputs[0].relay, None);
+        assert_eq!(job_req.inputs[0].marker, Some(JobRequestInputMarker::Quote));
+    }
+
+    #[test]
+    fn parse_event_rejects_malformed_i_tag() {
+        let keys = Keys::generate();
+        let event = job_request_event(
+            &keys,
+            vec![
+                i_tag(&["abc", "event", ""]),
+                i_tag(&["def"]),
+                i_tag(&["ghi"]),
+            ],
+        );
+
+        let err = parse_event(&event, &keys).unwrap_err();
+
+        let JobRequestError::InvalidJobInput(detail) = err else {
+            panic!("expected invalid job input, got {err:?}");
+        };
+        assert!(detail.contains(r#"["def"]"#));
+        assert!(detail.contains(r#"["ghi"]"#));
+        assert!(!detail.contains("abc"));
+    }
+
+    #[test]
+    fn parse_event_accepts_i_tag_without_relay_or_marker() {
+        let keys = Keys::generate();
+        let event = job_request_event(
+            &keys,
+            vec![
+                i_tag(&["abc", "text"]),
+                i_tag(&["def", "event", "wss://relay.test"]),
+            ],
+        );
+
+        let job_req = parse_event(&event, &keys).unwrap();
+
+        let [text, event] = &job_req.inputs[..] else {
+            panic!("expected two inputs");
+        };
+        assert_eq!(text.data, "abc");
+        assert_eq!(text.input_type, JobRequestInputType::Text);
+        assert_eq!(text.relay, None);
+        assert!(text.marker.is_none());
+        assert_eq!(event.relay.as_deref(), Some("wss://relay.test"));
+        assert!(event.marker.is_none());
+    }
+
+    #[test]
+    fn parse_event_exposes_requester_and_created_at() {
+        let (requester, provider) = (Keys::generate(), Keys::generate());
+        let created_at = Timestamp::from(1_700_000_000);
+        let event = EventBuilder::new(Kind::Custom(KIND_JOB_REQUEST), "")
+            .tag(i_tag(&["abc", "event", ""]))
+            .custom_created_at(created_at)
+            .sign_with_keys(&requester)
+            .unwrap();
+
+        let job_req = parse_event(&event, &provider).unwrap();
+
+        assert_eq!(job_req.requester, requester.public_key());
+        assert_eq!(job_req.created_at, created_at);
+    }
+
+    #[test]
+    fn parse_event_reads_alt() {
+        let keys = Keys::generate();
+        let event = job_request_event(
+            &keys,
+            vec![
+                i_tag(&["abc", "event", ""]),
+                Tag::alt("Order quote request"),
+            ],
+        );
+
+        let job_req = parse_event(&event, &keys).unwrap();
+
+        assert_eq!(job_req.alt.as_deref(), Some("Order quote request"));
+    }
+
+    async fn handle_event_with(
+        event: &Event,
+        keys: &Keys,
+        settings: &Settings,
+    ) -> Result<(), JobRequestError> {
+        let client = Client::builder().signer(keys.clone()).build();
+        let state = JobRequestState::from(settings);
+        handle_event(event.clone(), keys.clone(), client, settings, &state).await
+    }
+
+    #[tokio::test]
+    async fn answered_request_resends_result_id() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        let settings = Settings::default();
+        // Unanswered, the unmarked input would be rejected at dispatch.
+        let event = job_request_event(&keys, vec![i_tag(&["abc", "event", ""])]);
+        let state = JobRequestState::from(&settings);
+        let result_id = EventId::from_byte_array([1; 32]);
+        state
+            .store
+            .record(event.id.to_hex(), result_id)
+            .await
+            .unwrap();
+
+        handle_event(
+            event.clone(),
+            keys.clone(),
+            client.clone(),
+            &settings,
+            &state,
+        )
+        .await
+        .unwrap();
+
+        let feedback = client
+            .fetch_events(
+                Filter::new().kind(Kind::JobFeedback),
+                Duration::from_secs(2),
+            )
+            .await
+            .unwrap();
+        let answer = feedback.first().expect("request answered with feedback");
+        assert!(answer.tags.iter().any(|t| t.as_slice()
+            == [
+                "e".to_string(),
+                result_id.to_hex(),
+                String::new(),
+                "result".to_string()
+            ]));
+    }
+
+    fn keyed_job_request(keys: &Keys, input: &str, key: &str) -> Event {
+        job_request_event(
+            keys,
+            vec![
+                i_tag(&[input, "event", ""]),
+                Tag::custom(TagKind::custom("param"), ["idempotency-key", key]),
+            ],
+        )
+    }
+
+    #[test]
+    fn store_key_scoped_by_requester() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let store_key = |event: &Event| parse_event(event, &alice).unwrap().store_key();
+
+        let first = store_key(&keyed_job_request(&alice, "abc", "order-1"));
+        assert_eq!(first, format!("{}:order-1", alice.public_key().to_hex()));
+        assert_eq!(
+            store_key(&keyed_job_request(&alice, "def", "order-1")),
+            first
+        );
+        assert_ne!(store_key(&keyed_job_request(&bob, "abc", "order-1")), first);
+
+        let unkeyed = job_request_event(&alice, vec![i_tag(&["abc", "event", ""])]);
+        assert_eq!(store_key(&unkeyed), unkeyed.id.to_hex());
+    }
+
+    #[test]
+    fn idempotency_key_preferred_over_nonce() {
+        let keys = Keys::generate();
+        let nonce = Tag::custom(TagKind::custom("param"), ["nonce", "n-1"]);
+        let store_key = |event: &Event| parse_event(event, &keys).unwrap().store_key();
+
+        let mut tags = vec![i_tag(&["abc", "event", ""]), nonce.clone()];
+        assert_eq!(
+            store_key(&job_request_event(&keys, tags.clone())),
+            format!("{}:n-1", keys.public_key().to_hex())
+        );
+
+        tags.push(Tag::custom(
+            TagKind::custom("param"),
+            ["idempotency-key", "k-1"],
+        ));
+        assert_eq!(
+            store_key(&job_request_event(&keys, tags)),
+            format!("{}:k-1", keys.public_key().to_hex())
+        );
+    }
+
+    #[tokio::test]
+    async fn answered_idempotency_key_skipped() {
+        let keys = Keys::generate();
+        let settings = Settings {
+            dry_run: true,
+            ..Settings::default()
+        };
+        let event = keyed_job_request(&keys, "abc", "order-1");
+        let state = JobRequestState::from(&settings);
+        state
+            .store
+            .record(
+                parse_event(&event, &keys).unwrap().store_key(),
+                EventId::all_zeros(),
+            )
+            .await
+            .unwrap();
+
+        // Unanswered, the unmarked input would be rejected at dispatch.
+        let retry = keyed_job_request(&keys, "def", "order-1");
+        let handled = handle_event(
+            retry,
+            keys.clone(),
+            Client::builder().signer(keys.clone()).build(),
+            &settings,
+            &state,
+        )
+        .await;
+
+        assert!(handled.is_ok());
+    }
+
+    fn feedback_status(feedback: &Event) -> String {
+        feedback
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .find(|t| t[0] == "status")
+            .map(|t| t[1].clone())
+            .unwrap()
+    }
+
+    fn feedback_for<'a>(feedback: &'a [Event], request: &Event) -> &'a Event {
+        feedback
+            .iter()
+            .find(|f| f.tags.event_ids().next() == Some(&request.id))
+            .expect("request answered with feedback")
+    }
+
+    fn keyed_quote_request(keys: &Keys, listing: &EventId, key: &str, attempt: &str) -> Event {
+        job_request_event(
+            keys,
+            vec![
+                i_tag(&[&listing.to_hex(), "text", "", "quote"]),
+                Tag::custom(TagKind::custom("param"), ["idempotency-key", key]),
+                Tag::alt(attempt),
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn repeated_idempotency_key_computed_once() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        let settings = Settings::default();
+        let state = JobRequestState::from(&settings);
+        let handle = |event: &Event| {
+            handle_event(
+                event.clone(),
+                keys.clone(),
+                client.clone(),
+                &settings,
+                &state,
+            )
+        };
+        let listing = EventBuilder::new(Kind::ClassifiedListing, "")
+            .tags([
+                Tag::custom(TagKind::custom("price"), ["10", "USD", "1", "kg"]),
+                Tag::custom(TagKind::custom("quantity"), ["1", "kg", "bag"]),
+            ])
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        client.send_event(&listing).await.unwrap();
+
+        let requester = Keys::generate();
+        let first = keyed_quote_request(&requester, &listing.id, "quote-1", "first");
+        let concurrent = keyed_quote_request(&requester, &listing.id, "quote-1", "concurrent");
+        let (handled_first, handled_concurrent) = tokio::join!(handle(&first), handle(&concurrent));
+        handled_first.unwrap();
+        handled_concurrent.unwrap();
+        let retry = keyed_quote_request(&requester, &listing.id, "quote-1", "retry");
+        handle(&retry).await.unwrap();
+
+        let fetch =
+            |kind: Kind| client.fetch_events(Filter::new().kind(kind), Duration::from_secs(2));
+        let results: Vec<Event> = fetch(Kind::Custom(KIND_JOB_RESULT))
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].tags.event_ids().any(|id| id == &first.id));
+
+        let feedback: Vec<Event> = fetch(Kind::JobFeedback)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(feedback.len(), 2);
+        assert_eq!(
+            feedback_status(feedback_for(&feedback, &concurrent)),
+            "processing"
+        );
+        let answer = feedback_for(&feedback, &retry);
+        assert_eq!(feedback_status(answer), "success");
+        assert!(
+            answer
+                .tags
+                .iter()
+                .any(|t| t.as_slice().get(1) == Some(&results[0].id.to_hex()))
+        );
+    }
+
+    #[tokio::test]
+    async fn result_sent_to_relay_hinted_for_us() {
+        let shared = MockRelay::run().await.unwrap();
+        let hint = MockRelay::run().await.unwrap();
+        let other_hint = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(shared.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        let settings = Settings::default();
+        let state = JobRequestState::from(&settings);
+        let listing = EventBuilder::new(Kind::ClassifiedListing, "")
+            .tags([
+                Tag::custom(TagKind::custom("price"), ["10", "USD", "1", "kg"]),
+                Tag::custom(TagKind::custom("quantity"), ["1", "kg", "bag"]),
+            ])
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        client.send_event(&listing).await.unwrap();
+        let other = Keys::generate().public_key().to_hex();
+        let request = job_request_event(
+            &Keys::generate(),
+            vec![
+                i_tag(&[&listing.id.to_hex(), "text", "", "quote"]),
+                Tag::custom(
+                    TagKind::p(),
+                    [keys.public_key().to_hex(), hint.url().to_string()],
+                ),
+                Tag::custom(TagKind::p(), [other, other_hint.url().to_string()]),
+            ],
+        );
+
+        handle_event(request.clone(), keys, client.clone(), &settings, &state)
+            .await
+            .unwrap();
+
+        let result = Filter::new()
+            .kind(Kind::Custom(KIND_JOB_RESULT))
+            .event(request.id);
+        let fetch = |relay: &MockRelay| {
+            let (url, filter) = (relay.url(), result.clone());
+            async move {
+                let reader = Client::default();
+                reader.add_relay(url).await.unwrap();
+                reader.connect().await;
+                reader.wait_for_connection(Duration::from_secs(5)).await;
+                reader
+                    .fetch_events(filter, Duration::from_millis(500))
+                    .await
+                    .unwrap()
+                    .len()
+            }
+        };
+        // Relay hints are sent to in the background.
+        let mut hinted = 0;
+        for _ in 0..20 {
+            hinted = fetch(&hint).await;
+            if hinted > 0 {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(hinted, 1);
+        assert_eq!(fetch(&other_hint).await, 0);
+    }
+
+    #[tokio::test]
+    async fn each_configured_kind_dispatched() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        let settings = Settings {
+            job_kinds: vec![KIND_JOB_REQUEST, KIND_JOB_REQUEST + 1],
+            ..Default::default()
+        };
+        let state = JobRequestState::from(&settings);
+        let listing = EventBuilder::new(Kind::ClassifiedListing, "")
+            .tags([
+                Tag::custom(TagKind::custom("price"), ["10", "USD", "1", "kg"]),
+                Tag::custom(TagKind::custom("quantity"), ["1", "kg", "bag"]),
+            ])
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        client.send_event(&listing).await.unwrap();
+        let request = |kind: u16| {
+            EventBuilder::new(Kind::Custom(kind), "")
+                .tag(i_tag(&[&listing.id.to_hex(), "text", "", "quote"]))
+                .sign_with_keys(&Keys::generate())
+                .unwrap()
+        };
+
+        for kind in [KIND_JOB_REQUEST, KIND_JOB_REQUEST + 1] {
+            let request = request(kind);
+            handle_event(
+                request.clone(),
+                keys.clone(),
+                client.clone(),
+                &settings,
+                &state,
+            )
+            .await
+            .unwrap();
+
+            let results = client
+                .fetch_events(
+                    Filter::new()
+                        .kind(Kind::Custom(kind + 1000))
+                        .event(request.id),
+                    Duration::from_secs(2),
+                )
+                .await
+                .unwrap();
+            assert_eq!(results.len(), 1, "kind {kind} answered");
+        }
+
+        let unsupported = request(KIND_JOB_REQUEST + 2);
+        assert!(matches!(
+            handle_event(unsupported, keys, client, &settings, &state).await,
+            Err(JobRequestError::UnsupportedKind(kind)) if kind == KIND_JOB_REQUEST + 2
+        ));
+    }
+
+    fn unmarked_job_request(keys: &Keys, expires_at: Timestamp) -> Event {
+        let mut tags = vec![i_tag(&["abc", "event", ""])];
+        tags.push(Tag::expiration(expires_at));
+        job_request_event(keys, tags)
+    }
+
+    #[tokio::test]
+    async fn expired_job_request_not_dispatched() {
+        let keys = Keys::generate();
+        let settings = Settings::default();
+        let now = Timestamp::now().as_u64();
+
+        let expired = unmarked_job_request(&keys, Timestamp::from(now - 60));
+        let job_req = parse_event(&expired, &keys).unwrap();
+        assert!(job_req.is_expired(Timestamp::now()));
+        assert!(handle_event_with(&expired, &keys, &settings).await.is_ok());
+
+        // The same request unexpired reaches dispatch, where the missing marker is rejected.
+        let live = unmarked_job_request(&keys, Timestamp::from(now + 3_600));
+        assert!(matches!(
+            handle_event_with(&live, &keys, &settings).await,
+            Err(JobRequestError::MissingInputMarker(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn job_request_below_min_bid_not_dispatched() {
+        let keys = Keys::generate();
+        let settings = Settings {
+            min_bid_msat: Some(1_000),
+            dry_run: true,
+            ..Settings::default()
+        };
+        let expires_at = Timestamp::from(Timestamp::now().as_u64() + 3_600);
+        let with_bid = |bid: &str| {
+            let mut tags = unmarked_job_request(&keys, expires_at).tags.to_vec();
+            tags.push(Tag::custom(TagKind::custom("bid"), [bid]));
+            job_request_event(&keys, tags)
+        };
+
+        let missing = unmarked_job_request(&keys, expires_at);
+        assert!(handle_event_with(&missing, &keys, &settings).await.is_ok());
+        assert!(
+            handle_event_with(&with_bid("999"), &keys, &settings)
+                .await
+                .is_ok()
+        );
+
+        // A bid at the minimum reaches dispatch, where the missing marker is rejected.
+        assert!(matches!(
+            handle_event_with(&with_bid("1000"), &keys, &settings).await,
+            Err(JobRequestError::MissingInputMarker(_))
+        ));
+    }
+
+    async fn resolve_job_input(cached: &Event) -> Result<Event, JobRequestError> {
+        let settings = Settings::default();
+        let event_cache = EventCache::from(&settings.event_cache);
+        event_cache.insert(cached.id, cached.clone());
+        let input = JobRequestInput {
+            data: cached.id.to_hex(),
+            input_type: JobRequestInputType::Job,
+            relay: None,
+            marker: None,
+        };
+        job_request_input_job_result(&Client::default(), &event_cache, &settings, &input).await
+    }
+
+    #[tokio::test]
+    async fn job_input_resolves_job_result() {
+        let keys = Keys::generate();
+        // Results from other DVMs carry their own request kind + 1000.
+        for kind in [KIND_JOB_RESULT, 6050] {
+            let result = EventBuilder::new(Kind::Custom(kind), "{}")
+                .sign_with_keys(&keys)
+                .unwrap();
+
+            let resolved = resolve_job_input(&result).await.unwrap();
+
+            assert_eq!(resolved.id, result.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn job_input_rejects_other_kinds() {
+        let note = EventBuilder::text_note("rhi")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        assert!(matches!(
+            resolve_job_input(&note).await,
+            Err(JobRequestError::InvalidJobInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn job_input_rejects_unverified_result() {
+        let mut result = EventBuilder::new(Kind::Custom(KIND_JOB_RESULT), "{}")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        result.content = r#"{"forged":true}"#.into();
+
+        let err = resolve_job_input(&result).await.unwrap_err();
+
+        let JobRequestError::InvalidJobInput(detail) = err else {
+            panic!("expected an invalid job input, got {err:?}");
+        };
+        assert!(detail.contains("failed signature verification"), "{detail}");
+    }
+
+    #[test]
+    fn result_relays_merge_request_and_provider_hints() {
+        let keys = Keys::generate();
+        let provider = Keys::generate();
+        let ours = provider.public_key().to_hex();
+        let other = Keys::generate().public_key().to_hex();
+        let event = job_request_event(
+            &keys,
+            vec![
+                Tag::custom(TagKind::Relays, ["wss://a.test", "wss://b.test"]),
+                Tag::custom(TagKind::p(), [ours.as_str(), "wss://b.test"]),
+                Tag::custom(TagKind::p(), [ours.as_str(), "wss://c.test"]),
+                Tag::custom(TagKind::p(), [ours.as_str(), ""]),
+                Tag::custom(TagKind::p(), [other.as_str(), "wss://d.test"]),
+            ],
+        );
+
+        let job_req = parse_event(&event, &provider).unwrap();
+
+        assert_eq!(job_req.provider_relays, ["wss://b.test", "wss://c.test"]);
+        assert_eq!(
+            job_req.result_relays(),
+            ["wss://a.test", "wss://b.test", "wss://c.test"]
+        );
+    }
+
+    #[test]
+    fn job_request_addressed_to_us() {
+        let keys = Keys::generate();
+        let provider = Keys::generate();
+        let event = job_request_event(&keys, vec![Tag::public_key(provider.public_key())]);
+
+        assert_eq!(
+            job_request_addressed_to(&event, &provider.public_key()),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn job_request_addressed_to_other() {
+        let keys = Keys::generate();
+        let provider = Keys::generate();
+        let other = Keys::generate();
+        let event = job_request_event(&keys, vec![Tag::public_key(other.public_key())]);
+
+        assert_eq!(
+            job_request_addressed_to(&event, &provider.public_key()),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn job_request_unaddressed() {
+        let keys = Keys::generate();
+        let provider = Keys::generate();
+        let event = job_request_event(&keys, vec![i_tag(&["abc", "event", "", "order"])]);
+
+        assert_eq!(
+            job_request_addressed_to(&event, &provider.public_key()),
+            None
+        );
+    }
+
+    #[test]
+    fn encrypted_job_request_for_other_checked_before_decryption() {
+        let keys = Keys::generate();
+        let provider = Keys::generate();
+        let other = Keys::generate();
+        let event = EventBuilder::new(Kind::Custom(KIND_JOB_REQUEST), "not decryptable")
+            .tags([
+                Tag::public_key(other.public_key()),
+                Tag::from_standardized(TagStandard::Encrypted),
+            ])
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        assert_eq!(
+            job_request_addressed_to(&event, &provider.public_key()),
+            Some(false)
+        );
+        assert!(parse_event(&event, &provider).is_err());
+    }
+
+    #[tokio::test]
+    async fn canceled_job_request_stops_and_reports() {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![i_tag(&["abc", "event", "", "order"])]);
+        let tasks = JobRequestTasks::default();
+        let cancel = tasks.start(&event);
+        let finished = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let slow_job = {
+            let finished = finished.clone();
+            async move {
+                sleep(Duration::from_secs(60)).await;
+                finished.store(true, std::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            }
+        };
+        let run = tokio::spawn(job_request_run(slow_job, cancel));
+
+        let deletion = EventBuilder::new(Kind::EventDeletion, "")
+            .tag(Tag::event(event.id))
+            .sign_with_keys(&keys)
+            .unwrap();
+        assert_eq!(tasks.cancel(&deletion), vec![event.id]);
+
+        let result = tokio::time::timeout(Duration::from_secs(1), run)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(result, Err(JobRequestError::Canceled)));
+        assert!(!finished.load(std::sync::atomic::Ordering::SeqCst));
+
+        let feedback = nostr_event_job_feedback(
+            &event,
+            DataVendingMachineStatus::Error,
+            Some(JobRequestError::Canceled),
+            None,
+            None,
+        )
+        .unwrap()
+        .sign_with_keys(&keys)
+        .unwrap();
+        assert!(
+            feedback
+                .tags
+                .iter()
+                .any(|t| t.as_slice().iter().any(|v| v.contains("\"CANCELED\"")))
+        );
+    }
+
+    #[tokio::test]
+    async fn deletion_cancels_job_request_in_subscriber() {
+        let relay = MockRelay::run().await.unwrap();
+        let settings = Settings {
+            respond_to_unaddressed: true,
+            ..Settings::default()
+        };
+        let relays = vec![RelaySettings {
+            url: relay.url(),
+            role: RelayRole::Both,
+            auth: false,
+        }];
+        let seen = TtlCache::from(&settings.dedup);
+        let shutdown = CancellationToken::new();
+        let state = JobRequestState::from(&settings);
+        let subscriber = tokio::spawn(subscriber(
+            Keys::generate(),
+            relays,
+            settings,
+            HealthState::default(),
+            shutdown.clone(),
+            state,
+            seen,
+        ));
+
+        let requester = Client::builder().signer(Keys::generate()).build();
+        requester.add_relay(relay.url()).await.unwrap();
+        requester.connect().await;
+        requester.wait_for_connection(Duration::from_secs(5)).await;
+        requester
+            .subscribe(Filter::new().kind(Kind::JobFeedback), None)
+            .await
+            .unwrap();
+        let mut notifications = requester.notifications();
+
+        // Debug builds hold each request briefly before handling it, so the deletion lands
+        // while the order is still in flight.
+        let missing = EventId::all_zeros().to_hex();
+        let request = requester
+            .send_event_builder(
+                EventBuilder::new(Kind::Custom(KIND_JOB_REQUEST), "")
+                    .tag(i_tag(&[&missing, "event", "", "order"])),
+            )
+            .await
+            .unwrap()
+            .val;
+        let deletion = EventBuilder::new(Kind::EventDeletion, "").tags([
+            Tag::event(request),
+            Tag::custom(
+                TagKind::SingleLetter(SingleLetterTag::lowercase(Alphabet::K)),
+                [KIND_JOB_REQUEST.to_string()],
+            ),
+        ]);
+        requester.send_event_builder(deletion).await.unwrap();
+
+        let feedback = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(RelayPoolNotification::Event { event, .. }) = notifications.recv().await {
+                    break event;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        shutdown.cancel();
+        subscriber.await.unwrap().unwrap();
+
+        assert_eq!(feedback.tags.event_ids().next(), Some(&request));
+        assert!(
+            feedback
+                .tags
+                .iter()
+                .any(|t| t.as_slice().iter().any(|v| v.contains("\"CANCELED\"")))
+        );
+    }
+
+    fn output_tag(mimetype: &str) -> Tag {
+        Tag::custom(TagKind::custom("output"), [mimetype])
+    }
+
+    fn quote_result() -> QuoteResult {
+        QuoteResult {
+            id: EventId::all_zeros(),
+            prices: vec![QuoteResultPrice::from(EventClassifiedPrice {
+                amount: 12.5,
+                currency: "USD".into(),
+                quantity_amount: 1.0,
+                quantity_unit: Unit::Count,
+            })],
+            quantities: vec![],
+            discounts: vec![],
+            accepted_currencies: vec!["USD".into()],
+            tax_rate_percent: None,
+            shipping: None,
+        }
+    }
+
+    #[test]
+    fn render_result_in_requested_format() {
+        let result = quote_result();
+
+        let (payload, mimetype) = render_result(&result, OutputFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(value["prices"][0]["amount"], 12.5);
+        assert_eq!(mimetype, "application/json");
+
+        let (payload, mimetype) = render_result(&result, OutputFormat::Csv).unwrap();
+        assert!(payload.ends_with("\n12.5,USD,1,each,\n"), "{payload}");
+        assert_eq!(mimetype, "text/csv");
+    }
+
+    #[test]
+    fn render_result_without_csv_rejected() {
+        let result = OrderResultEnvelope {
+            result: OrderResult {
+                lines: vec![],
+                total: OrderResultTotal {
+                    price_amount: 12.5,
+                    price_currency: "USD".into(),
+                },
+            },
+            receipt: OrderResultReceipt::new(None, None),
+        };
+
+        assert!(render_result(&result, OutputFormat::Json).is_ok());
+        assert!(matches!(
+            render_result(&result, OutputFormat::Csv),
+            Err(JobRequestError::UnsupportedOutput(_))
+        ));
+        assert!(render_supported::<QuoteResult>(OutputFormat::Csv).is_ok());
+        assert!(matches!(
+            render_supported::<OrderResultEnvelope<OrderResult>>(OutputFormat::Csv),
+            Err(JobRequestError::UnsupportedOutput(_))
+        ));
+    }
+
+    #[test]
+    fn job_request_saturated_by_policy() {
+        let permits = Semaphore::new(1);
+
+        assert!(!job_request_saturated(&permits, 0, OverloadPolicy::Drop));
+        assert!(job_request_saturated(&permits, 1, OverloadPolicy::Drop));
+        let held = permits.try_acquire().unwrap();
+        assert!(job_request_saturated(&permits, 0, OverloadPolicy::Drop));
+
+        assert!(!job_request_saturated(&permits, 0, OverloadPolicy::Queue));
+        assert!(!job_request_saturated(
+            &permits,
+            JOB_REQUEST_QUEUE_MAX - 1,
+            OverloadPolicy::Queue
+        ));
+        assert!(job_request_saturated(
+            &permits,
+            JOB_REQUEST_QUEUE_MAX,
+            OverloadPolicy::Queue
+        ));
+        drop(held);
+    }
+
+    #[tokio::test]
+    async fn queued_request_canceled_while_handlers_busy() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        client
+            .subscribe(
+                Filter::new().kinds([Kind::Custom(KIND_JOB_REQUEST), Kind::EventDeletion]),
+                None,
+            )
+            .await
+            .unwrap();
+        let settings = Settings {
+            max_concurrent_jobs: 1,
+            shutdown_grace_secs: 0,
+            ..Settings::default()
+        };
+        let shutdown = CancellationToken::new();
+        let started = Arc::new(AtomicUsize::new(0));
+
+        let subscriber = {
+            let (keys, client, shutdown) = (keys.clone(), client.clone(), shutdown.clone());
+            let (settings, started) = (settings.clone(), started.clone());
+            tokio::spawn(async move {
+                job_request_loop(
+                    &keys,
+                    &client,
+                    &settings,
+                    &shutdown,
+                    &JobRequestState::from(&settings),
+                    &TtlCache::new(100, Duration::from_secs(60)),
+                    |_, _, _, _, _| {
+                        started.fetch_add(1, Ordering::SeqCst);
+                        async move {
+                            sleep(Duration::from_secs(60)).await;
+                            Ok(())
+                        }
+                    },
+                )
+                .await
+            })
+        };
+
+        let requester = Keys::generate();
+        let busy = job_request_event(&requester, vec![i_tag(&["a", "text", ""])]);
+        let waiting = job_request_event(&requester, vec![i_tag(&["b", "text", ""])]);
+        client.send_event(&busy).await.unwrap();
+        client.send_event(&waiting).await.unwrap();
+        let deletion = EventBuilder::new(Kind::EventDeletion, "")
+            .tag(Tag::event(waiting.id))
+            .sign_with_keys(&requester)
+            .unwrap();
+        client.send_event(&deletion).await.unwrap();
+
+        let canceled = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let feedback = client
+                    .fetch_events(
+                        Filter::new().kind(Kind::JobFeedback).event(waiting.id),
+                        Duration::from_millis(500),
+                    )
+                    .await
+                    .unwrap();
+                if let Some(feedback) = feedback.first() {
+                    break feedback.clone();
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown.cancel();
+        subscriber.await.unwrap().unwrap();
+
+        assert!(
+            canceled
+                .tags
+                .iter()
+                .any(|t| t.as_slice().iter().any(|v| v.contains("\"CANCELED\"")))
+        );
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn subscriber_limits_concurrent_handlers() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        client
+            .subscribe(nostr_filter_kinds(&[KIND_JOB_REQUEST]), None)
+            .await
+            .unwrap();
+        let settings = Settings {
+            max_concurrent_jobs: 2,
+            ..Settings::default()
+        };
+        let shutdown = CancellationToken::new();
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let handled = Arc::new(AtomicUsize::new(0));
+
+        let subscriber = {
+            let (keys, client, shutdown) = (keys.clone(), client.clone(), shutdown.clone());
+            let (running, peak, handled) = (running.clone(), peak.clone(), handled.clone());
+            tokio::spawn(async move {
+                job_request_loop(
+                    &keys,
+                    &client,
+                    &settings,
+                    &shutdown,
+                    &JobRequestState::from(&settings),
+                    &TtlCache::new(100, Duration::from_secs(60)),
+                    |_, _, _, _, _| {
+                        let (running, handled) = (running.clone(), handled.clone());
+                        // Counted when the handler is built, so queued requests count too.
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        async move {
+                            sleep(Duration::from_millis(50)).await;
+                            running.fetch_sub(1, Ordering::SeqCst);
+                            handled.fetch_add(1, Ordering::SeqCst);
+                            Ok(())
+                        }
+                    },
+                )
+                .await
+            })
+        };
+
+        let requester = Keys::generate();
+        for input in ["a", "b", "c", "d", "e", "f"] {
+            let event = job_request_event(&requester, vec![i_tag(&[input, "text", ""])]);
+            client.send_event(&event).await.unwrap();
+        }
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while handled.load(Ordering::SeqCst) < 6 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown.cancel();
+        subscriber.await.unwrap().unwrap();
+
+        assert!((1..=2).contains(&peak.load(Ordering::SeqCst)));
+    }
+
+    #[test]
+    fn job_request_span_records_request_and_requester() {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![]);
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            job_request_span(&event).in_scope(|| info!("handling"));
+        });
+
+        let logs = logs.contents();
+        assert!(logs.contains(&format!("id={}", event.id)));
+        assert!(logs.contains(&format!("requester={}", event.pubkey)));
+    }
+
+    #[test]
+    fn supported_output_parsed() {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![output_tag("Application/JSON")]);
+
+        let job_req = parse_event(&event, &keys).unwrap();
+
+        assert_eq!(job_req.output, Some(OutputFormat::Json));
+    }
+
+    #[test]
+    fn unsupported_output_rejected() {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![output_tag("application/xml")]);
+
+        let err = parse_event(&event, &keys).unwrap_err();
+
+        assert!(matches!(&err, JobRequestError::UnsupportedOutput(m) if m == "application/xml"));
+        let feedback = JobRequestFeedbackError::from(&err);
+        assert_eq!(feedback.code, "UNSUPPORTED_OUTPUT");
+        assert_eq!(feedback.field, Some("output"));
+        assert!(feedback.message.contains("application/xml"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_waits_for_in_flight_handler() {
+        let mut handlers = JoinSet::new();
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = done.clone();
+        handlers.spawn(async move {
+            sleep(Duration::from_secs(2)).await;
+            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+        });
+
+        drain_handlers(&mut handlers, Duration::from_secs(5)).await;
+
+        assert!(done.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(handlers.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_cancels_handlers_past_grace() {
+        let mut handlers = JoinSet::new();
+        handlers.spawn(async {
+            sleep(Duration::from_secs(2)).await;
+        });
+        handlers.spawn(async {
+            sleep(Duration::from_secs(60)).await;
+        });
+        let started = tokio::time::Instant::now();
+
+        drain_handlers(&mut handlers, Duration::from_secs(5)).await;
+
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+        while let Some(joined) = handlers.join_next().await {
+            assert!(joined.unwrap_err().is_cancelled());
+        }
+    }
+
+    async fn process_with<F, Fut>(
+        handler: F,
+        client: &Client,
+        store: &JobRequestStore,
+    ) -> JobRequest
+    where
+        F: FnOnce(
+            Event,
+            Keys,
+            Client,
+            JobRequestState,
+            Settings,
+            JobRequest,
+            JobRequestInput,
+        ) -> Fut,
+        Fut: std::future::Future<Output = Result<Option<JobResultPending>, JobRequestError>>,
     {
-        let _ = handle_error(
-            err,
-            error_event,
-            error_keys,
-            error_client,
-            Some(error_job_req),
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![i_tag(&["{}", "text", "", "quote"])]);
+        let job_req = parse_event(&event, &keys).unwrap();
+        let job_req_input = job_req.inputs[0].clone();
+        let settings = Settings {
+            job_timeout_ms: 200,
+            ..Settings::default()
+        };
+        let state = JobRequestState {
+            store: store.clone(),
+            ..JobRequestState::from(&settings)
+        };
+
+        process_job_request(
+            handler,
+            event,
+            keys,
+            client.clone(),
+            state,
+            settings,
+            job_req.clone(),
+            job_req_input,
         )
         .await;
+        job_req
+    }
+
+    #[tokio::test]
+    async fn process_job_request_abandons_slow_handler() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = Client::builder().signer(Keys::generate()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        let (held, abandoned) = tokio::sync::oneshot::channel::<()>();
+        let started = Instant::now();
+
+        process_with(
+            move |_, _, _, _, _, _, _| async move {
+                let _held = held;
+                std::future::pending::<Result<Option<JobResultPending>, JobRequestError>>().await
+            },
+            &client,
+            &JobRequestStore::default(),
+        )
+        .await;
+
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        // Dropping the handler future closes its end of the channel.
+        assert!(abandoned.await.is_err());
+
+        let feedback = client
+            .fetch_events(
+                Filter::new().kind(Kind::JobFeedback),
+                Duration::from_secs(2),
+            )
+            .await
+            .unwrap();
+        assert_eq!(feedback.len(), 1);
+        let status = feedback
+            .first()
+            .unwrap()
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .find(|t| t[0] == "status")
+            .unwrap();
+        assert_eq!(status[1], "error");
+        assert!(status[2].contains("TIMEOUT"));
+    }
+
+    #[tokio::test]
+    async fn job_result_send_detaches_follow_up() {
+        let keys = Keys::generate();
+        let job_req = parse_event(&job_request_event(&keys, vec![]), &keys).unwrap();
+        let (sent_tx, sent_rx) = tokio::sync::oneshot::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+        let on_sent: JobResultSent = {
+            let finished = finished.clone();
+            Box::new(move |result_id| {
+                Box::pin(async move {
+                    sleep(Duration::from_millis(200)).await;
+                    finished.store(true, Ordering::SeqCst);
+                    let _ = sent_tx.send(result_id);
+                })
+            })
+        };
+
+        let result_id = job_result_send(
+            Client::builder().signer(keys).build(),
+            JobResultPending {
+                event: EventBuilder::text_note("result"),
+                on_sent: Some(on_sent),
+            },
+            &job_req,
+            &AuditLog::default(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert!(!finished.load(Ordering::SeqCst));
+        assert_eq!(sent_rx.await.unwrap(), result_id);
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn process_job_request_sends_result_outside_timeout() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = Client::builder().signer(Keys::generate()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        let store = JobRequestStore::default();
+
+        let job_req = process_with(
+            |_, _, _, _, _, _, _| async move {
+                let on_sent: JobResultSent =
+                    Box::new(|_| Box::pin(sleep(Duration::from_millis(400))));
+                Ok(Some(JobResultPending {
+                    event: EventBuilder::text_note("result"),
+                    on_sent: Some(on_sent),
+                }))
+            },
+            &client,
+            &store,
+        )
+        .await;
+
+        assert!(store.get(&job_req.store_key()).is_some());
+        let feedback = client
+            .fetch_events(
+                Filter::new().kind(Kind::JobFeedback),
+                Duration::from_secs(2),
+            )
+            .await
+            .unwrap();
+        assert!(feedback.is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metric_value(name: &str, marker: &str) -> f64 {
+        let prefix = format!("{name}{{marker=\"{marker}\"}} ");
+        metrics::render()
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix(&prefix))
+            .map_or(0.0, |value| value.parse().unwrap())
+    }
+
+    #[cfg(feature = "metrics")]
+    async fn process_stub(result: Result<Option<JobResultPending>, JobRequestError>) {
+        let keys = Keys::generate();
+        let event = job_request_event(&keys, vec![i_tag(&["{}", "text", "", "quote"])]);
+        let job_req = parse_event(&event, &keys).unwrap();
+        let job_req_input = job_req.inputs[0].clone();
+        let settings = Settings {
+            dry_run: true,
+            ..Settings::default()
+        };
+
+        process_job_request(
+            move |_, _, _, _, _, _, _| async move { result },
+            event,
+            keys.clone(),
+            Client::builder().signer(keys).build(),
+            JobRequestState::from(&settings),
+            settings,
+            job_req,
+            job_req_input,
+        )
+        .await;
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn metrics_count_processed_requests() {
+        let received = metric_value("rhi_job_requests_received_total", "quote");
+        let succeeded = metric_value("rhi_job_requests_succeeded_total", "quote");
+        let failed = metric_value("rhi_job_requests_failed_total", "quote");
+
+        process_stub(Ok(Some(JobResultPending::new(EventBuilder::text_note(
+            "rhi",
+        )))))
+        .await;
+        process_stub(Err(JobRequestError::MissingInputMarker("id".into()))).await;
+
+        assert!(metric_value("rhi_job_requests_received_total", "quote") >= received + 2.0);
+        assert!(metric_value("rhi_job_requests_succeeded_total", "quote") >= succeeded + 1.0);
+        assert!(metric_value("rhi_job_requests_failed_total", "quote") >= failed + 1.0);
+        assert!(
+            metrics::render()
+                .unwrap()
+                .contains("rhi_job_request_duration_seconds_count{marker=\"quote\"}")
+        );
     }
 }
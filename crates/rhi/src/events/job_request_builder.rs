@@ -0,0 +1,190 @@
+use nostr::{
+    event::{EventBuilder, Kind, Tag, TagKind, TagStandard},
+    filter::{Alphabet, SingleLetterTag},
+    key::PublicKey,
+    types::{RelayUrl, Timestamp},
+};
+use radroots_common::KIND_JOB_REQUEST;
+
+use crate::events::job_request::{
+    JobRequestInput, JobRequestInputMarker, JobRequestInputType, OutputFormat,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct JobRequestBuilder {
+    inputs: Vec<JobRequestInput>,
+    output: Option<OutputFormat>,
+    bid_msat: Option<u64>,
+    relays: Vec<RelayUrl>,
+    service_providers: Vec<PublicKey>,
+    params: Vec<(String, String)>,
+    hashtags: Vec<String>,
+    expires_at: Option<Timestamp>,
+}
+
+impl JobRequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input(
+        mut self,
+        data: impl Into<String>,
+        input_type: JobRequestInputType,
+        relay: Option<String>,
+        marker: Option<JobRequestInputMarker>,
+    ) -> Self {
+        self.inputs.push(JobRequestInput {
+            data: data.into(),
+            input_type,
+            relay,
+            marker,
+        });
+        self
+    }
+
+    pub fn output(mut self, output: OutputFormat) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn bid_msat(mut self, bid_msat: u64) -> Self {
+        self.bid_msat = Some(bid_msat);
+        self
+    }
+
+    pub fn relays(mut self, relays: impl IntoIterator<Item = RelayUrl>) -> Self {
+        self.relays.extend(relays);
+        self
+    }
+
+    pub fn service_provider(mut self, public_key: PublicKey) -> Self {
+        self.service_providers.push(public_key);
+        self
+    }
+
+    pub fn param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn hashtag(mut self, hashtag: impl Into<String>) -> Self {
+        self.hashtags.push(hashtag.into());
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Timestamp) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn build(self) -> EventBuilder {
+        let mut tags = Vec::new();
+
+        for input in self.inputs {
+            let mut values = vec![
+                input.data,
+                input.input_type.as_str().to_string(),
+                input.relay.unwrap_or_default(),
+            ];
+            if let Some(marker) = input.marker {
+                values.push(marker.as_str().to_string());
+            }
+            tags.push(Tag::custom(
+                TagKind::SingleLetter(SingleLetterTag::lowercase(Alphabet::I)),
+                values,
+            ));
+        }
+
+        if let Some(output) = self.output {
+            tags.push(Tag::custom(TagKind::custom("output"), [output.as_str()]));
+        }
+
+        if let Some(bid_msat) = self.bid_msat {
+            tags.push(Tag::custom(TagKind::custom("bid"), [bid_msat.to_string()]));
+        }
+
+        if !self.relays.is_empty() {
+            tags.push(Tag::from_standardized(TagStandard::Relays(self.relays)));
+        }
+
+        for public_key in self.service_providers {
+            tags.push(Tag::public_key(public_key));
+        }
+
+        for (key, value) in self.params {
+            tags.push(Tag::custom(TagKind::custom("param"), [key, value]));
+        }
+
+        for hashtag in self.hashtags {
+            tags.push(Tag::hashtag(hashtag));
+        }
+
+        if let Some(expires_at) = self.expires_at {
+            tags.push(Tag::expiration(expires_at));
+        }
+
+        EventBuilder::new(Kind::Custom(KIND_JOB_REQUEST), "").tags(tags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::key::Keys;
+
+    use super::*;
+    use crate::events::job_request::parse_event;
+
+    #[test]
+    fn build_round_trips_through_parse_event() {
+        let requester = Keys::generate();
+        let provider = Keys::generate().public_key();
+        let relay = RelayUrl::parse("wss://relay.test").unwrap();
+        let expires_at = Timestamp::from(2_000_000_000);
+
+        let event = JobRequestBuilder::new()
+            .input(
+                "order-1",
+                JobRequestInputType::Text,
+                None,
+                Some(JobRequestInputMarker::Order),
+            )
+            .input(
+                "https://orders.test/2",
+                JobRequestInputType::Url,
+                Some("wss://hint.test".into()),
+                Some(JobRequestInputMarker::Quote),
+            )
+            .output(OutputFormat::Json)
+            .bid_msat(21_000)
+            .relays([relay.clone()])
+            .service_provider(provider)
+            .param("currency", "USD")
+            .hashtag("coffee")
+            .expires_at(expires_at)
+            .build()
+            .sign_with_keys(&requester)
+            .unwrap();
+
+        let job_req = parse_event(&event, &requester).unwrap();
+
+        assert_eq!(job_req.inputs.len(), 2);
+        assert_eq!(job_req.inputs[0].data, "order-1");
+        assert_eq!(job_req.inputs[0].input_type, JobRequestInputType::Text);
+        assert_eq!(job_req.inputs[0].marker, Some(JobRequestInputMarker::Order));
+        assert_eq!(job_req.inputs[1].input_type, JobRequestInputType::Url);
+        assert_eq!(job_req.inputs[1].relay.as_deref(), Some("wss://hint.test"));
+        assert_eq!(job_req.inputs[1].marker, Some(JobRequestInputMarker::Quote));
+        assert_eq!(job_req.output, Some(OutputFormat::Json));
+        assert_eq!(job_req.bid_msat, Some(21_000));
+        assert_eq!(job_req.relays, vec![relay.to_string()]);
+        assert_eq!(job_req.service_providers, vec![provider.to_hex()]);
+        assert_eq!(
+            job_req.params,
+            vec![("currency".to_string(), "USD".to_string())]
+        );
+        assert_eq!(job_req.hashtags, vec!["coffee".to_string()]);
+        assert_eq!(job_req.expires_at, Some(expires_at));
+        assert_eq!(job_req.requester, requester.public_key());
+    }
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, str::FromStr};
+
+use thiserror::Error;
+
+const KNOWN_KEYS: [&str; 6] = [
+    "currency",
+    "max_price",
+    "delivery",
+    "nonce",
+    "idempotency-key",
+    "as_of",
+];
+
+#[derive(Debug, Error)]
+#[error("{key} {value}")]
+pub struct JobRequestParamsError {
+    pub key: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobRequestDelivery {
+    Pickup,
+    Shipping,
+}
+
+impl FromStr for JobRequestDelivery {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pickup" => Ok(Self::Pickup),
+            "shipping" | "delivery" => Ok(Self::Shipping),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct JobRequestParams<'a> {
+    params: &'a [(String, String)],
+}
+
+impl<'a> JobRequestParams<'a> {
+    pub fn new(params: &'a [(String, String)]) -> Self {
+        Self { params }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn currency(&self) -> Result<Option<String>, JobRequestParamsError> {
+        let Some(currency) = self.get("currency").map(str::trim) else {
+            return Ok(None);
+        };
+        if !(currency.len() == 3 && currency.chars().all(|c| c.is_ascii_alphabetic())) {
+            return Err(Self::invalid("currency", currency));
+        }
+        Ok(Some(currency.to_string()))
+    }
+
+    pub fn max_price(&self) -> Result<Option<f64>, JobRequestParamsError> {
+        self.parse("max_price", |v| {
+            v.parse::<f64>().ok().filter(|p| p.is_finite() && *p >= 0.0)
+        })
+    }
+
+    pub fn delivery(&self) -> Result<Option<JobRequestDelivery>, JobRequestParamsError> {
+        self.parse("delivery", |v| v.parse().ok())
+    }
+
+    pub fn nonce(&self) -> Option<&'a str> {
+        self.get("nonce")
+    }
+
+    pub fn idempotency_key(&self) -> Option<&'a str> {
+        self.get("idempotency-key")
+    }
+
+    pub fn as_of(&self) -> Result<Option<u64>, JobRequestParamsError> {
+        self.parse("as_of", |v| v.parse().ok())
+    }
+
+    pub fn unknown(&self) -> HashMap<&'a str, &'a str> {
+        self.params
+            .iter()
+            .filter(|(k, _)| !KNOWN_KEYS.contains(&k.as_str()))
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect()
+    }
+
+    fn parse<T>(
+        &self,
+        key: &'static str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>, JobRequestParamsError> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        parse(value.trim())
+            .map(Some)
+            .ok_or_else(|| Self::invalid(key, value))
+    }
+
+    fn invalid(key: &'static str, value: &str) -> JobRequestParamsError {
+        JobRequestParamsError {
+            key,
+            value: value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn typed_accessors_parse_known_params() {
+        let pairs = params(&[
+            ("currency", " eur "),
+            ("max_price", "12.5"),
+            ("delivery", "Delivery"),
+            ("nonce", "n-1"),
+            ("idempotency-key", "k-1"),
+            ("as_of", "1700000000"),
+            ("note", "ring twice"),
+        ]);
+        let params = JobRequestParams::new(&pairs);
+
+        assert_eq!(params.currency().unwrap().as_deref(), Some("eur"));
+        assert_eq!(params.max_price().unwrap(), Some(12.5));
+        assert_eq!(
+            params.delivery().unwrap(),
+            Some(JobRequestDelivery::Shipping)
+        );
+        assert_eq!(params.nonce(), Some("n-1"));
+        assert_eq!(params.idempotency_key(), Some("k-1"));
+        assert_eq!(params.as_of().unwrap(), Some(1_700_000_000));
+        assert_eq!(params.unknown(), HashMap::from([("note", "ring twice")]));
+    }
+
+    #[test]
+    fn typed_accessors_absent_params() {
+        let params = JobRequestParams::new(&[]);
+
+        assert_eq!(params.currency().unwrap(), None);
+        assert_eq!(params.max_price().unwrap(), None);
+        assert_eq!(params.delivery().unwrap(), None);
+        assert_eq!(params.nonce(), None);
+        assert_eq!(params.idempotency_key(), None);
+        assert_eq!(params.as_of().unwrap(), None);
+        assert!(params.unknown().is_empty());
+    }
+
+    #[test]
+    fn typed_accessors_reject_invalid_values() {
+        let pairs = params(&[
+            ("currency", "euro"),
+            ("max_price", "-1"),
+            ("delivery", "drone"),
+            ("as_of", "yesterday"),
+        ]);
+        let params = JobRequestParams::new(&pairs);
+
+        let err = params.currency().unwrap_err();
+        assert_eq!((err.key, err.value.as_str()), ("currency", "euro"));
+        assert_eq!(params.max_price().unwrap_err().key, "max_price");
+        assert_eq!(params.delivery().unwrap_err().key, "delivery");
+        assert_eq!(params.as_of().unwrap_err().to_string(), "as_of yesterday");
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use crate::audit::AuditLog;
+use crate::config::Settings;
+use crate::events::job_request_store::JobRequestStore;
+use crate::events::listing_index::ListingIndex;
+use crate::handlers::job_request_quote::QuoteCache;
+use crate::utils::nostr::EventCache;
+use crate::utils::rate::{SharedFiatRateProvider, StaticFiatRateProvider};
+
+/// Runtime handles shared by every profile's job request handlers, built once at startup.
+#[derive(Clone)]
+pub struct JobRequestState {
+    pub store: JobRequestStore,
+    pub event_cache: EventCache,
+    pub quotes: QuoteCache,
+    pub listings: ListingIndex,
+    pub audit: AuditLog,
+    /// Prices orders in millisats when set.
+    pub rates: Option<SharedFiatRateProvider>,
+}
+
+impl From<&Settings> for JobRequestState {
+    /// Builds the caches and static `msat_rates` from `settings`, with an in-memory store and
+    /// no audit log.
+    fn from(settings: &Settings) -> Self {
+        Self {
+            store: JobRequestStore::default(),
+            event_cache: EventCache::from(&settings.event_cache),
+            quotes: QuoteCache::from(&settings.quote_cache),
+            listings: ListingIndex::from(settings.listing_index.as_ref()),
+            audit: AuditLog::default(),
+            rates: Some(StaticFiatRateProvider::new(&settings.msat_rates))
+                .filter(|rates| !rates.is_empty())
+                .map(|rates| Arc::new(rates) as SharedFiatRateProvider),
+        }
+    }
+}
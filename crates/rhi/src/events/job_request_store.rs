@@ -0,0 +1,468 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use nostr::{event::EventId, types::Timestamp};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::warn;
+
+use crate::config::CacheSettings;
+
+#[derive(Debug, Error)]
+pub enum JobRequestStoreError {
+    #[error("Failed to open job request store at {0}: {1}")]
+    FileOpen(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to serialize job request store: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("IO error during job request store write: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to persist job request store to disk: {0}")]
+    Persist(#[from] tempfile::PersistError),
+
+    #[error("Job request store write task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+/// What the store holds for a key that could not be reserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobRequestStoreEntry {
+    /// Another job request with the same key is being processed.
+    Pending,
+    Answered(EventId),
+}
+
+/// One line of the store log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JobRequestRecord {
+    key: String,
+    result: EventId,
+    at: Timestamp,
+}
+
+#[derive(Debug)]
+struct JobRequestEntries {
+    answered: HashMap<String, (EventId, Timestamp)>,
+    pending: HashSet<String>,
+    /// Lines in the log, compacted once it holds twice the capacity.
+    logged: usize,
+}
+
+/// Answered job requests keyed by `JobRequest::store_key`, bounded by age and count, and
+/// appended to a log when a path is set so they survive restarts.
+#[derive(Debug, Clone)]
+pub struct JobRequestStore {
+    entries: Arc<Mutex<JobRequestEntries>>,
+    capacity: usize,
+    ttl_secs: u64,
+    path: Option<PathBuf>,
+    write: Arc<AsyncMutex<()>>,
+}
+
+impl Default for JobRequestStore {
+    fn default() -> Self {
+        Self::new(&CacheSettings::default(), None)
+    }
+}
+
+impl JobRequestStore {
+    fn new(settings: &CacheSettings, path: Option<PathBuf>) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(JobRequestEntries {
+                answered: HashMap::new(),
+                pending: HashSet::new(),
+                logged: 0,
+            })),
+            capacity: settings.capacity.max(1),
+            ttl_secs: settings.ttl_secs,
+            path,
+            write: Arc::default(),
+        }
+    }
+
+    pub async fn open(
+        path: Option<&str>,
+        settings: &CacheSettings,
+    ) -> Result<Self, JobRequestStoreError> {
+        let Some(path) = path.map(PathBuf::from) else {
+            return Ok(Self::new(settings, None));
+        };
+
+        let store = Self::new(settings, Some(path.clone()));
+        let records = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || records_read(&path)).await??
+        };
+        let now = Timestamp::now();
+        if let Ok(mut entries) = store.entries.lock() {
+            entries.logged = records.len();
+            for record in records {
+                store.insert(&mut entries, record, now);
+            }
+        }
+        Ok(store)
+    }
+
+    pub fn get(&self, key: &str) -> Option<EventId> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .answered
+            .get(key)
+            .filter(|(_, at)| self.is_live(*at, Timestamp::now()))
+            .map(|(result_id, _)| *result_id)
+    }
+
+    /// Claims a key before its job request is dispatched, so a repeat arriving while it is
+    /// processed is not computed again. The claim is released when the reservation drops
+    /// without a recorded result.
+    pub fn reserve(&self, key: &str) -> Result<JobRequestReservation, JobRequestStoreEntry> {
+        let reservation = JobRequestReservation {
+            store: self.clone(),
+            key: key.to_string(),
+        };
+        let Ok(mut entries) = self.entries.lock() else {
+            return Ok(reservation);
+        };
+
+        if let Some(result_id) = entries
+            .answered
+            .get(key)
+            .filter(|(_, at)| self.is_live(*at, Timestamp::now()))
+            .map(|(result_id, _)| *result_id)
+        {
+            return Err(JobRequestStoreEntry::Answered(result_id));
+        }
+        if !entries.pending.insert(key.to_string()) {
+            return Err(JobRequestStoreEntry::Pending);
+        }
+        Ok(reservation)
+    }
+
+    pub async fn record(
+        &self,
+        key: String,
+        result_id: EventId,
+    ) -> Result<(), JobRequestStoreError> {
+        let record = JobRequestRecord {
+            key,
+            result: result_id,
+            at: Timestamp::now(),
+        };
+        let compact = match self.entries.lock() {
+            Ok(mut entries) => {
+                entries.pending.remove(&record.key);
+                self.insert(&mut entries, record.clone(), record.at);
+                entries.logged += 1;
+                entries.logged > self.capacity * 2
+            }
+            Err(_) => return Ok(()),
+        };
+
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+
+        let _write = self.write.lock().await;
+        if !compact {
+            let line = serde_json::to_string(&record)?;
+            return tokio::task::spawn_blocking(move || record_append(&path, &line)).await?;
+        }
+
+        // Rewritten under the write lock, so the log always ends up holding every live record.
+        let (json, logged) = match self.entries.lock() {
+            Ok(entries) => {
+                let mut json = String::new();
+                for (key, (result, at)) in &entries.answered {
+                    let record = JobRequestRecord {
+                        key: key.clone(),
+                        result: *result,
+                        at: *at,
+                    };
+                    json.push_str(&serde_json::to_string(&record)?);
+                    json.push('\n');
+                }
+                (json, entries.answered.len())
+            }
+            Err(_) => return Ok(()),
+        };
+        tokio::task::spawn_blocking(move || atomic_write(&path, &json)).await??;
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.logged = logged;
+        }
+        Ok(())
+    }
+
+    fn is_live(&self, at: Timestamp, now: Timestamp) -> bool {
+        now.as_u64().saturating_sub(at.as_u64()) < self.ttl_secs
+    }
+
+    fn insert(&self, entries: &mut JobRequestEntries, record: JobRequestRecord, now: Timestamp) {
+        if !self.is_live(record.at, now) {
+            return;
+        }
+
+        let answered = &mut entries.answered;
+        if !answered.contains_key(&record.key) && answered.len() >= self.capacity {
+            answered.retain(|_, (_, at)| self.is_live(*at, now));
+        }
+        if !answered.contains_key(&record.key) && answered.len() >= self.capacity {
+            let oldest = answered
+                .iter()
+                .min_by_key(|(_, (_, at))| *at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                answered.remove(&oldest);
+            }
+        }
+        answered.insert(record.key, (record.result, record.at));
+    }
+
+    fn release(&self, key: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.pending.remove(key);
+        }
+    }
+}
+
+/// A key claimed by `JobRequestStore::reserve`.
+#[derive(Debug)]
+pub struct JobRequestReservation {
+    store: JobRequestStore,
+    key: String,
+}
+
+impl JobRequestReservation {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl Drop for JobRequestReservation {
+    fn drop(&mut self) {
+        self.store.release(&self.key);
+    }
+}
+
+fn records_read(path: &Path) -> Result<Vec<JobRequestRecord>, JobRequestStoreError> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(JobRequestStoreError::FileOpen(path.to_path_buf(), e)),
+    };
+
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| JobRequestStoreError::FileOpen(path.to_path_buf(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A crash mid-append leaves a partial last line, which only loses that record.
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("skipping unreadable job request store record: {e}"),
+        }
+    }
+    Ok(records)
+}
+
+fn record_append(path: &Path, line: &str) -> Result<(), JobRequestStoreError> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(format!("{line}\n").as_bytes())?;
+    file.sync_data()?;
+    Ok(())
+}
+
+fn atomic_write(path: &Path, json: &str) -> Result<(), JobRequestStoreError> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let mut temp_file = NamedTempFile::new_in(dir)?;
+
+    temp_file.write_all(json.as_bytes())?;
+    temp_file.as_file_mut().sync_all()?;
+    temp_file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_id(byte: u8) -> EventId {
+        EventId::from_byte_array([byte; 32])
+    }
+
+    fn store_settings(capacity: usize) -> CacheSettings {
+        CacheSettings {
+            capacity,
+            ttl_secs: 3_600,
+        }
+    }
+
+    #[tokio::test]
+    async fn records_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("processed.jsonl");
+        let path = path.to_str().unwrap();
+
+        let store = JobRequestStore::open(Some(path), &store_settings(10))
+            .await
+            .unwrap();
+        assert_eq!(store.get("request"), None);
+        store.record("request".into(), result_id(1)).await.unwrap();
+        drop(store);
+
+        let restarted = JobRequestStore::open(Some(path), &store_settings(10))
+            .await
+            .unwrap();
+        assert_eq!(restarted.get("request"), Some(result_id(1)));
+        assert_eq!(
+            restarted.reserve("request").err(),
+            Some(JobRequestStoreEntry::Answered(result_id(1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_records_all_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("processed.jsonl");
+        let path = path.to_str().unwrap();
+        let store = JobRequestStore::open(Some(path), &store_settings(100))
+            .await
+            .unwrap();
+
+        let records = (0..8u8).map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move { store.record(format!("request-{i}"), result_id(i)).await })
+        });
+        for record in records {
+            record.await.unwrap().unwrap();
+        }
+
+        let restarted = JobRequestStore::open(Some(path), &store_settings(100))
+            .await
+            .unwrap();
+        for i in 0..8u8 {
+            assert_eq!(restarted.get(&format!("request-{i}")), Some(result_id(i)));
+        }
+    }
+
+    #[tokio::test]
+    async fn in_memory_without_path() {
+        let store = JobRequestStore::open(None, &store_settings(10))
+            .await
+            .unwrap();
+
+        store.record("request".into(), result_id(1)).await.unwrap();
+
+        assert_eq!(store.get("request"), Some(result_id(1)));
+    }
+
+    #[test]
+    fn reservation_held_until_recorded_or_dropped() {
+        let store = JobRequestStore::default();
+
+        let reservation = store.reserve("request").unwrap();
+        assert_eq!(reservation.key(), "request");
+        assert_eq!(
+            store.reserve("request").err(),
+            Some(JobRequestStoreEntry::Pending)
+        );
+
+        drop(reservation);
+        assert!(store.reserve("request").is_ok());
+    }
+
+    #[tokio::test]
+    async fn recorded_reservation_answers_repeats() {
+        let store = JobRequestStore::default();
+
+        let reservation = store.reserve("request").unwrap();
+        store.record("request".into(), result_id(1)).await.unwrap();
+        drop(reservation);
+
+        assert_eq!(
+            store.reserve("request").err(),
+            Some(JobRequestStoreEntry::Answered(result_id(1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn oldest_records_evicted_over_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("processed.jsonl");
+        let path = path.to_str().unwrap();
+
+        // Records made within a second share a timestamp, so the log is written with older ones.
+        let lines: Vec<String> = (0..3u64)
+            .map(|i| {
+                let record = JobRequestRecord {
+                    key: format!("request-{i}"),
+                    result: result_id(i as u8),
+                    at: Timestamp::from(Timestamp::now().as_u64() - 30 + i),
+                };
+                serde_json::to_string(&record).unwrap()
+            })
+            .collect();
+        std::fs::write(path, lines.join("\n")).unwrap();
+
+        let restarted = JobRequestStore::open(Some(path), &store_settings(2))
+            .await
+            .unwrap();
+        assert_eq!(restarted.get("request-0"), None);
+        assert_eq!(restarted.get("request-1"), Some(result_id(1)));
+        assert_eq!(restarted.get("request-2"), Some(result_id(2)));
+    }
+
+    #[tokio::test]
+    async fn expired_records_dropped_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("processed.jsonl");
+        let record = |key: &str, age: u64| JobRequestRecord {
+            key: key.into(),
+            result: result_id(1),
+            at: Timestamp::from(Timestamp::now().as_u64() - age),
+        };
+        let lines = [record("stale", 7_200), record("fresh", 60)]
+            .map(|record| serde_json::to_string(&record).unwrap());
+        std::fs::write(&path, lines.join("\n") + "\n{\"key\":").unwrap();
+
+        let store = JobRequestStore::open(path.to_str(), &store_settings(10))
+            .await
+            .unwrap();
+
+        assert_eq!(store.get("stale"), None);
+        assert_eq!(store.get("fresh"), Some(result_id(1)));
+    }
+
+    #[tokio::test]
+    async fn log_compacted_to_live_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("processed.jsonl");
+        let path = path.to_str().unwrap();
+        let store = JobRequestStore::open(Some(path), &store_settings(2))
+            .await
+            .unwrap();
+
+        for i in 0..5u8 {
+            store.record("request".into(), result_id(i)).await.unwrap();
+        }
+
+        let log = std::fs::read_to_string(path).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        let restarted = JobRequestStore::open(Some(path), &store_settings(2))
+            .await
+            .unwrap();
+        assert_eq!(restarted.get("request"), Some(result_id(4)));
+    }
+}
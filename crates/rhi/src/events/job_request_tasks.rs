@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use nostr::{
+    event::{Event, EventId},
+    key::PublicKey,
+};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Clone, Default)]
+pub struct JobRequestTasks {
+    tasks: Arc<Mutex<HashMap<EventId, (PublicKey, CancellationToken)>>>,
+}
+
+impl JobRequestTasks {
+    pub fn start(&self, event: &Event) -> CancellationToken {
+        let token = CancellationToken::new();
+        if let Ok(mut tasks) = self.tasks.lock() {
+            tasks.insert(event.id, (event.pubkey, token.clone()));
+        }
+        token
+    }
+
+    pub fn finish(&self, id: &EventId) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            tasks.remove(id);
+        }
+    }
+
+    pub fn cancel(&self, deletion: &Event) -> Vec<EventId> {
+        let mut canceled = vec![];
+
+        if let Ok(tasks) = self.tasks.lock() {
+            for id in deletion.tags.event_ids() {
+                if let Some((requester, token)) = tasks.get(id) {
+                    if *requester == deletion.pubkey {
+                        token.cancel();
+                        canceled.push(*id);
+                    }
+                }
+            }
+        }
+
+        canceled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{
+        event::{EventBuilder, Kind, Tag},
+        key::Keys,
+    };
+
+    use super::*;
+
+    fn job_request(keys: &Keys) -> Event {
+        EventBuilder::new(Kind::Custom(5300), "")
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    fn deletion(keys: &Keys, id: EventId) -> Event {
+        EventBuilder::new(Kind::EventDeletion, "")
+            .tag(Tag::event(id))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn requester_cancels_task() {
+        let requester = Keys::generate();
+        let tasks = JobRequestTasks::default();
+        let event = job_request(&requester);
+        let token = tasks.start(&event);
+
+        assert_eq!(
+            tasks.cancel(&deletion(&requester, event.id)),
+            vec![event.id]
+        );
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn other_pubkey_cannot_cancel_task() {
+        let requester = Keys::generate();
+        let tasks = JobRequestTasks::default();
+        let event = job_request(&requester);
+        let token = tasks.start(&event);
+
+        assert!(
+            tasks
+                .cancel(&deletion(&Keys::generate(), event.id))
+                .is_empty()
+        );
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn finished_task_not_canceled() {
+        let requester = Keys::generate();
+        let tasks = JobRequestTasks::default();
+        let event = job_request(&requester);
+        let token = tasks.start(&event);
+        tasks.finish(&event.id);
+
+        assert!(tasks.cancel(&deletion(&requester, event.id)).is_empty());
+        assert!(!token.is_cancelled());
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use nostr::{
+    event::{Event, EventId, Kind},
+    key::{Keys, PublicKey},
+    types::Timestamp,
+};
+use nostr_sdk::RelayPoolNotification;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::config::{ListingIndexSettings, RelayRole, RelaySettings, Settings};
+use crate::handlers::job_request_order::classified_verify;
+use crate::models::event_classified::EventClassified;
+use crate::utils::cache::TtlCache;
+use crate::utils::nostr::{
+    nostr_client_add_relays, nostr_client_connect, nostr_client_new, nostr_filter_deletions,
+    nostr_filter_kinds, nostr_filter_new_events,
+};
+
+/// The `pubkey:kind:d` address an addressable listing is replaced under.
+type ListingAddress = (PublicKey, Kind, String);
+
+#[derive(Debug, Clone)]
+struct IndexedListing {
+    author: PublicKey,
+    created_at: Timestamp,
+    classified: EventClassified,
+}
+
+/// Listings verified and parsed by the index subscriber, read by every profile's order and
+/// quote handlers. A stored listing reaches the subscription only once, so entries don't expire
+/// by age: they are evicted when deleted by their author, replaced under the same address,
+/// past their `expiration`, or the least recently read once the index is full.
+#[derive(Debug, Clone, Default)]
+pub struct ListingIndex {
+    listings: Option<TtlCache<EventId, IndexedListing>>,
+    addresses: Arc<Mutex<HashMap<ListingAddress, EventId>>>,
+}
+
+impl ListingIndex {
+    pub fn get(&self, id: &EventId) -> Option<EventClassified> {
+        let listings = self.listings.as_ref()?;
+        let indexed = listings.get(id)?;
+
+        if indexed
+            .classified
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Timestamp::now())
+        {
+            listings.remove(id);
+            return None;
+        }
+
+        Some(indexed.classified)
+    }
+
+    /// Indexes a verified listing, replacing an older one published under the same address.
+    pub fn insert(&self, event: &Event, classified: EventClassified) {
+        let Some(listings) = &self.listings else {
+            return;
+        };
+        let Ok(mut addresses) = self.addresses.lock() else {
+            return;
+        };
+
+        if let Some(identifier) = event.tags.identifier() {
+            let address = (event.pubkey, event.kind, identifier.to_string());
+            if let Some(previous) = addresses.get(&address).copied() {
+                match listings.get(&previous) {
+                    Some(indexed) if indexed.created_at > event.created_at => return,
+                    _ => {
+                        listings.remove(&previous);
+                    }
+                }
+            }
+            addresses.insert(address, event.id);
+        }
+
+        listings.insert(
+            event.id,
+            IndexedListing {
+                author: event.pubkey,
+                created_at: event.created_at,
+                classified,
+            },
+        );
+
+        // Addresses whose listing was evicted for capacity are no longer needed.
+        if addresses.len() > listings.capacity() {
+            addresses.retain(|_, id| listings.contains(id));
+        }
+    }
+
+    /// Evicts the listings a NIP-09 deletion names, by id or by address, if its author owns them.
+    pub fn delete(&self, deletion: &Event) {
+        let Some(listings) = &self.listings else {
+            return;
+        };
+        let Ok(mut addresses) = self.addresses.lock() else {
+            return;
+        };
+
+        for id in deletion.tags.event_ids() {
+            if listings
+                .get(id)
+                .is_some_and(|indexed| indexed.author == deletion.pubkey)
+            {
+                listings.remove(id);
+            }
+        }
+
+        for coordinate in deletion.tags.coordinates() {
+            if coordinate.public_key != deletion.pubkey {
+                continue;
+            }
+            let address = (
+                coordinate.public_key,
+                coordinate.kind,
+                coordinate.identifier.clone(),
+            );
+            let Some(id) = addresses.get(&address).copied() else {
+                continue;
+            };
+            if listings
+                .get(&id)
+                .is_none_or(|indexed| indexed.created_at <= deletion.created_at)
+            {
+                listings.remove(&id);
+                addresses.remove(&address);
+            }
+        }
+    }
+}
+
+impl From<Option<&ListingIndexSettings>> for ListingIndex {
+    fn from(settings: Option<&ListingIndexSettings>) -> Self {
+        Self {
+            listings: settings.map(|settings| TtlCache::new(settings.capacity, Duration::MAX)),
+            addresses: Arc::default(),
+        }
+    }
+}
+
+/// Parses a listing with the checks the order handler applies to fetched listings, so index
+/// hits need no further checks.
+fn listing_index_parse(event: &Event, settings: &Settings) -> Option<EventClassified> {
+    classified_verify(event, &event.id, settings).ok()
+}
+
+pub async fn subscriber(
+    keys: Keys,
+    relays: Vec<RelaySettings>,
+    settings: Settings,
+    index: ListingIndex,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let Some(index_settings) = settings.listing_index.clone() else {
+        return Ok(());
+    };
+
+    let relays = if index_settings.relays.is_empty() {
+        relays
+    } else {
+        index_settings
+            .relays
+            .iter()
+            .map(|url| RelaySettings {
+                url: url.clone(),
+                role: RelayRole::Read,
+                auth: false,
+            })
+            .collect()
+    };
+
+    info!("Starting listing index for kind {}", index_settings.kind);
+    let client = nostr_client_new(keys, &relays);
+    nostr_client_add_relays(&client, &relays).await?;
+    nostr_client_connect(
+        &client,
+        settings.connect_timeout(),
+        settings.min_connected_relays,
+    )
+    .await?;
+
+    // Relays drop deleted listings, so only deletions from now on can name an indexed one.
+    client
+        .subscribe(nostr_filter_kinds(&[index_settings.kind]), None)
+        .await?;
+    client
+        .subscribe(
+            nostr_filter_new_events(nostr_filter_deletions(&[index_settings.kind])),
+            None,
+        )
+        .await?;
+
+    let mut notifications = client.notifications();
+
+    loop {
+        let n = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            n = notifications.recv() => match n {
+                Ok(n) => n,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("listing index lagged, {skipped} notifications skipped");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        let RelayPoolNotification::Event { event, .. } = n else {
+            continue;
+        };
+
+        if event.kind == Kind::EventDeletion {
+            if event.verify().is_ok() {
+                index.delete(&event);
+            }
+            continue;
+        }
+
+        match listing_index_parse(&event, &settings) {
+            Some(classified) => index.insert(&event, classified),
+            None => debug!("event {} not indexed, not a verified listing", event.id),
+        }
+    }
+
+    client.disconnect().await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::event::{EventBuilder, Tag, TagKind};
+
+    use super::*;
+
+    fn listing_event(kind: Kind, tags: Vec<Tag>) -> Event {
+        listing_event_by(&Keys::generate(), kind, tags, Timestamp::now())
+    }
+
+    fn listing_event_by(keys: &Keys, kind: Kind, tags: Vec<Tag>, created_at: Timestamp) -> Event {
+        EventBuilder::new(kind, "indexed")
+            .tags(tags)
+            .custom_created_at(created_at)
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    fn listing_tags() -> Vec<Tag> {
+        vec![
+            Tag::custom(TagKind::custom("price"), ["12", "USD", "1", "kg"]),
+            Tag::custom(TagKind::custom("quantity"), ["500", "g", "bag"]),
+        ]
+    }
+
+    fn addressed_tags(identifier: &str) -> Vec<Tag> {
+        let mut tags = listing_tags();
+        tags.push(Tag::identifier(identifier));
+        tags
+    }
+
+    fn index_settings() -> Settings {
+        Settings {
+            listing_index: Some(ListingIndexSettings::default()),
+            ..Settings::default()
+        }
+    }
+
+    fn indexed(index: &ListingIndex, settings: &Settings, event: &Event) {
+        index.insert(event, listing_index_parse(event, settings).unwrap());
+    }
+
+    #[test]
+    fn listing_index_parses_verified_listings() {
+        let settings = Settings::default();
+        let kind = Kind::ClassifiedListing;
+        let event = listing_event(kind, listing_tags());
+
+        let classified = listing_index_parse(&event, &settings).unwrap();
+        assert_eq!(classified.id, event.id);
+        assert!(classified.is_listing());
+
+        let mut tampered = event.clone();
+        tampered.content = "discounted".into();
+        assert!(listing_index_parse(&tampered, &settings).is_none());
+
+        let note = listing_event(Kind::TextNote, listing_tags());
+        assert!(listing_index_parse(&note, &settings).is_none());
+
+        let unpriced = listing_event(kind, vec![]);
+        assert!(listing_index_parse(&unpriced, &settings).is_none());
+    }
+
+    #[test]
+    fn listing_index_parses_only_the_configured_kind() {
+        let settings = Settings {
+            listing_index: Some(ListingIndexSettings {
+                kind: 30403,
+                ..Default::default()
+            }),
+            ..Settings::default()
+        };
+
+        let configured = listing_event(Kind::Custom(30403), listing_tags());
+        assert!(listing_index_parse(&configured, &settings).is_some());
+
+        let classified = listing_event(Kind::ClassifiedListing, listing_tags());
+        assert!(listing_index_parse(&classified, &settings).is_none());
+    }
+
+    #[test]
+    fn listing_index_evicts_listings_deleted_by_their_author() {
+        let settings = index_settings();
+        let index = ListingIndex::from(settings.listing_index.as_ref());
+        let author = Keys::generate();
+        let by_id = listing_event_by(
+            &author,
+            Kind::ClassifiedListing,
+            listing_tags(),
+            Timestamp::now(),
+        );
+        let by_address = listing_event_by(
+            &author,
+            Kind::ClassifiedListing,
+            addressed_tags("coffee"),
+            Timestamp::now(),
+        );
+        indexed(&index, &settings, &by_id);
+        indexed(&index, &settings, &by_address);
+
+        let deletion = |keys: &Keys| {
+            EventBuilder::new(Kind::EventDeletion, "")
+                .tags([
+                    Tag::event(by_id.id),
+                    Tag::parse([
+                        "a".to_string(),
+                        format!("30402:{}:coffee", author.public_key()),
+                    ])
+                    .unwrap(),
+                ])
+                .sign_with_keys(keys)
+                .unwrap()
+        };
+
+        index.delete(&deletion(&Keys::generate()));
+        assert!(index.get(&by_id.id).is_some());
+        assert!(index.get(&by_address.id).is_some());
+
+        index.delete(&deletion(&author));
+        assert!(index.get(&by_id.id).is_none());
+        assert!(index.get(&by_address.id).is_none());
+    }
+
+    #[test]
+    fn listing_index_replaces_listings_under_the_same_address() {
+        let settings = index_settings();
+        let index = ListingIndex::from(settings.listing_index.as_ref());
+        let author = Keys::generate();
+        let now = Timestamp::now().as_u64();
+        let version = |identifier: &str, created_at: u64| {
+            listing_event_by(
+                &author,
+                Kind::ClassifiedListing,
+                addressed_tags(identifier),
+                Timestamp::from(created_at),
+            )
+        };
+        let (first, second, stale) = (
+            version("coffee", now - 20),
+            version("coffee", now - 10),
+            version("coffee", now - 30),
+        );
+        let other = version("tea", now - 20);
+
+        indexed(&index, &settings, &first);
+        indexed(&index, &settings, &other);
+        indexed(&index, &settings, &second);
+        indexed(&index, &settings, &stale);
+
+        assert!(index.get(&first.id).is_none());
+        assert!(index.get(&stale.id).is_none());
+        assert!(index.get(&second.id).is_some());
+        assert!(index.get(&other.id).is_some());
+    }
+
+    #[test]
+    fn listing_index_drops_expired_listings() {
+        let settings = index_settings();
+        let index = ListingIndex::from(settings.listing_index.as_ref());
+        let mut tags = listing_tags();
+        tags.push(Tag::expiration(Timestamp::from(
+            Timestamp::now().as_u64() - 1,
+        )));
+        let expired = listing_event(Kind::ClassifiedListing, tags);
+        let live = listing_event(Kind::ClassifiedListing, listing_tags());
+        indexed(&index, &settings, &expired);
+        indexed(&index, &settings, &live);
+
+        assert!(index.get(&expired.id).is_none());
+        assert!(index.get(&live.id).is_some());
+    }
+}
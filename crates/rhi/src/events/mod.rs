@@ -1 +1,7 @@
 pub mod job_request;
+pub mod job_request_builder;
+pub mod job_request_params;
+pub mod job_request_state;
+pub mod job_request_store;
+pub mod job_request_tasks;
+pub mod listing_index;
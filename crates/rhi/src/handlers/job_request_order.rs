@@ -1,17 +1,46 @@
 use anyhow::Result;
 use nostr::{
-    event::{Event, Tag, TagKind},
+    event::{Event, EventId, Tag, TagKind},
     key::Keys,
+    nips::nip90::DataVendingMachineStatus,
+    types::Timestamp,
 };
 use nostr_sdk::{Client, client::Error as NostrClientError};
-use radroots_common::models::listing_order_request::ListingOrderRequest;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::info;
+use tracing::{info, warn};
+use typeshare::typeshare;
 
 use crate::{
-    events::job_request::{JobRequest, JobRequestError, JobRequestInput},
-    models::event_classified::EventClassified,
-    utils::nostr::{nostr_event_job_result, nostr_fetch_event_by_id, nostr_send_event},
+    config::{RoundingMode, Settings, UrlInputs},
+    events::{
+        job_request::{
+            JobRequest, JobRequestError, JobRequestInput, JobRequestInputType, JobResultPending,
+            JobResultSent, OutputFormat, job_request_input_job_result, job_result_event_tag,
+            job_result_output_tag, render_result, render_supported,
+        },
+        job_request_params::JobRequestParamsError,
+        job_request_state::JobRequestState,
+    },
+    models::{
+        event_classified::{EventClassified, OrderContext},
+        order_result::{
+            OrderClassifiedResult, OrderResult, OrderResultEnvelope, OrderResultLine,
+            OrderResultReceipt, OrderResultSummary, OrderResultTotal,
+        },
+    },
+    utils::{
+        geo::haversine_km,
+        http::{http_fetch_text, http_post_json, http_url_is_supported},
+        nostr::{
+            EventCache, Payment, nostr_event_is_encrypted, nostr_event_job_feedback,
+            nostr_event_job_result, nostr_event_job_result_encrypted, nostr_fetch_event_by_id,
+            nostr_send_event, nostr_tag_first_value,
+        },
+        price::{OrderSpec, round_amount},
+        rate::{FiatRateError, FiatRateProvider, fiat_amount_msat},
+        unit::Unit,
+    },
 };
 
 #[derive(Debug, Error)]
@@ -32,41 +61,2289 @@ pub enum JobRequestOrderError {
     ResponseSend(#[from] NostrClientError),
 
     #[error("Request cannot be satisfied: {0}")]
-    Unsatisfiable(String),
+    Unsatisfiable(#[from] OrderUnsatisfiable),
+
+    #[error("Invalid order parameter: {0}")]
+    InvalidParam(#[from] JobRequestParamsError),
+
+    #[error("Failed to fetch order input: {0}")]
+    FetchInput(String),
+
+    #[error("Invalid order: {0}")]
+    InvalidOrder(String),
+
+    #[error("Order total cannot be converted to msat: {0}")]
+    FiatRate(#[from] FiatRateError),
+}
+
+impl JobRequestOrderError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::ParseReference(_) => "INVALID_REFERENCE",
+            Self::FetchReference(_) => "FETCH_REFERENCE_FAILED",
+            Self::MissingReference(_) => "MISSING_REFERENCE",
+            Self::MissingRequested(_) => "MISSING_REQUESTED",
+            Self::ResponseSend(_) => "RESPONSE_SEND_FAILED",
+            Self::Unsatisfiable(_) => "UNSATISFIABLE",
+            Self::InvalidParam(_) => "INVALID_PARAM",
+            Self::FetchInput(_) => "FETCH_INPUT_FAILED",
+            Self::InvalidOrder(_) => "INVALID_ORDER",
+            Self::FiatRate(_) => "FIAT_RATE_UNAVAILABLE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Error)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum OrderUnsatisfiable {
+    #[error("listing expired at {expires_at}")]
+    ListingExpired { expires_at: Timestamp },
+
+    #[error("order count {count} below listing minimum of {min}")]
+    BelowMinimumOrder { count: f64, min: u32 },
+
+    #[error("order count {count} above listing maximum of {max}")]
+    AboveMaximumOrder { count: f64, max: u32 },
+
+    #[error("insufficient stock")]
+    OutOfStock,
+
+    #[error("cannot check a {quantity_unit} quantity against stock in {stock_unit}")]
+    StockUnitMismatch {
+        quantity_unit: String,
+        stock_unit: String,
+    },
+
+    #[error("invalid {field} unit")]
+    InvalidUnit { field: &'static str },
+
+    #[error("requested packaging {amount} {unit} not available")]
+    PackagingUnavailable { amount: f64, unit: String },
+
+    #[error("no matching price tier {quantity_amount} {quantity_unit} {currency} found")]
+    NoMatchingTier {
+        quantity_amount: f64,
+        quantity_unit: String,
+        currency: String,
+    },
+
+    #[error("currency {currency} not accepted, expected one of {}", accepted.join(", "))]
+    CurrencyNotAccepted {
+        currency: String,
+        accepted: Vec<String>,
+    },
+
+    #[error("ambiguous price tier")]
+    AmbiguousTier,
+
+    #[error("price mismatch: expected {expected}, got {got}")]
+    PriceMismatch { expected: f64, got: f64 },
+
+    #[error("cannot price a {quantity_unit} quantity by {price_unit}")]
+    UnitMismatch {
+        quantity_unit: String,
+        price_unit: String,
+    },
+
+    #[error("{component} currency {currency} does not match order currency {expected}")]
+    CurrencyMismatch {
+        component: &'static str,
+        currency: String,
+        expected: String,
+    },
+
+    #[error("mass-based shipping unavailable for count quantities")]
+    ShippingUnavailable,
+
+    #[error("outside service area")]
+    OutsideServiceArea,
+
+    #[error("listing has no location")]
+    MissingLocation,
+}
+
+const ORDER_AS_OF_MAX_SKEW_SECS: u64 = 60;
+
+pub enum JobRequestOrderData {
+    Single(JobRequestOrderSingle),
+    Multiple(Vec<JobRequestOrderLine>),
+    Inline(JobRequestOrderInline),
+}
+
+/// Mirrors `ListingOrderRequest`, with the payload read as an `OrderSpec`.
+#[derive(Deserialize)]
+pub struct JobRequestOrderSingle {
+    pub event: JobRequestOrderEvent,
+    pub payload: OrderSpec,
+}
+
+#[derive(Deserialize)]
+pub struct JobRequestOrderEvent {
+    pub id: String,
+}
+
+#[typeshare]
+#[derive(Deserialize)]
+pub struct JobRequestOrderLine {
+    pub id: String,
+    #[typeshare(serialized_as = "OrderSpecWire")]
+    pub order: OrderSpec,
+}
+
+pub struct JobRequestOrderInline {
+    pub listing: JobRequestOrderInlineListing,
+    pub order: OrderSpec,
+}
+
+/// A signed listing event is checked like a fetched one and referenced in the result. A bare
+/// `EventClassified` is priced as given only under `unsigned_inline_listings`, since nothing
+/// vouches for it, and is never referenced.
+pub enum JobRequestOrderInlineListing {
+    Signed(Event),
+    Classified(EventClassified),
+}
+
+impl JobRequestOrderInline {
+    fn from_fields(
+        fields: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Self, serde_json::Error> {
+        let listing = fields.get("listing").cloned().unwrap_or_default();
+        let listing = if listing.get("sig").is_some() {
+            JobRequestOrderInlineListing::Signed(serde_json::from_value(listing)?)
+        } else {
+            JobRequestOrderInlineListing::Classified(serde_json::from_value(listing)?)
+        };
+        let order = serde_json::from_value(fields.get("order").cloned().unwrap_or_default())?;
+        Ok(Self { listing, order })
+    }
+}
+
+impl JobRequestOrderInlineListing {
+    pub fn id(&self) -> EventId {
+        match self {
+            Self::Signed(event) => event.id,
+            Self::Classified(classified) => classified.id,
+        }
+    }
+}
+
+impl JobRequestOrderData {
+    /// Picks the shape from the JSON before deserializing it, so a field error names the
+    /// field instead of reporting that no shape matched.
+    pub fn parse(data: &str) -> Result<Self, JobRequestOrderError> {
+        let parse_error =
+            |e: serde_json::Error| JobRequestOrderError::ParseReference(e.to_string());
+        let value: serde_json::Value = serde_json::from_str(data).map_err(parse_error)?;
+
+        match &value {
+            serde_json::Value::Array(lines) => {
+                for (index, line) in lines.iter().enumerate() {
+                    order_field_check(line, "id", serde_json::Value::is_string, "a string")
+                        .and_then(|()| order_amounts_check(line, "order"))
+                        .map_err(|e| {
+                            JobRequestOrderError::InvalidOrder(format!("order line {index}: {e}"))
+                        })?;
+                }
+                serde_json::from_str(data).map(Self::Multiple)
+            }
+            serde_json::Value::Object(fields) if fields.contains_key("listing") => {
+                order_amounts_check(&value, "order").map_err(JobRequestOrderError::InvalidOrder)?;
+                JobRequestOrderInline::from_fields(fields).map(Self::Inline)
+            }
+            _ => {
+                order_field_check(&value, "event.id", serde_json::Value::is_string, "a string")
+                    .and_then(|()| order_amounts_check(&value, "payload"))
+                    .map_err(JobRequestOrderError::InvalidOrder)?;
+                serde_json::from_str(data).map(Self::Single)
+            }
+        }
+        .map_err(parse_error)
+    }
+
+    pub fn validate(&self) -> Result<(), JobRequestOrderError> {
+        match self {
+            Self::Single(order) => validate_order_line(&order.event.id, &order.payload),
+            Self::Multiple(lines) => lines
+                .iter()
+                .try_for_each(|line| validate_order_line(&line.id, &line.order)),
+            Self::Inline(inline) => {
+                validate_order_line(&inline.listing.id().to_hex(), &inline.order)
+            }
+        }
+    }
+}
+
+/// Checks a field by its full dotted path, since serde would only name the last segment.
+fn order_field_check(
+    value: &serde_json::Value,
+    path: &str,
+    matches: fn(&serde_json::Value) -> bool,
+    expected: &str,
+) -> Result<(), String> {
+    let mut field = value;
+    let mut walked = 0;
+    for segment in path.split('.') {
+        walked += segment.len();
+        field = match field.get(segment) {
+            Some(next) if !next.is_null() => next,
+            _ => return Err(format!("{} is missing", &path[..walked])),
+        };
+        walked += 1;
+    }
+    if !matches(field) {
+        return Err(format!("{path} must be {expected}, got {field}"));
+    }
+    Ok(())
+}
+
+fn order_amounts_check(value: &serde_json::Value, order_path: &str) -> Result<(), String> {
+    ["quantity.amount", "price.amount"]
+        .iter()
+        .try_for_each(|amount| {
+            order_field_check(
+                value,
+                &format!("{order_path}.{amount}"),
+                serde_json::Value::is_number,
+                "a number",
+            )
+        })
+}
+
+fn validate_order_line(id: &str, order: &OrderSpec) -> Result<(), JobRequestOrderError> {
+    let invalid = |msg: String| Err(JobRequestOrderError::InvalidOrder(msg));
+    let quantity = &order.payload.quantity;
+    let price = &order.payload.price;
+
+    if id.trim().is_empty() {
+        return invalid("event id is empty".into());
+    }
+    if !(quantity.amount.is_finite() && quantity.amount > 0.0) {
+        return invalid(format!(
+            "quantity amount {} must be positive",
+            quantity.amount
+        ));
+    }
+    if order.fractional_count.is_none() && quantity.count == 0 {
+        return invalid("quantity count must be positive".into());
+    }
+    order.count()?;
+    if quantity.unit.parse::<Unit>().is_err() {
+        return invalid(format!("unknown quantity unit {}", quantity.unit));
+    }
+    if !(price.amount.is_finite() && price.amount > 0.0) {
+        return invalid(format!("price amount {} must be positive", price.amount));
+    }
+    if !(price.quantity_amount.is_finite() && price.quantity_amount > 0.0) {
+        return invalid(format!(
+            "price quantity amount {} must be positive",
+            price.quantity_amount
+        ));
+    }
+    if price.quantity_unit.parse::<Unit>().is_err() {
+        return invalid(format!(
+            "unknown price quantity unit {}",
+            price.quantity_unit
+        ));
+    }
+    if !(price.currency.len() == 3 && price.currency.chars().all(|c| c.is_ascii_alphabetic())) {
+        return invalid(format!("unknown currency {}", price.currency));
+    }
+
+    Ok(())
 }
 
 pub async fn handle_job_request_order(
     event_job_request: Event,
-    _keys: Keys,
+    keys: Keys,
     client: Client,
-    _job_req: JobRequest,
+    state: JobRequestState,
+    settings: Settings,
+    job_req: JobRequest,
     job_req_input: JobRequestInput,
-) -> Result<(), JobRequestError> {
-    let order_data: ListingOrderRequest = serde_json::from_str(&job_req_input.data)
-        .map_err(|e| JobRequestOrderError::ParseReference(e.to_string()))?;
+) -> Result<Option<JobResultPending>, JobRequestError> {
+    let ctx = order_context(&job_req, &settings)?;
+    let output = job_req.output.unwrap_or_default();
+    render_supported::<OrderResultEnvelope<OrderResult>>(output)?;
 
-    let ref_id = &order_data.event.id;
-    let ref_event = nostr_fetch_event_by_id(client.clone(), ref_id)
-        .await
-        .map_err(|_| JobRequestOrderError::FetchReference(ref_id.clone()))?;
+    let processing = nostr_event_job_feedback(
+        &event_job_request,
+        DataVendingMachineStatus::Processing,
+        None,
+        None,
+        None,
+    )?;
+    if let Err(e) =
+        nostr_send_event(client.clone(), processing, &state.audit, settings.dry_run).await
+    {
+        warn!("job request order processing feedback not sent: {e}");
+    }
+
+    let order_data =
+        order_data_resolve(&client, &state.event_cache, &settings, &job_req_input).await?;
+
+    let ((payload, mimetype), mut tags, amount_msat, total) = match order_data {
+        JobRequestOrderData::Single(order) => {
+            let order_line = calculate_order_line(
+                &client,
+                &state,
+                &settings,
+                &order.event.id,
+                job_req_input.relay.as_deref(),
+                &order.payload,
+                &ctx,
+            )
+            .await?;
+            order_line_render(order_line, &job_req, state.rates.as_deref(), output).await?
+        }
+        JobRequestOrderData::Inline(inline) => {
+            if job_req_input.input_type != JobRequestInputType::Text {
+                return Err(JobRequestError::InvalidInputType(format!(
+                    "inline listing in {} input",
+                    job_req_input.input_type.as_str()
+                )));
+            }
 
-    let ref_classified = EventClassified::from_event(&ref_event)
-        .map_err(|_| JobRequestOrderError::ParseReference(ref_id.clone()))?;
+            let order_line = match &inline.listing {
+                JobRequestOrderInlineListing::Signed(event) => {
+                    let listing = classified_verify(event, &event.id, &settings)?;
+                    calculate_classified_order_line(&listing, &settings, &inline.order, &ctx)?
+                }
+                JobRequestOrderInlineListing::Classified(listing) => {
+                    if !settings.unsigned_inline_listings {
+                        return Err(JobRequestOrderError::ParseReference(format!(
+                            "inline listing {} is unsigned",
+                            listing.id
+                        ))
+                        .into());
+                    }
+                    if !listing.is_listing() {
+                        return Err(JobRequestOrderError::MissingRequested(format!(
+                            "inline listing {} is not a classified listing",
+                            listing.id
+                        ))
+                        .into());
+                    }
+                    let (_, order_result) =
+                        calculate_classified_order_line(listing, &settings, &inline.order, &ctx)?;
+                    (order_result_tags(listing), order_result)
+                }
+            };
+            order_line_render(order_line, &job_req, state.rates.as_deref(), output).await?
+        }
+        JobRequestOrderData::Multiple(order_lines) => {
+            if order_lines.is_empty() {
+                return Err(JobRequestOrderError::ParseReference("empty order".into()).into());
+            }
 
-    let order_result = ref_classified.calculate_order(&order_data.payload)?;
+            let mut lines = Vec::with_capacity(order_lines.len());
+            let mut tags = Vec::new();
+            for line in &order_lines {
+                let (ref_tags, order_result) = calculate_order_line(
+                    &client,
+                    &state,
+                    &settings,
+                    &line.id,
+                    job_req_input.relay.as_deref(),
+                    &line.order,
+                    &ctx,
+                )
+                .await?;
+                tags.extend(ref_tags);
+                lines.push(OrderResultLine {
+                    id: line.id.clone(),
+                    result: order_result,
+                });
+            }
 
-    let payload = serde_json::to_string(&order_result)?;
-    let tags = vec![Tag::custom(
-        TagKind::custom("e_ref"),
-        [ref_event.id.to_hex()],
-    )];
+            let total = order_result_total(&lines, ctx.rounding)?;
+            let amount_msat = match state.rates.as_deref() {
+                Some(rates) => Some(order_amount_msat(&total, rates).await?),
+                None => None,
+            };
+            let receipt = OrderResultReceipt::new(job_req.bid_msat, amount_msat);
+            let amount_msat = receipt.amount_msat;
+            let payload = render_result(
+                &OrderResultEnvelope {
+                    result: OrderResult {
+                        lines,
+                        total: total.clone(),
+                    },
+                    receipt,
+                },
+                output,
+            )?;
+            (payload, tags, amount_msat, total)
+        }
+    };
 
     let job_result_event =
-        nostr_event_job_result(&event_job_request, payload, 0, None, Some(tags))?;
+        if settings.encrypt_results || nostr_event_is_encrypted(&event_job_request) {
+            // Listing, reference and alt tags would reveal what was ordered, so they are left off.
+            nostr_event_job_result_encrypted(
+                &event_job_request,
+                &keys,
+                payload,
+                amount_msat,
+                Payment::None,
+                Some(vec![job_result_output_tag(mimetype)]),
+            )?
+        } else {
+            tags.push(order_result_alt_tag(&tags));
+            tags.push(job_result_output_tag(mimetype));
+            nostr_event_job_result(
+                &event_job_request,
+                payload,
+                amount_msat,
+                Payment::None,
+                Some(tags),
+            )?
+        };
+
+    let on_sent = settings
+        .result_webhook
+        .filter(|_| !settings.dry_run)
+        .map(|url| order_result_webhook(url, &job_req, total));
+
+    Ok(Some(JobResultPending {
+        event: job_result_event,
+        on_sent,
+    }))
+}
+
+fn order_result_webhook(
+    url: String,
+    job_req: &JobRequest,
+    total: OrderResultTotal,
+) -> JobResultSent {
+    let (request_id, requester) = (job_req.id, job_req.requester);
+    Box::new(move |result_id| {
+        Box::pin(async move {
+            let summary = OrderResultSummary {
+                request_id,
+                result_id,
+                total: total.price_amount,
+                currency: total.price_currency,
+                requester,
+            };
+            if let Err(e) = http_post_json(&url, &summary).await {
+                warn!("job request order result webhook failed: {e}");
+            }
+        })
+    })
+}
+
+async fn order_amount_msat<P: FiatRateProvider + ?Sized>(
+    total: &OrderResultTotal,
+    rates: &P,
+) -> Result<u64, JobRequestOrderError> {
+    Ok(fiat_amount_msat(rates, total.price_amount, &total.price_currency).await?)
+}
+
+async fn order_data_resolve(
+    client: &Client,
+    event_cache: &EventCache,
+    settings: &Settings,
+    input: &JobRequestInput,
+) -> Result<JobRequestOrderData, JobRequestError> {
+    let data = match input.input_type {
+        JobRequestInputType::Event | JobRequestInputType::Text => input.data.clone(),
+        JobRequestInputType::Url => {
+            if settings.url_inputs == UrlInputs::Disabled || !http_url_is_supported(&input.data) {
+                return Err(JobRequestError::InvalidInputType(format!(
+                    "url {}",
+                    input.data
+                )));
+            }
+            http_fetch_text(&input.data, settings.url_inputs == UrlInputs::Any)
+                .await
+                .map_err(|e| JobRequestOrderError::FetchInput(e.to_string()))?
+        }
+        JobRequestInputType::Job => {
+            job_request_input_job_result(client, event_cache, settings, input)
+                .await?
+                .content
+        }
+    };
+
+    let order_data = JobRequestOrderData::parse(&data)?;
+    order_data.validate()?;
+    Ok(order_data)
+}
+
+pub(crate) async fn classified_fetch(
+    client: &Client,
+    state: &JobRequestState,
+    settings: &Settings,
+    ref_id: &str,
+    relay: Option<&str>,
+) -> Result<EventClassified, JobRequestOrderError> {
+    let ref_event_id = EventId::parse(ref_id)
+        .map_err(|_| JobRequestOrderError::ParseReference(format!("invalid event id {ref_id}")))?;
+
+    // The index only holds listings it has already verified and parsed.
+    if let Some(classified) = state.listings.get(&ref_event_id) {
+        info!("reference event {ref_id} served from listing index");
+        return Ok(classified);
+    }
+
+    let ref_event = nostr_fetch_event_by_id(
+        client.clone(),
+        &state.event_cache,
+        &ref_event_id,
+        relay,
+        settings.fetch_timeout(),
+    )
+    .await
+    .map_err(|_| JobRequestOrderError::FetchReference(ref_id.to_string()))?
+    .ok_or_else(|| JobRequestOrderError::MissingReference(ref_id.to_string()))?;
+
+    classified_verify(&ref_event, &ref_event_id, settings)
+}
 
-    let job_result_event_id = nostr_send_event(client, job_result_event).await?;
+/// Checks a listing the same way whether it was fetched, sent inline or ingested by the index.
+pub(crate) fn classified_verify(
+    ref_event: &Event,
+    ref_event_id: &EventId,
+    settings: &Settings,
+) -> Result<EventClassified, JobRequestOrderError> {
+    let ref_id = ref_event_id.to_hex();
 
-    info!("job request order result sent: {:?}", job_result_event_id);
+    if ref_event.id != *ref_event_id || ref_event.verify().is_err() {
+        return Err(JobRequestOrderError::ParseReference(format!(
+            "reference event {ref_id} failed signature verification"
+        )));
+    }
+
+    let ref_classified = EventClassified::from_event(
+        ref_event,
+        settings.default_currency.as_deref(),
+        settings.default_unit.as_ref(),
+        settings
+            .service_area
+            .as_ref()
+            .and_then(|area| area.min_geohash_precision),
+    )
+    .map_err(|_| JobRequestOrderError::ParseReference(ref_id.clone()))?;
+
+    if ref_event.kind != settings.listing_kind() || !ref_classified.is_listing() {
+        return Err(JobRequestOrderError::MissingRequested(format!(
+            "referenced event {ref_id} is not a classified listing"
+        )));
+    }
+
+    Ok(ref_classified)
+}
+
+async fn calculate_order_line(
+    client: &Client,
+    state: &JobRequestState,
+    settings: &Settings,
+    ref_id: &str,
+    relay: Option<&str>,
+    order: &OrderSpec,
+    ctx: &OrderContext,
+) -> Result<(Vec<Tag>, OrderClassifiedResult), JobRequestError> {
+    let ref_classified = classified_fetch(client, state, settings, ref_id, relay).await?;
+
+    calculate_classified_order_line(&ref_classified, settings, order, ctx)
+}
+
+fn calculate_classified_order_line(
+    classified: &EventClassified,
+    settings: &Settings,
+    order: &OrderSpec,
+    ctx: &OrderContext,
+) -> Result<(Vec<Tag>, OrderClassifiedResult), JobRequestError> {
+    check_service_area(classified, settings)?;
+
+    let order_result = classified.calculate_order(order, ctx)?;
+
+    let mut tags = order_result_ref_tags(&classified.id);
+    tags.extend(order_result_tags(classified));
+
+    Ok((tags, order_result))
+}
+
+async fn order_line_render<P: FiatRateProvider + ?Sized>(
+    (ref_tags, order_result): (Vec<Tag>, OrderClassifiedResult),
+    job_req: &JobRequest,
+    rates: Option<&P>,
+    output: OutputFormat,
+) -> Result<((String, &'static str), Vec<Tag>, u64, OrderResultTotal), JobRequestError> {
+    let total = order_result.grand_total();
+    let amount_msat = match rates {
+        Some(rates) => Some(order_amount_msat(&total, rates).await?),
+        None => None,
+    };
+    let receipt = OrderResultReceipt::new(job_req.bid_msat, amount_msat);
+    let amount_msat = receipt.amount_msat;
+    let payload = render_result(
+        &OrderResultEnvelope {
+            result: order_result,
+            receipt,
+        },
+        output,
+    )?;
+    Ok((payload, ref_tags, amount_msat, total))
+}
+
+fn check_service_area(
+    classified: &EventClassified,
+    settings: &Settings,
+) -> Result<(), JobRequestOrderError> {
+    let Some(area) = &settings.service_area else {
+        return Ok(());
+    };
+
+    match &classified.geolocation {
+        Some(geo) => {
+            let distance = haversine_km((area.lat, area.lng), (geo.lat, geo.lng));
+            if distance > area.radius_km {
+                return Err(OrderUnsatisfiable::OutsideServiceArea.into());
+            }
+        }
+        None if area.require_location => {
+            return Err(OrderUnsatisfiable::MissingLocation.into());
+        }
+        None => {}
+    }
 
     Ok(())
 }
+
+fn order_context(
+    job_req: &JobRequest,
+    settings: &Settings,
+) -> Result<OrderContext, JobRequestOrderError> {
+    let params = job_req.job_params();
+    let currency = params.currency()?;
+
+    let ctx = OrderContext {
+        tax_rate_percent: settings.tax_rate_percent,
+        rounding: settings.rounding,
+        price_policy: settings.price_policy,
+        tier_selection: settings.tier_selection,
+        currency,
+        ..OrderContext::now()
+    };
+
+    let Some(as_of) = params.as_of()? else {
+        return Ok(ctx);
+    };
+
+    if as_of > ctx.now.as_u64() + ORDER_AS_OF_MAX_SKEW_SECS {
+        return Err(JobRequestParamsError {
+            key: "as_of",
+            value: format!("{as_of} is in the future"),
+        }
+        .into());
+    }
+
+    Ok(OrderContext {
+        now: Timestamp::from(as_of),
+        ..ctx
+    })
+}
+
+pub(crate) fn order_result_ref_tags(ref_event_id: &EventId) -> Vec<Tag> {
+    vec![
+        job_result_event_tag(ref_event_id, "reference"),
+        Tag::custom(TagKind::custom("e_ref"), [ref_event_id.to_hex()]),
+    ]
+}
+
+fn order_result_alt_tag(tags: &[Tag]) -> Tag {
+    let keys: Vec<String> = tags
+        .iter()
+        .filter_map(|t| nostr_tag_first_value(t, "key"))
+        .collect();
+    let alt = match &keys[..] {
+        [] => "Order quote".to_string(),
+        [key] => format!("Order quote for listing {key}"),
+        keys => format!("Order quote for listings {}", keys.join(", ")),
+    };
+    Tag::alt(alt)
+}
+
+pub fn order_result_tags(classified: &EventClassified) -> Vec<Tag> {
+    let listing = &classified.listing;
+    [
+        ("key", Some(&listing.key)),
+        ("lot", listing.lot.as_ref()),
+        ("year", listing.year.as_ref()),
+        ("category", Some(&listing.category)),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| {
+        let value = value.filter(|v| !v.is_empty())?;
+        Some(Tag::custom(TagKind::custom(key), [value.clone()]))
+    })
+    .collect()
+}
+
+fn order_result_total(
+    lines: &[OrderResultLine],
+    rounding: RoundingMode,
+) -> Result<OrderResultTotal, JobRequestOrderError> {
+    let totals: Vec<OrderResultTotal> = lines.iter().map(|l| l.result.grand_total()).collect();
+    let currency = totals[0].price_currency.clone();
+
+    if let Some(total) = totals
+        .iter()
+        .find(|t| !t.price_currency.eq_ignore_ascii_case(&currency))
+    {
+        return Err(OrderUnsatisfiable::CurrencyMismatch {
+            component: "order line",
+            currency: total.price_currency.clone(),
+            expected: currency,
+        }
+        .into());
+    }
+
+    let price_amount: f64 = totals.iter().map(|t| t.price_amount).sum();
+
+    Ok(OrderResultTotal {
+        price_amount: round_amount(price_amount, rounding, 2),
+        price_currency: currency,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use futures::future::BoxFuture;
+    use nostr::{
+        event::{EventBuilder, Kind},
+        filter::Filter,
+    };
+    use nostr_relay_builder::MockRelay;
+    use nostr_sdk::RelayPoolNotification;
+    use radroots_common::KIND_JOB_REQUEST;
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::events::{
+        job_request::{JobRequestInputMarker, job_result_send, parse_event},
+        job_request_builder::JobRequestBuilder,
+        listing_index,
+    };
+    use crate::handlers::job_request_quote::handle_job_request_quote;
+    use crate::models::event_classified::{
+        EventClassifiedBasis, EventClassifiedDiscount, EventClassifiedGeolocation,
+        EventClassifiedListing, EventClassifiedPrice, EventClassifiedQuantity,
+    };
+    use crate::utils::unit::MassUnit;
+    use crate::{
+        audit::AuditLog,
+        config::{ListingIndexSettings, RelayRole, RelaySettings, ServiceAreaSettings},
+        health::HealthState,
+        utils::cache::TtlCache,
+    };
+
+    fn listing(currency: &str) -> EventClassified {
+        EventClassified::new(
+            EventId::all_zeros(),
+            EventClassifiedBasis::default(),
+            EventClassifiedListing {
+                key: "coffee".into(),
+                category: "coffee".into(),
+                ..Default::default()
+            },
+            vec![EventClassifiedPrice {
+                amount: 10.0,
+                currency: currency.into(),
+                quantity_amount: 1.0,
+                quantity_unit: Unit::Mass(MassUnit::Kg),
+            }],
+            vec![EventClassifiedQuantity {
+                amount: 1.0,
+                unit: Unit::Mass(MassUnit::Kg),
+                label: "bag".into(),
+            }],
+            vec![],
+        )
+    }
+
+    fn order_json(count: u32, currency: &str) -> serde_json::Value {
+        json!({
+            "quantity": { "amount": 1.0, "unit": "kg", "label": "bag", "count": count },
+            "price": {
+                "amount": 10.0,
+                "currency": currency,
+                "quantity_amount": 1.0,
+                "quantity_unit": "kg",
+            },
+        })
+    }
+
+    fn order(count: u32, currency: &str) -> OrderSpec {
+        serde_json::from_value(order_json(count, currency)).unwrap()
+    }
+
+    fn order_line(id: &str, count: u32, currency: &str) -> OrderResultLine {
+        let ctx = OrderContext::now();
+        OrderResultLine {
+            id: id.to_string(),
+            result: listing(currency)
+                .calculate_order(&order(count, currency), &ctx)
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn order_result_total_sums_lines() {
+        let lines = vec![order_line("a", 2, "USD"), order_line("b", 3, "USD")];
+
+        let total = order_result_total(&lines, RoundingMode::HalfUp).unwrap();
+
+        assert_eq!(total.price_amount, 50.0);
+        assert_eq!(total.price_currency, "USD");
+    }
+
+    #[test]
+    fn order_result_total_rejects_mixed_currencies() {
+        let lines = vec![order_line("a", 2, "USD"), order_line("b", 3, "EUR")];
+
+        let err = order_result_total(&lines, RoundingMode::HalfUp).unwrap_err();
+
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::CurrencyMismatch { .. })
+        ));
+    }
+
+    fn job_request(params: &[(&str, &str)]) -> JobRequest {
+        let keys = Keys::generate();
+        let builder = params
+            .iter()
+            .fold(JobRequestBuilder::new(), |builder, (key, value)| {
+                builder.param(*key, *value)
+            });
+        let event = builder.build().sign_with_keys(&keys).unwrap();
+        parse_event(&event, &keys).unwrap()
+    }
+
+    fn promotion(valid_from: u64, valid_until: u64) -> EventClassifiedDiscount {
+        EventClassifiedDiscount::Subtotal {
+            threshold: 0.0,
+            currency: "USD".into(),
+            value: 10.0,
+            is_percent: true,
+            valid_from: Some(Timestamp::from(valid_from)),
+            valid_until: Some(Timestamp::from(valid_until)),
+        }
+    }
+
+    #[test]
+    fn as_of_applies_past_promotion() {
+        let now = Timestamp::now().as_u64();
+        let mut classified = listing("USD");
+        classified.discounts = vec![promotion(now - 7_200, now - 3_600)];
+        let settings = Settings::default();
+
+        let as_of = (now - 5_400).to_string();
+        let ctx = order_context(&job_request(&[("as_of", &as_of)]), &settings).unwrap();
+        let result = classified.calculate_order(&order(2, "USD"), &ctx).unwrap();
+        assert_eq!(result.order.discounts.len(), 1);
+        assert_eq!(result.order.total.price_amount, 18.0);
+
+        let ctx = order_context(&job_request(&[]), &settings).unwrap();
+        let result = classified.calculate_order(&order(2, "USD"), &ctx).unwrap();
+        assert!(result.order.discounts.is_empty());
+        assert_eq!(result.order.total.price_amount, 20.0);
+    }
+
+    #[test]
+    fn as_of_in_future_rejected() {
+        let as_of = (Timestamp::now().as_u64() + 3_600).to_string();
+
+        let err = order_context(&job_request(&[("as_of", &as_of)]), &Settings::default());
+
+        assert!(matches!(err, Err(JobRequestOrderError::InvalidParam(_))));
+    }
+
+    fn fetch_settings() -> Settings {
+        Settings {
+            fetch_timeout_ms: 500,
+            ..Settings::default()
+        }
+    }
+
+    async fn mock_client(relay: &MockRelay) -> Client {
+        let client = Client::builder().signer(Keys::generate()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        client
+    }
+
+    #[tokio::test]
+    async fn classified_fetch_malformed_id() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let settings = fetch_settings();
+        let state = JobRequestState::from(&settings);
+
+        let err = classified_fetch(&client, &state, &settings, "not-an-id", None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, JobRequestOrderError::ParseReference(_)));
+    }
+
+    #[tokio::test]
+    async fn classified_fetch_missing_id() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let settings = fetch_settings();
+        let state = JobRequestState::from(&settings);
+        let missing = EventId::all_zeros().to_hex();
+
+        let err = classified_fetch(&client, &state, &settings, &missing, None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, JobRequestOrderError::MissingReference(_)));
+    }
+
+    fn classified_tags() -> Vec<Tag> {
+        vec![
+            Tag::custom(TagKind::custom("price"), ["12", "USD", "1", "kg"]),
+            Tag::custom(TagKind::custom("quantity"), ["500", "g", "bag"]),
+        ]
+    }
+
+    async fn classified_fetch_cached(
+        kind: Kind,
+        tags: Vec<Tag>,
+    ) -> Result<EventClassified, JobRequestOrderError> {
+        let event = EventBuilder::new(kind, "")
+            .tags(tags)
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        classified_fetch_as(event.id, event).await
+    }
+
+    async fn classified_fetch_as(
+        id: EventId,
+        event: Event,
+    ) -> Result<EventClassified, JobRequestOrderError> {
+        let settings = fetch_settings();
+        let state = JobRequestState::from(&settings);
+        state.event_cache.insert(id, event);
+
+        classified_fetch(&Client::default(), &state, &settings, &id.to_hex(), None).await
+    }
+
+    #[tokio::test]
+    async fn classified_fetch_accepts_listing() {
+        let classified = classified_fetch_cached(Kind::ClassifiedListing, classified_tags())
+            .await
+            .unwrap();
+
+        assert!(classified.is_listing());
+    }
+
+    #[tokio::test]
+    async fn classified_fetch_rejects_non_listing() {
+        let err = classified_fetch_cached(Kind::TextNote, classified_tags())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, JobRequestOrderError::MissingRequested(_)));
+
+        let err = classified_fetch_cached(Kind::ClassifiedListing, vec![])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, JobRequestOrderError::MissingRequested(_)));
+    }
+
+    #[tokio::test]
+    async fn classified_fetch_rejects_unverified_event() {
+        let event = EventBuilder::new(Kind::ClassifiedListing, "")
+            .tags(classified_tags())
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        let mut tampered = event.clone();
+        tampered.content = "discounted".into();
+        let err = classified_fetch_as(event.id, tampered).await.unwrap_err();
+        assert!(matches!(err, JobRequestOrderError::ParseReference(_)));
+
+        let err = classified_fetch_as(EventId::all_zeros(), event)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, JobRequestOrderError::ParseReference(_)));
+    }
+
+    fn index_settings() -> Settings {
+        Settings {
+            listing_index: Some(ListingIndexSettings::default()),
+            ..fetch_settings()
+        }
+    }
+
+    #[tokio::test]
+    async fn classified_fetch_serves_listing_index_hits() {
+        let settings = index_settings();
+        let state = JobRequestState::from(&settings);
+        let listing = listing_event(listing_tags());
+        let indexed = classified_verify(&listing, &listing.id, &settings).unwrap();
+        state.listings.insert(&listing, indexed);
+
+        // A client without relays fails every fetch, so only the index can answer.
+        let classified = classified_fetch(
+            &Client::default(),
+            &state,
+            &settings,
+            &listing.id.to_hex(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(classified.id, listing.id);
+        assert_eq!(classified.listing.key, "coffee");
+    }
+
+    #[tokio::test]
+    async fn indexed_listing_ordered_without_fetch() {
+        let index_relay = MockRelay::run().await.unwrap();
+        let listing = listing_event(listing_tags());
+        mock_client(&index_relay)
+            .await
+            .send_event(&listing)
+            .await
+            .unwrap();
+
+        let settings = index_settings();
+        let state = JobRequestState::from(&settings);
+        let relays = vec![RelaySettings {
+            url: index_relay.url().to_string(),
+            role: RelayRole::Both,
+            auth: false,
+        }];
+        let shutdown = CancellationToken::new();
+        let subscriber = tokio::spawn(listing_index::subscriber(
+            Keys::generate(),
+            relays,
+            settings.clone(),
+            state.listings.clone(),
+            shutdown.clone(),
+        ));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.listings.get(&listing.id).is_none() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // The order is answered over a relay that never saw the listing.
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let result_id = handle_order_request(
+            &client,
+            state.clone(),
+            settings,
+            reference_order_request(&listing),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let result = published_result(&client, result_id).await;
+        let content: serde_json::Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(content["result"]["total"]["price_amount"], 20.0);
+        // Every fetched event lands in the event cache, so an empty cache means no fetch ran.
+        assert!(state.event_cache.get(&listing.id).is_none());
+
+        shutdown.cancel();
+        subscriber.await.unwrap().unwrap();
+    }
+
+    fn service_settings(require_location: bool) -> Settings {
+        Settings {
+            service_area: Some(ServiceAreaSettings {
+                lat: 48.8566,
+                lng: 2.3522,
+                radius_km: 50.0,
+                require_location,
+                min_geohash_precision: None,
+            }),
+            ..Settings::default()
+        }
+    }
+
+    fn located_listing(lat: f64, lng: f64) -> EventClassified {
+        let mut classified = listing("USD");
+        classified.geolocation = Some(EventClassifiedGeolocation {
+            geohash: None,
+            lat,
+            lng,
+        });
+        classified
+    }
+
+    #[test]
+    fn service_area_accepts_nearby_listing() {
+        let settings = service_settings(false);
+
+        assert!(check_service_area(&located_listing(48.8049, 2.1204), &settings).is_ok());
+    }
+
+    #[test]
+    fn service_area_rejects_distant_listing() {
+        let settings = service_settings(false);
+
+        let err = check_service_area(&located_listing(51.5074, -0.1278), &settings).unwrap_err();
+
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::OutsideServiceArea)
+        ));
+    }
+
+    #[test]
+    fn service_area_requires_location_when_configured() {
+        assert!(check_service_area(&listing("USD"), &service_settings(false)).is_ok());
+
+        let err = check_service_area(&listing("USD"), &service_settings(true)).unwrap_err();
+
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::MissingLocation)
+        ));
+    }
+
+    fn input(input_type: JobRequestInputType, data: String) -> JobRequestInput {
+        JobRequestInput {
+            data,
+            input_type,
+            relay: None,
+            marker: None,
+        }
+    }
+
+    async fn resolve(input: JobRequestInput) -> Result<JobRequestOrderData, JobRequestError> {
+        resolve_with(fetch_settings(), input).await
+    }
+
+    async fn resolve_with(
+        settings: Settings,
+        input: JobRequestInput,
+    ) -> Result<JobRequestOrderData, JobRequestError> {
+        let cache = EventCache::from(&settings.event_cache);
+        order_data_resolve(&Client::default(), &cache, &settings, &input).await
+    }
+
+    #[tokio::test]
+    async fn order_data_text_input_inline_json() {
+        let lines = json!([{ "id": "coffee", "order": order_json(2, "USD") }]).to_string();
+
+        let data = resolve(input(JobRequestInputType::Text, lines))
+            .await
+            .unwrap();
+
+        assert!(matches!(data, JobRequestOrderData::Multiple(lines) if lines.len() == 1));
+    }
+
+    #[tokio::test]
+    async fn order_data_text_input_not_json() {
+        let err = resolve(input(JobRequestInputType::Text, "two bags".into()))
+            .await
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            err,
+            JobRequestError::JobRequestOrder(JobRequestOrderError::ParseReference(_))
+        ));
+    }
+
+    fn parse_error_message(data: serde_json::Value) -> String {
+        match JobRequestOrderData::parse(&data.to_string()) {
+            Err(JobRequestOrderError::ParseReference(message)) => message,
+            Err(other) => panic!("expected a parse error, got {other:?}"),
+            Ok(_) => panic!("expected a parse error for {data}"),
+        }
+    }
+
+    #[test]
+    fn order_data_shape_chosen_before_parsing() {
+        let mut order = order_json(1, "USD");
+        order["price"].as_object_mut().unwrap().remove("currency");
+        let message = parse_error_message(json!([{ "id": "coffee", "order": order }]));
+        assert!(message.contains("missing field `currency`"), "{message}");
+
+        let message = parse_error_message(json!({ "listing": {}, "order": order_json(1, "USD") }));
+        assert!(message.contains("missing field `id`"), "{message}");
+
+        let mut payload = order_json(1, "USD");
+        payload["quantity"].as_object_mut().unwrap().remove("unit");
+        let message =
+            parse_error_message(json!({ "event": { "id": "coffee" }, "payload": payload }));
+        assert!(message.contains("missing field `unit`"), "{message}");
+    }
+
+    fn invalid_data_message(data: serde_json::Value) -> String {
+        match JobRequestOrderData::parse(&data.to_string()) {
+            Err(JobRequestOrderError::InvalidOrder(message)) => message,
+            Err(other) => panic!("expected an invalid order, got {other:?}"),
+            Ok(_) => panic!("expected an invalid order for {data}"),
+        }
+    }
+
+    fn single_data(patch: impl FnOnce(&mut serde_json::Value)) -> serde_json::Value {
+        let mut data = json!({ "event": { "id": "coffee" }, "payload": order_json(1, "USD") });
+        patch(&mut data);
+        data
+    }
+
+    #[test]
+    fn order_field_errors_name_full_path() {
+        let cases: Vec<(serde_json::Value, &str)> = vec![
+            (
+                single_data(|d| d["payload"]["quantity"] = json!({ "unit": "kg", "count": 1 })),
+                "payload.quantity.amount is missing",
+            ),
+            (
+                single_data(|d| d["payload"]["quantity"]["amount"] = json!("1")),
+                "payload.quantity.amount must be a number, got \"1\"",
+            ),
+            (
+                single_data(|d| d["payload"]["price"]["amount"] = json!(null)),
+                "payload.price.amount is missing",
+            ),
+            (
+                single_data(|d| d["payload"]["price"]["amount"] = json!("ten")),
+                "payload.price.amount must be a number, got \"ten\"",
+            ),
+            (
+                single_data(|d| d["event"] = json!({})),
+                "event.id is missing",
+            ),
+            (
+                single_data(|d| d["event"]["id"] = json!(5)),
+                "event.id must be a string, got 5",
+            ),
+            (
+                json!({ "payload": order_json(1, "USD") }),
+                "event is missing",
+            ),
+            (
+                json!([{ "order": order_json(1, "USD") }]),
+                "order line 0: id is missing",
+            ),
+            (
+                json!([{ "id": "coffee", "order": { "price": { "amount": 10.0 } } }]),
+                "order line 0: order.quantity is missing",
+            ),
+            (
+                json!({ "listing": {}, "order": { "quantity": { "amount": 1.0 } } }),
+                "order.price is missing",
+            ),
+        ];
+
+        for (data, expected) in cases {
+            assert_eq!(invalid_data_message(data), expected);
+        }
+
+        let err =
+            JobRequestOrderData::parse(&single_data(|d| d["event"]["id"] = json!(5)).to_string())
+                .err()
+                .unwrap();
+        assert_eq!(err.error_code(), "INVALID_ORDER");
+        assert_eq!(
+            err.to_string(),
+            "Invalid order: event.id must be a string, got 5"
+        );
+    }
+
+    async fn serve_order(body: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/order.json", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        url
+    }
+
+    fn url_settings(url_inputs: UrlInputs) -> Settings {
+        Settings {
+            url_inputs,
+            ..fetch_settings()
+        }
+    }
+
+    #[tokio::test]
+    async fn order_data_url_input_fetched() {
+        let document = json!([{ "id": "coffee", "order": order_json(2, "USD") }]).to_string();
+        let url = serve_order(document).await;
+
+        let data = resolve_with(
+            url_settings(UrlInputs::Any),
+            input(JobRequestInputType::Url, url),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(data, JobRequestOrderData::Multiple(lines) if lines[0].id == "coffee"));
+    }
+
+    #[tokio::test]
+    async fn order_data_url_input_guarded() {
+        let url = serve_order("[]".into()).await;
+
+        let err = resolve(input(JobRequestInputType::Url, url.clone()))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, JobRequestError::InvalidInputType(_)));
+
+        let err = resolve_with(
+            url_settings(UrlInputs::Public),
+            input(JobRequestInputType::Url, url),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err,
+            JobRequestError::JobRequestOrder(JobRequestOrderError::FetchInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn order_data_url_input_unsupported_scheme() {
+        let err = resolve(input(
+            JobRequestInputType::Url,
+            "ftp://orders.test/1".into(),
+        ))
+        .await
+        .err()
+        .unwrap();
+
+        assert!(matches!(err, JobRequestError::InvalidInputType(_)));
+    }
+
+    #[tokio::test]
+    async fn order_data_job_input_invalid_id() {
+        let err = resolve(input(JobRequestInputType::Job, "not-an-id".into()))
+            .await
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, JobRequestError::InvalidJobInput(_)));
+    }
+
+    #[tokio::test]
+    async fn unsupported_output_rejected_before_processing() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let data = json!({ "event": { "id": EventId::all_zeros().to_hex() }, "payload": order_json(1, "USD") });
+        let requester = Keys::generate();
+        let event = JobRequestBuilder::new()
+            .input(
+                data.to_string(),
+                JobRequestInputType::Text,
+                None,
+                Some(JobRequestInputMarker::Order),
+            )
+            .output(OutputFormat::Csv)
+            .build()
+            .sign_with_keys(&requester)
+            .unwrap();
+        let job_req = parse_event(&event, &requester).unwrap();
+        let job_req_input = job_req.inputs[0].clone();
+        let settings = fetch_settings();
+
+        let err = handle_job_request_order(
+            event,
+            Keys::generate(),
+            client.clone(),
+            JobRequestState::from(&settings),
+            settings,
+            job_req,
+            job_req_input,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert!(matches!(err, JobRequestError::UnsupportedOutput(_)));
+        let feedback = client
+            .fetch_events(
+                Filter::new().kind(Kind::JobFeedback),
+                Duration::from_secs(2),
+            )
+            .await
+            .unwrap();
+        assert!(feedback.is_empty());
+    }
+
+    #[tokio::test]
+    async fn quote_result_chains_into_order() {
+        let settings = Settings {
+            dry_run: true,
+            ..fetch_settings()
+        };
+        let state = JobRequestState::from(&settings);
+        let listing = listing_event(listing_tags());
+        state.event_cache.insert(listing.id, listing.clone());
+        let upstream = Keys::generate();
+        let cache_result = |result: Event| {
+            state.event_cache.insert(result.id, result.clone());
+            result
+        };
+        let requester = Keys::generate();
+        let request = |job: &Event, marker: JobRequestInputMarker| {
+            let event = JobRequestBuilder::new()
+                .input(
+                    job.id.to_hex(),
+                    JobRequestInputType::Job,
+                    None,
+                    Some(marker),
+                )
+                .build()
+                .sign_with_keys(&requester)
+                .unwrap();
+            let job_req = parse_event(&event, &requester).unwrap();
+            (event, job_req)
+        };
+
+        // Another DVM's result names the listing to quote.
+        let found = cache_result(
+            EventBuilder::new(Kind::Custom(6050), listing.id.to_hex())
+                .sign_with_keys(&upstream)
+                .unwrap(),
+        );
+        let (event, job_req) = request(&found, JobRequestInputMarker::Quote);
+        let job_req_input = job_req.inputs[0].clone();
+        let quoted = handle_job_request_quote(
+            event,
+            Keys::generate(),
+            Client::default(),
+            state.clone(),
+            settings.clone(),
+            job_req,
+            job_req_input,
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .event
+        .sign_with_keys(&Keys::generate())
+        .unwrap();
+        let quote: serde_json::Value = serde_json::from_str(&quoted.content).unwrap();
+        assert_eq!(quote["id"], listing.id.to_hex());
+
+        // A cart DVM turns the quote's first tier and packaging into an order.
+        let (tier, packaging) = (&quote["prices"][0], &quote["quantities"][0]);
+        let cart = json!({
+            "event": { "id": quote["id"] },
+            "payload": {
+                "quantity": {
+                    "amount": packaging["amount"],
+                    "unit": packaging["unit"],
+                    "label": packaging["label"],
+                    "count": 3,
+                },
+                "price": {
+                    "amount": tier["amount"],
+                    "currency": tier["currency"],
+                    "quantity_amount": tier["quantity_amount"],
+                    "quantity_unit": tier["quantity_unit"],
+                },
+            },
+        });
+        let carted = cache_result(
+            EventBuilder::new(Kind::Custom(6050), cart.to_string())
+                .sign_with_keys(&upstream)
+                .unwrap(),
+        );
+        let (event, job_req) = request(&carted, JobRequestInputMarker::Order);
+        let job_req_input = job_req.inputs[0].clone();
+        let ordered = handle_job_request_order(
+            event,
+            Keys::generate(),
+            Client::default(),
+            state,
+            settings,
+            job_req,
+            job_req_input,
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .event
+        .sign_with_keys(&Keys::generate())
+        .unwrap();
+
+        let content: serde_json::Value = serde_json::from_str(&ordered.content).unwrap();
+        assert_eq!(content["result"]["total"]["price_amount"], 30.0);
+        assert!(ordered.tags.event_ids().any(|id| id == &listing.id));
+    }
+
+    fn tag_values(tags: &[Tag]) -> Vec<Vec<String>> {
+        tags.iter().map(|t| t.as_slice().to_vec()).collect()
+    }
+
+    #[test]
+    fn order_result_tags_for_full_listing() {
+        let mut classified = listing("USD");
+        classified.listing.lot = Some("A-12".into());
+        classified.listing.year = Some("2024".into());
+
+        let tags = tag_values(&order_result_tags(&classified));
+
+        assert_eq!(
+            tags,
+            vec![
+                vec!["key".to_string(), "coffee".to_string()],
+                vec!["lot".to_string(), "A-12".to_string()],
+                vec!["year".to_string(), "2024".to_string()],
+                vec!["category".to_string(), "coffee".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn order_result_alt_names_listing_keys() {
+        let mut tea = listing("USD");
+        tea.listing.key = "tea".into();
+        let alt = |tags: &[Tag]| tag_values(&[order_result_alt_tag(tags)]).remove(0);
+
+        assert_eq!(alt(&[]), ["alt", "Order quote"]);
+        assert_eq!(
+            alt(&order_result_tags(&listing("USD"))),
+            ["alt", "Order quote for listing coffee"]
+        );
+        let tags = [order_result_tags(&listing("USD")), order_result_tags(&tea)].concat();
+        assert_eq!(alt(&tags), ["alt", "Order quote for listings coffee, tea"]);
+    }
+
+    #[test]
+    fn order_result_ref_tags_keep_e_ref() {
+        let id = EventId::all_zeros();
+
+        let tags = tag_values(&order_result_ref_tags(&id));
+
+        assert_eq!(
+            tags,
+            vec![
+                vec![
+                    "e".to_string(),
+                    id.to_hex(),
+                    String::new(),
+                    "reference".to_string()
+                ],
+                vec!["e_ref".to_string(), id.to_hex()],
+            ]
+        );
+    }
+
+    #[test]
+    fn order_result_marks_request_and_reference() {
+        let keys = Keys::generate();
+        let job_request = EventBuilder::new(Kind::JobRequest(5300), "")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let ref_id = EventId::all_zeros();
+
+        let result = nostr_event_job_result(
+            &job_request,
+            "{}",
+            0,
+            Payment::None,
+            Some(order_result_ref_tags(&ref_id)),
+        )
+        .unwrap()
+        .sign_with_keys(&keys)
+        .unwrap();
+
+        let marked: Vec<(&str, &str)> = result
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .filter(|t| t[0] == "e")
+            .map(|t| (t[1].as_str(), t[3].as_str()))
+            .collect();
+        let (request_hex, ref_hex) = (job_request.id.to_hex(), ref_id.to_hex());
+        assert_eq!(
+            marked,
+            [
+                (request_hex.as_str(), "request"),
+                (ref_hex.as_str(), "reference"),
+            ]
+        );
+    }
+
+    fn listing_event(tags: Vec<Tag>) -> Event {
+        EventBuilder::new(Kind::ClassifiedListing, "")
+            .tags(tags)
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+    }
+
+    /// The signed counterpart of `listing("USD")`.
+    fn listing_tags() -> Vec<Tag> {
+        vec![
+            Tag::custom(TagKind::custom("key"), ["coffee"]),
+            Tag::custom(TagKind::custom("category"), ["coffee"]),
+            Tag::custom(TagKind::custom("price"), ["10", "USD", "1", "kg"]),
+            Tag::custom(TagKind::custom("quantity"), ["1", "kg", "bag"]),
+        ]
+    }
+
+    fn order_request(data: serde_json::Value) -> (Event, JobRequest) {
+        let requester = Keys::generate();
+        let event = JobRequestBuilder::new()
+            .input(
+                data.to_string(),
+                JobRequestInputType::Text,
+                None,
+                Some(JobRequestInputMarker::Order),
+            )
+            .build()
+            .sign_with_keys(&requester)
+            .unwrap();
+        let job_req = parse_event(&event, &requester).unwrap();
+        (event, job_req)
+    }
+
+    fn inline_order_request(listing: &Event) -> (Event, JobRequest) {
+        order_request(json!({
+            "listing": listing,
+            "order": order_json(2, "USD"),
+        }))
+    }
+
+    fn reference_order_request(listing: &Event) -> (Event, JobRequest) {
+        order_request(json!({
+            "event": { "id": listing.id.to_hex() },
+            "payload": order_json(2, "USD"),
+        }))
+    }
+
+    async fn handle_order(
+        client: &Client,
+        settings: Settings,
+    ) -> Result<Option<EventId>, JobRequestError> {
+        let state = JobRequestState::from(&settings);
+        handle_listing_order(client, state, settings, &listing_event(listing_tags())).await
+    }
+
+    /// Publishes the listing and orders it by reference, so it is fetched like any other.
+    async fn handle_listing_order(
+        client: &Client,
+        state: JobRequestState,
+        settings: Settings,
+        listing: &Event,
+    ) -> Result<Option<EventId>, JobRequestError> {
+        client.send_event(listing).await.unwrap();
+        handle_order_request(client, state, settings, reference_order_request(listing)).await
+    }
+
+    async fn handle_inline_listing_order(
+        client: &Client,
+        state: JobRequestState,
+        settings: Settings,
+        listing: &Event,
+    ) -> Result<Option<EventId>, JobRequestError> {
+        handle_order_request(client, state, settings, inline_order_request(listing)).await
+    }
+
+    async fn handle_order_request(
+        client: &Client,
+        state: JobRequestState,
+        settings: Settings,
+        (event, job_req): (Event, JobRequest),
+    ) -> Result<Option<EventId>, JobRequestError> {
+        let audit = state.audit.clone();
+        let dry_run = settings.dry_run;
+        let job_req_input = job_req.inputs[0].clone();
+        let pending = handle_job_request_order(
+            event,
+            Keys::generate(),
+            client.clone(),
+            state,
+            settings,
+            job_req.clone(),
+            job_req_input,
+        )
+        .await?;
+        match pending {
+            Some(pending) => job_result_send(client.clone(), pending, &job_req, &audit, dry_run)
+                .await
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    #[tokio::test]
+    async fn order_data_text_input_inline_listing() {
+        let data = json!({
+            "listing": listing_event(listing_tags()),
+            "order": order_json(2, "USD"),
+        })
+        .to_string();
+
+        let data = resolve(input(JobRequestInputType::Text, data))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            data,
+            JobRequestOrderData::Inline(JobRequestOrderInline {
+                listing: JobRequestOrderInlineListing::Signed(event),
+                ..
+            }) if event.kind == Kind::ClassifiedListing
+        ));
+
+        let data = json!({ "listing": listing("USD"), "order": order_json(2, "USD") }).to_string();
+        let data = resolve(input(JobRequestInputType::Text, data))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            data,
+            JobRequestOrderData::Inline(JobRequestOrderInline {
+                listing: JobRequestOrderInlineListing::Classified(classified),
+                ..
+            }) if classified.is_listing()
+        ));
+    }
+
+    async fn published_result(client: &Client, result_id: EventId) -> Event {
+        client
+            .fetch_events(Filter::new().id(result_id), Duration::from_secs(2))
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn inline_signed_listing_priced_and_referenced() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let settings = fetch_settings();
+        let state = JobRequestState::from(&settings);
+        let listing = listing_event(listing_tags());
+
+        // The listing never reaches the relay, so it can only have been read from the input.
+        let result_id = handle_inline_listing_order(&client, state, settings, &listing)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let result = published_result(&client, result_id).await;
+        let content: serde_json::Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(content["result"]["total"]["price_amount"], 20.0);
+        let tags = tag_values(&result.tags.to_vec());
+        assert!(
+            tags.iter()
+                .any(|t| t[0] == "e_ref" && t[1] == listing.id.to_hex())
+        );
+    }
+
+    #[tokio::test]
+    async fn inline_unsigned_listing_rejected_by_default() {
+        let settings = Settings {
+            dry_run: true,
+            ..fetch_settings()
+        };
+        let state = JobRequestState::from(&settings);
+        let request =
+            order_request(json!({ "listing": listing("USD"), "order": order_json(2, "USD") }));
+
+        let err = handle_order_request(&Client::default(), state, settings, request)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            JobRequestError::JobRequestOrder(JobRequestOrderError::ParseReference(message))
+                if message.contains("unsigned")
+        ));
+    }
+
+    #[tokio::test]
+    async fn inline_classified_priced_without_reference() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let settings = Settings {
+            unsigned_inline_listings: true,
+            ..fetch_settings()
+        };
+        let state = JobRequestState::from(&settings);
+        let request =
+            order_request(json!({ "listing": listing("USD"), "order": order_json(2, "USD") }));
+
+        let result_id = handle_order_request(&client, state, settings, request)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let result = published_result(&client, result_id).await;
+        let content: serde_json::Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(content["result"]["total"]["price_amount"], 20.0);
+        let tags = tag_values(&result.tags.to_vec());
+        assert!(
+            !tags
+                .iter()
+                .any(|t| t[0] == "e" && t.get(3).is_some_and(|m| m == "reference"))
+        );
+        assert!(tags.iter().any(|t| t[0] == "key" && t[1] == "coffee"));
+        assert!(!tags.iter().any(|t| t[0] == "e_ref"));
+    }
+
+    #[tokio::test]
+    async fn inline_non_listing_rejected() {
+        let settings = Settings {
+            dry_run: true,
+            ..fetch_settings()
+        };
+        let mut tags = listing_tags();
+        tags.pop();
+        let unlisted = listing_event(tags);
+
+        let state = JobRequestState::from(&settings);
+        let err = handle_inline_listing_order(&Client::default(), state, settings, &unlisted)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            JobRequestError::JobRequestOrder(JobRequestOrderError::MissingRequested(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn inline_tampered_listing_rejected() {
+        let settings = Settings {
+            dry_run: true,
+            ..fetch_settings()
+        };
+        let mut tampered = listing_event(listing_tags());
+        tampered.content = "discounted".into();
+
+        let state = JobRequestState::from(&settings);
+        let err = handle_inline_listing_order(&Client::default(), state, settings, &tampered)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            JobRequestError::JobRequestOrder(JobRequestOrderError::ParseReference(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dry_run_computes_result_without_publishing() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let settings = Settings {
+            dry_run: true,
+            ..fetch_settings()
+        };
+
+        let result_id = handle_order(&client, settings).await.unwrap();
+
+        assert!(result_id.is_some());
+        let published = client
+            .fetch_events(Filter::new(), Duration::from_secs(2))
+            .await
+            .unwrap();
+        // Only the referenced listing reached the relay.
+        assert!(
+            published
+                .iter()
+                .all(|event| event.kind == Kind::ClassifiedListing)
+        );
+    }
+
+    #[tokio::test]
+    async fn order_discounted_to_zero_priced_at_zero() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let settings = Settings {
+            msat_rates: HashMap::from([("USD".to_string(), 1_500_000.0)]),
+            ..fetch_settings()
+        };
+        let mut tags = listing_tags();
+        tags.push(Tag::custom(
+            TagKind::custom("price-discount-subtotal"),
+            ["0", "USD", "100", "%"],
+        ));
+
+        let state = JobRequestState::from(&settings);
+        let result_id = handle_listing_order(&client, state, settings, &listing_event(tags))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let result = client
+            .fetch_events(Filter::new().id(result_id), Duration::from_secs(2))
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        let content: serde_json::Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(content["receipt"]["priced"], true);
+        assert_eq!(content["receipt"]["amount_msat"], 0);
+        assert_eq!(content["receipt"]["status"], "paid");
+    }
+
+    struct FixedRate(f64);
+
+    impl FiatRateProvider for FixedRate {
+        fn rate<'a>(&'a self, _currency: &'a str) -> BoxFuture<'a, Result<f64, FiatRateError>> {
+            Box::pin(async move { Ok(self.0) })
+        }
+    }
+
+    #[tokio::test]
+    async fn order_priced_with_injected_rate_provider() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let settings = fetch_settings();
+        let state = JobRequestState {
+            rates: Some(Arc::new(FixedRate(1_000.0))),
+            ..JobRequestState::from(&settings)
+        };
+
+        let listing = listing_event(listing_tags());
+        let result_id = handle_inline_listing_order(&client, state, settings, &listing)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let result = client
+            .fetch_events(Filter::new().id(result_id), Duration::from_secs(2))
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        let content: serde_json::Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(content["receipt"]["priced"], true);
+        assert_eq!(content["receipt"]["amount_msat"], 20_000);
+    }
+
+    #[tokio::test]
+    async fn processing_feedback_published_before_result() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let observer = mock_client(&relay).await;
+        observer.subscribe(Filter::new(), None).await.unwrap();
+        let mut notifications = observer.notifications();
+
+        let result_id = handle_order(&client, fetch_settings())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let notification = tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match notification {
+                RelayPoolNotification::Event { event, .. }
+                    if event.kind != Kind::ClassifiedListing =>
+                {
+                    received.push(*event)
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(received[0].kind, Kind::JobFeedback);
+        let status = received[0]
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .find(|t| t[0] == "status")
+            .map(|t| t[1].clone());
+        assert_eq!(status.as_deref(), Some("processing"));
+        assert_eq!(received[1].id, result_id);
+    }
+
+    #[tokio::test]
+    async fn processed_order_writes_audit_records() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        // Looks back far enough to serve a request sent before the subscription is open.
+        let settings = Settings {
+            backfill_secs: 60,
+            ..fetch_settings()
+        };
+        let state = JobRequestState {
+            audit: AuditLog::open(path.to_str()).unwrap(),
+            ..JobRequestState::from(&settings)
+        };
+        let audit = state.audit.clone();
+        let relays = vec![RelaySettings {
+            url: relay.url().to_string(),
+            role: RelayRole::Both,
+            auth: false,
+        }];
+        let shutdown = CancellationToken::new();
+        let subscriber = tokio::spawn(crate::events::job_request::subscriber(
+            Keys::generate(),
+            relays,
+            settings.clone(),
+            HealthState::default(),
+            shutdown.clone(),
+            state,
+            TtlCache::from(&settings.dedup),
+        ));
+        let listing = listing_event(listing_tags());
+        client.send_event(&listing).await.unwrap();
+        let (request, _) = reference_order_request(&listing);
+        client.send_event(&request).await.unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let results = client
+                    .fetch_events(
+                        Filter::new()
+                            .kind(Kind::Custom(KIND_JOB_REQUEST + 1000))
+                            .event(request.id),
+                        Duration::from_millis(500),
+                    )
+                    .await
+                    .unwrap();
+                if let Some(result) = results.first() {
+                    break result.clone();
+                }
+            }
+        })
+        .await
+        .unwrap();
+        shutdown.cancel();
+        subscriber.await.unwrap().unwrap();
+        audit.flush();
+
+        let records: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["direction"], "in");
+        assert_eq!(records[0]["kind"], KIND_JOB_REQUEST);
+        assert_eq!(records[0]["id"], request.id.to_hex());
+        assert!(records[1..].iter().all(|r| r["direction"] == "out"));
+        assert_eq!(records[1]["kind"], Kind::JobFeedback.as_u16());
+        assert_eq!(records[2]["kind"], KIND_JOB_REQUEST + 1000);
+        assert_eq!(records[2]["id"], result.id.to_hex());
+    }
+
+    async fn webhook_receive(listener: TcpListener) -> serde_json::Value {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        let body = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8(request.clone()).unwrap();
+            let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                assert!(n > 0, "webhook request ended before its body");
+                continue;
+            };
+            let length = head
+                .lines()
+                .find_map(|l| {
+                    l.to_lowercase()
+                        .strip_prefix("content-length:")
+                        .map(|v| v.trim().parse::<usize>().unwrap())
+                })
+                .unwrap_or(0);
+            if n == 0 || body.len() >= length {
+                break body.to_string();
+            }
+        };
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        stream.write_all(response.as_bytes()).await.unwrap();
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn processed_order_posts_result_webhook() {
+        let relay = MockRelay::run().await.unwrap();
+        let client = mock_client(&relay).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = Settings {
+            result_webhook: Some(format!("http://{}/orders", listener.local_addr().unwrap())),
+            ..fetch_settings()
+        };
+        let webhook = tokio::spawn(webhook_receive(listener));
+        let state = JobRequestState::from(&settings);
+        let audit = state.audit.clone();
+        let (event, job_req) = inline_order_request(&listing_event(listing_tags()));
+        let job_req_input = job_req.inputs[0].clone();
+
+        let pending = handle_job_request_order(
+            event,
+            Keys::generate(),
+            client.clone(),
+            state,
+            settings,
+            job_req.clone(),
+            job_req_input,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let result_id = job_result_send(client, pending, &job_req, &audit, false)
+            .await
+            .unwrap();
+
+        let summary = tokio::time::timeout(Duration::from_secs(5), webhook)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary["request_id"], job_req.id.to_hex());
+        assert_eq!(summary["result_id"], result_id.to_hex());
+        assert_eq!(summary["total"], 20.0);
+        assert_eq!(summary["currency"], "USD");
+        assert_eq!(summary["requester"], job_req.requester.to_hex());
+    }
+
+    #[tokio::test]
+    async fn encrypted_order_result_carries_no_listing_tags() {
+        let settings = Settings {
+            dry_run: true,
+            encrypt_results: true,
+            ..fetch_settings()
+        };
+        let keys = Keys::generate();
+        let (event, job_req) = inline_order_request(&listing_event(listing_tags()));
+        let job_req_input = job_req.inputs[0].clone();
+
+        let pending = handle_job_request_order(
+            event,
+            keys.clone(),
+            Client::default(),
+            JobRequestState::from(&settings),
+            settings,
+            job_req,
+            job_req_input,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let result = pending.event.sign_with_keys(&keys).unwrap();
+
+        assert!(nostr_event_is_encrypted(&result));
+        for tag in tag_values(result.tags.as_slice()) {
+            assert!(
+                !["key", "lot", "year", "category", "alt", "e_ref"].contains(&tag[0].as_str()),
+                "{tag:?}"
+            );
+            assert!(tag[0] != "e" || tag.get(3).is_none_or(|m| m != "reference"));
+        }
+    }
+
+    fn invalid_order_message(id: &str, patch: impl FnOnce(&mut serde_json::Value)) -> String {
+        let mut value = order_json(1, "USD");
+        patch(&mut value);
+        let order: OrderSpec = serde_json::from_value(value).unwrap();
+        match validate_order_line(id, &order) {
+            Err(JobRequestOrderError::InvalidOrder(message)) => message,
+            other => panic!("expected invalid order, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn validate_order_line_accepts_valid_order() {
+        assert!(validate_order_line("coffee", &order(1, "USD")).is_ok());
+    }
+
+    #[test]
+    fn validate_order_line_accepts_fractional_count() {
+        let mut value = order_json(0, "USD");
+        value["fractional_count"] = json!(0.5);
+        let order: OrderSpec = serde_json::from_value(value).unwrap();
+
+        assert!(validate_order_line("coffee", &order).is_ok());
+    }
+
+    #[test]
+    fn validate_order_line_failures() {
+        let cases: Vec<(&str, Box<dyn FnOnce(&mut serde_json::Value)>, &str)> = vec![
+            (" ", Box::new(|_| {}), "event id is empty"),
+            (
+                "coffee",
+                Box::new(|v| v["quantity"]["amount"] = json!(0.0)),
+                "quantity amount 0 must be positive",
+            ),
+            (
+                "coffee",
+                Box::new(|v| v["quantity"]["count"] = json!(0)),
+                "quantity count must be positive",
+            ),
+            (
+                "coffee",
+                Box::new(|v| v["fractional_count"] = json!(-1.5)),
+                "quantity fractional count -1.5 must be positive",
+            ),
+            (
+                "coffee",
+                Box::new(|v| v["quantity"]["unit"] = json!("stone")),
+                "unknown quantity unit stone",
+            ),
+            (
+                "coffee",
+                Box::new(|v| v["price"]["amount"] = json!(-1.0)),
+                "price amount -1 must be positive",
+            ),
+            (
+                "coffee",
+                Box::new(|v| v["price"]["quantity_amount"] = json!(0.0)),
+                "price quantity amount 0 must be positive",
+            ),
+            (
+                "coffee",
+                Box::new(|v| v["price"]["quantity_unit"] = json!("stone")),
+                "unknown price quantity unit stone",
+            ),
+            (
+                "coffee",
+                Box::new(|v| v["price"]["currency"] = json!("US$")),
+                "unknown currency US$",
+            ),
+        ];
+
+        for (id, patch, expected) in cases {
+            assert_eq!(invalid_order_message(id, patch), expected);
+        }
+    }
+}
@@ -3,16 +3,20 @@ use nostr::{event::Event, key::Keys};
 use nostr_sdk::Client;
 use tracing::info;
 
-use crate::events::job_request::{JobRequest, JobRequestError, JobRequestInput};
+use crate::config::Settings;
+use crate::events::job_request::{JobRequest, JobRequestError, JobRequestInput, JobResultPending};
+use crate::events::job_request_state::JobRequestState;
 
 pub async fn handle_job_request_preview(
     _event: Event,
     _keys: Keys,
     _client: Client,
+    _state: JobRequestState,
+    _settings: Settings,
     job_req: JobRequest,
     _job_req_input: JobRequestInput,
-) -> Result<(), JobRequestError> {
+) -> Result<Option<JobResultPending>, JobRequestError> {
     info!("handle_job_request_preview job_req: {:?}", job_req);
 
-    Ok(())
+    Ok(None)
 }
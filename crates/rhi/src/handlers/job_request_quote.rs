@@ -1,18 +1,373 @@
 use anyhow::Result;
-use nostr::{event::Event, key::Keys};
+use nostr::{
+    event::{Event, EventId},
+    key::Keys,
+    types::Timestamp,
+};
 use nostr_sdk::Client;
 use tracing::info;
 
-use crate::events::job_request::{JobRequest, JobRequestError, JobRequestInput};
+use crate::config::Settings;
+use crate::events::job_request::{
+    JobRequest, JobRequestError, JobRequestInput, JobRequestInputType, JobResultPending,
+    OutputFormat, job_request_input_job_result, job_result_output_tag, render_result,
+    render_supported,
+};
+use crate::events::job_request_state::JobRequestState;
+use crate::handlers::job_request_order::{
+    JobRequestOrderError, classified_fetch, order_result_ref_tags,
+};
+use crate::models::event_classified::EventClassified;
+use crate::models::quote_result::{QuoteResult, QuoteResultDiscount, QuoteResultPrice};
+use crate::utils::cache::TtlCache;
+use crate::utils::nostr::{
+    Payment, nostr_event_is_encrypted, nostr_event_job_result, nostr_event_job_result_encrypted,
+};
+
+/// A quote as sent, which only changes once one of the listing's discount windows opens or
+/// closes.
+#[derive(Debug, Clone)]
+pub struct QuoteRendered {
+    pub payload: String,
+    pub mimetype: &'static str,
+    pub valid_until: Option<Timestamp>,
+}
+
+pub type QuoteCache = TtlCache<(EventId, OutputFormat), QuoteRendered>;
 
 pub async fn handle_job_request_quote(
-    _event: Event,
-    _keys: Keys,
-    _client: Client,
+    event_job_request: Event,
+    keys: Keys,
+    client: Client,
+    state: JobRequestState,
+    settings: Settings,
     job_req: JobRequest,
-    _job_req_input: JobRequestInput,
-) -> Result<(), JobRequestError> {
-    info!("handle_job_request_quote job_req: {:?}", job_req);
+    job_req_input: JobRequestInput,
+) -> Result<Option<JobResultPending>, JobRequestError> {
+    info!("handle_job_request_quote job_req: {}", job_req.id);
+
+    let output = job_req.output.unwrap_or_default();
+    render_supported::<QuoteResult>(output)?;
+
+    // A job input's relay hint locates the prior result, not the listing it names.
+    let (ref_id, relay) = match job_req_input.input_type {
+        JobRequestInputType::Event | JobRequestInputType::Text => (
+            job_req_input.data.trim().to_string(),
+            job_req_input.relay.as_deref(),
+        ),
+        JobRequestInputType::Job => {
+            let result = job_request_input_job_result(
+                &client,
+                &state.event_cache,
+                &settings,
+                &job_req_input,
+            )
+            .await?;
+            (result.content.trim().to_string(), None)
+        }
+        JobRequestInputType::Url => {
+            return Err(JobRequestError::InvalidInputType(
+                job_req_input.input_type.as_str().to_string(),
+            ));
+        }
+    };
+    let ref_id = ref_id.as_str();
+    let ref_event_id = EventId::parse(ref_id)
+        .map_err(|_| JobRequestOrderError::ParseReference(format!("invalid event id {ref_id}")))?;
+
+    let (client_ref, state_ref, settings_ref) = (&client, &state, &settings);
+    let QuoteRendered {
+        payload, mimetype, ..
+    } = quote_rendered(
+        &state.quotes,
+        (ref_event_id, output),
+        Timestamp::now(),
+        |now| async move {
+            let classified =
+                classified_fetch(client_ref, state_ref, settings_ref, ref_id, relay).await?;
+            quote_render(classified, settings_ref, output, now)
+        },
+    )
+    .await?;
+
+    let job_result_event =
+        if settings.encrypt_results || nostr_event_is_encrypted(&event_job_request) {
+            nostr_event_job_result_encrypted(
+                &event_job_request,
+                &keys,
+                payload,
+                0,
+                Payment::None,
+                Some(vec![job_result_output_tag(mimetype)]),
+            )?
+        } else {
+            let mut tags = order_result_ref_tags(&ref_event_id);
+            tags.push(job_result_output_tag(mimetype));
+            nostr_event_job_result(&event_job_request, payload, 0, Payment::None, Some(tags))?
+        };
+
+    Ok(Some(JobResultPending::new(job_result_event)))
+}
+
+/// Serves a cached quote until its TTL or next discount window boundary, otherwise renders
+/// and caches it.
+async fn quote_rendered<F, Fut>(
+    quotes: &QuoteCache,
+    key: (EventId, OutputFormat),
+    now: Timestamp,
+    render: F,
+) -> Result<QuoteRendered, JobRequestError>
+where
+    F: FnOnce(Timestamp) -> Fut,
+    Fut: Future<Output = Result<QuoteRendered, JobRequestError>>,
+{
+    let cached = quotes
+        .get(&key)
+        .filter(|quote| quote.valid_until.is_none_or(|until| now < until));
+    if let Some(quote) = cached {
+        info!("job request quote for {} served from cache", key.0);
+        return Ok(quote);
+    }
+
+    let quote = render(now).await?;
+    quotes.insert(key, quote.clone());
+    Ok(quote)
+}
+
+fn quote_render(
+    classified: EventClassified,
+    settings: &Settings,
+    output: OutputFormat,
+    now: Timestamp,
+) -> Result<QuoteRendered, JobRequestError> {
+    let valid_until = classified
+        .discounts
+        .iter()
+        .flat_map(|discount| {
+            let (from, until) = discount.window();
+            [from, until]
+        })
+        .flatten()
+        .filter(|boundary| *boundary > now)
+        .min();
+    let quote = quote_from_classified(classified, settings, now);
+    let (payload, mimetype) = render_result(&quote, output)?;
+    Ok(QuoteRendered {
+        payload,
+        mimetype,
+        valid_until,
+    })
+}
+
+fn quote_from_classified(
+    classified: EventClassified,
+    settings: &Settings,
+    now: Timestamp,
+) -> QuoteResult {
+    let accepted_currencies = classified.accepted_currencies();
+    QuoteResult {
+        id: classified.id,
+        prices: classified
+            .prices
+            .into_iter()
+            .map(QuoteResultPrice::from)
+            .collect(),
+        quantities: classified.quantities,
+        discounts: classified
+            .discounts
+            .iter()
+            .filter(|d| d.is_active(now))
+            .map(QuoteResultDiscount::from)
+            .collect(),
+        accepted_currencies,
+        tax_rate_percent: classified.tax_rate_percent.or(settings.tax_rate_percent),
+        shipping: classified.shipping,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use nostr::{
+        Kind,
+        event::{EventBuilder, Tag, TagKind},
+    };
+
+    use crate::events::{
+        job_request::{JobRequestInputMarker, parse_event},
+        job_request_builder::JobRequestBuilder,
+    };
+    use crate::models::event_classified::{
+        EventClassifiedBasis, EventClassifiedDiscount, EventClassifiedListing,
+    };
+
+    use super::*;
+    use crate::utils::nostr::EventCache;
+
+    #[test]
+    fn quote_filters_discounts_when_served() {
+        let classified = EventClassified::new(
+            EventId::all_zeros(),
+            EventClassifiedBasis::default(),
+            EventClassifiedListing::default(),
+            vec![],
+            vec![],
+            vec![EventClassifiedDiscount::Subtotal {
+                threshold: 0.0,
+                currency: "USD".into(),
+                value: 5.0,
+                is_percent: true,
+                valid_from: Some(Timestamp::from(1_000)),
+                valid_until: Some(Timestamp::from(2_000)),
+            }],
+        );
+        let settings = Settings::default();
+        let quote_at = |now: u64| {
+            quote_from_classified(classified.clone(), &settings, Timestamp::from(now)).discounts
+        };
+
+        assert!(quote_at(500).is_empty());
+        assert_eq!(quote_at(1_500).len(), 1);
+        assert!(quote_at(2_500).is_empty());
+    }
+
+    #[test]
+    fn quote_valid_until_next_discount_boundary() {
+        let classified = EventClassified::new(
+            EventId::all_zeros(),
+            EventClassifiedBasis::default(),
+            EventClassifiedListing::default(),
+            vec![],
+            vec![],
+            vec![EventClassifiedDiscount::Subtotal {
+                threshold: 0.0,
+                currency: "USD".into(),
+                value: 5.0,
+                is_percent: true,
+                valid_from: Some(Timestamp::from(1_000)),
+                valid_until: Some(Timestamp::from(2_000)),
+            }],
+        );
+        let settings = Settings::default();
+        let valid_until = |now: u64| {
+            quote_render(
+                classified.clone(),
+                &settings,
+                OutputFormat::Json,
+                Timestamp::from(now),
+            )
+            .unwrap()
+            .valid_until
+        };
+
+        assert_eq!(valid_until(500), Some(Timestamp::from(1_000)));
+        assert_eq!(valid_until(1_500), Some(Timestamp::from(2_000)));
+        assert_eq!(valid_until(2_500), None);
+    }
+
+    #[tokio::test]
+    async fn quote_rendered_once_until_discount_boundary() {
+        let quotes = QuoteCache::from(&Settings::default().quote_cache);
+        let renders = Cell::new(0);
+        let render = |now: Timestamp| {
+            renders.set(renders.get() + 1);
+            async move {
+                Ok::<_, JobRequestError>(QuoteRendered {
+                    payload: now.as_u64().to_string(),
+                    mimetype: "application/json",
+                    valid_until: Some(Timestamp::from(2_000)),
+                })
+            }
+        };
+        let json = (EventId::all_zeros(), OutputFormat::Json);
+        let csv = (EventId::all_zeros(), OutputFormat::Csv);
+        let at = Timestamp::from;
+
+        let first = quote_rendered(&quotes, json, at(1_000), render)
+            .await
+            .unwrap();
+        let repeat = quote_rendered(&quotes, json, at(1_500), render)
+            .await
+            .unwrap();
+        assert_eq!(
+            (first.payload.as_str(), repeat.payload.as_str()),
+            ("1000", "1000")
+        );
+        assert_eq!(renders.get(), 1);
+
+        // Each output format is rendered separately.
+        let other = quote_rendered(&quotes, csv, at(1_500), render)
+            .await
+            .unwrap();
+        assert_eq!(other.payload, "1500");
+        assert_eq!(renders.get(), 2);
+
+        // A discount window closing makes the cached quote stale.
+        let stale = quote_rendered(&quotes, json, at(2_000), render)
+            .await
+            .unwrap();
+        assert_eq!(stale.payload, "2000");
+        assert_eq!(renders.get(), 3);
+    }
+
+    async fn quote(
+        listing: &Event,
+        state: JobRequestState,
+        settings: Settings,
+    ) -> Result<Option<JobResultPending>, JobRequestError> {
+        let requester = Keys::generate();
+        let event = JobRequestBuilder::new()
+            .input(
+                listing.id.to_hex(),
+                JobRequestInputType::Event,
+                None,
+                Some(JobRequestInputMarker::Quote),
+            )
+            .build()
+            .sign_with_keys(&requester)
+            .unwrap();
+        let job_req = parse_event(&event, &requester).unwrap();
+        let job_req_input = job_req.inputs[0].clone();
+
+        handle_job_request_quote(
+            event,
+            Keys::generate(),
+            Client::default(),
+            state,
+            settings,
+            job_req,
+            job_req_input,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn quotes_within_ttl_fetch_listing_once() {
+        let listing = EventBuilder::new(Kind::ClassifiedListing, "")
+            .tags([
+                Tag::custom(TagKind::custom("price"), ["12", "USD", "1", "kg"]),
+                Tag::custom(TagKind::custom("quantity"), ["500", "g", "bag"]),
+            ])
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let settings = Settings::default();
+        let state = JobRequestState::from(&settings);
+        state.event_cache.insert(listing.id, listing.clone());
+
+        assert!(
+            quote(&listing, state.clone(), settings.clone())
+                .await
+                .is_ok()
+        );
 
-    Ok(())
+        // The client has no relays, so a second quote can only be answered from the quote cache.
+        let empty = JobRequestState {
+            event_cache: EventCache::from(&settings.event_cache),
+            ..state
+        };
+        assert!(quote(&listing, empty, settings.clone()).await.is_ok());
+        let fresh = JobRequestState::from(&settings);
+        assert!(quote(&listing, fresh, settings).await.is_err());
+    }
 }
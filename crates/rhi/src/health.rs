@@ -0,0 +1,150 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use nostr::key::PublicKey;
+use nostr_sdk::Client;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{info, warn};
+
+use crate::metrics;
+
+#[derive(Clone, Default)]
+pub struct HealthState {
+    clients: Arc<RwLock<HashMap<PublicKey, Client>>>,
+}
+
+impl HealthState {
+    pub fn set_client(&self, public_key: PublicKey, client: Client) {
+        if let Ok(mut clients) = self.clients.write() {
+            clients.insert(public_key, client);
+        }
+    }
+
+    pub async fn is_ready(&self) -> bool {
+        let clients: Vec<Client> = match self.clients.read() {
+            Ok(clients) => clients.values().cloned().collect(),
+            Err(_) => return false,
+        };
+
+        if clients.is_empty() {
+            return false;
+        }
+
+        for client in clients {
+            if !client.relays().await.values().any(|r| r.is_connected()) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+pub async fn serve(addr: &str, state: HealthState) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Health server listening on {addr}");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, state).await {
+                warn!("Health server connection error: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: HealthState) -> std::io::Result<()> {
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, body) = match path {
+        "/healthz" => ("200 OK", "ok".to_string()),
+        "/readyz" if state.is_ready().await => ("200 OK", "ready".to_string()),
+        "/readyz" => ("503 Service Unavailable", "not ready".to_string()),
+        "/metrics" => match metrics::render() {
+            Some(body) => ("200 OK", body),
+            None => ("404 Not Found", "not found".to_string()),
+        },
+        _ => ("404 Not Found", "not found".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nostr::key::Keys;
+    use nostr_relay_builder::MockRelay;
+
+    use super::*;
+
+    async fn request(state: &HealthState, path: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = state.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_connection(stream, state).await.unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn healthz_ok() {
+        let response = request(&HealthState::default(), "/healthz").await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn readyz_reflects_relay_connection() {
+        let state = HealthState::default();
+        assert!(request(&state, "/readyz").await.starts_with("HTTP/1.1 503"));
+
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        state.set_client(keys.public_key(), client);
+
+        assert!(
+            request(&state, "/readyz")
+                .await
+                .starts_with("HTTP/1.1 200 OK")
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_path_not_found() {
+        let response = request(&HealthState::default(), "/nope").await;
+
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
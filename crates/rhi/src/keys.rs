@@ -1,10 +1,11 @@
 use anyhow::Result;
 use nostr::{
-    Event, Keys,
-    event::{EventBuilder, Kind, Tag, TagKind},
+    Event, JsonUtil, Keys,
+    event::{EventBuilder, Kind, Tag, TagKind, TagStandard},
     nips::nip01::Metadata,
+    types::RelayUrl,
 };
-use radroots_common::{KIND_APPLICATION_HANDLER, KIND_JOB_REQUEST};
+use radroots_common::KIND_APPLICATION_HANDLER;
 use serde::{Deserialize, Serialize};
 use std::{
     fs::{self, File},
@@ -17,6 +18,8 @@ use thiserror::Error;
 use tracing::{error, warn};
 use uuid::Uuid;
 
+use crate::config::{RelayRole, RelaySettings};
+
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
@@ -48,6 +51,16 @@ pub enum KeyProfileError {
 
     #[error("Invalid secret key for identifier: {0}")]
     InvalidSecretKey(String),
+
+    #[error("Keys file at {0} contains no profiles")]
+    Empty(PathBuf),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyProfileFile {
+    Many(Vec<KeyProfile>),
+    One(KeyProfile),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -59,6 +72,9 @@ pub struct KeyProfile {
 
     #[serde(skip)]
     path: Option<PathBuf>,
+
+    #[serde(skip)]
+    index: Option<usize>,
 }
 
 impl KeyProfile {
@@ -68,32 +84,68 @@ impl KeyProfile {
         identifier_tag: Option<String>,
     ) -> Result<Self, KeyProfileError> {
         let path = PathBuf::from(path_str.as_ref());
+        Self::init_all(path_str, generate, identifier_tag)?
+            .into_iter()
+            .next()
+            .ok_or(KeyProfileError::Empty(path))
+    }
+
+    pub fn init_all<P: AsRef<str>>(
+        path_str: P,
+        generate: bool,
+        identifier_tag: Option<String>,
+    ) -> Result<Vec<Self>, KeyProfileError> {
+        let path = PathBuf::from(path_str.as_ref());
 
         if path.exists() {
             let file = File::open(&path).map_err(|e| KeyProfileError::FileOpen(path.clone(), e))?;
             let reader = BufReader::new(file);
-            let mut profile: KeyProfile = serde_json::from_reader(reader)
-                .map_err(|e| KeyProfileError::FileParse(path.clone(), e))?;
-            profile.path = Some(path.clone());
+            let profiles = match serde_json::from_reader(reader)
+                .map_err(|e| KeyProfileError::FileParse(path.clone(), e))?
+            {
+                KeyProfileFile::One(profile) => vec![profile],
+                KeyProfileFile::Many(profiles) => profiles
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, profile)| KeyProfile {
+                        index: Some(index),
+                        ..profile
+                    })
+                    .collect(),
+            };
 
-            if !profile.identifier.trim().is_empty() {
-                if let Some(new_id) = identifier_tag {
-                    warn!(
-                        "Provided identifier '{}' is being ignored because the keys file already contains identifier '{}'.",
-                        new_id, profile.identifier
-                    );
+            if profiles.is_empty() {
+                return Err(KeyProfileError::Empty(path));
+            }
+
+            let mut loaded = Vec::with_capacity(profiles.len());
+            // Handler identifiers must differ between profiles, so only the first may take
+            // the provided one.
+            let mut identifier_tag = identifier_tag;
+            for mut profile in profiles {
+                profile.path = Some(path.clone());
+
+                if !profile.identifier.trim().is_empty() {
+                    if let Some(new_id) = identifier_tag.take() {
+                        warn!(
+                            "Provided identifier '{}' is being ignored because the keys file already contains identifier '{}'.",
+                            new_id, profile.identifier
+                        );
+                    }
+                } else {
+                    profile.identifier = identifier_tag.take().unwrap_or_else(|| {
+                        warn!(
+                            "Missing NIP-89 application handler identifier in key file, generating UUID."
+                        );
+                        Uuid::new_v4().to_string()
+                    });
+                    profile.persist()?;
                 }
-            } else {
-                profile.identifier = identifier_tag.unwrap_or_else(|| {
-                    warn!(
-                        "Missing NIP-89 application handler identifier in key file, generating UUID."
-                    );
-                    Uuid::new_v4().to_string()
-                });
-                profile.persist()?;
+
+                loaded.push(profile);
             }
 
-            Ok(profile)
+            Ok(loaded)
         } else if generate {
             let keys = Keys::generate();
             let secret = keys.secret_key();
@@ -113,23 +165,26 @@ impl KeyProfile {
                 metadata: None,
                 application_handler: None,
                 path: Some(path.clone()),
+                index: None,
             };
 
-            profile.atomic_write(&path)?;
-            Ok(profile)
+            profile.atomic_write(&path, &serde_json::to_string(&profile)?)?;
+            Ok(vec![profile])
         } else {
             Err(KeyProfileError::NotFound(path))
         }
     }
 
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
     pub fn keys(&self) -> Result<Keys, KeyProfileError> {
         Keys::from_str(&self.key)
             .map_err(|_| KeyProfileError::InvalidSecretKey(self.identifier.clone()))
     }
 
-    fn atomic_write<P: AsRef<Path>>(&self, path: P) -> Result<(), KeyProfileError> {
-        let json = serde_json::to_string(self)?;
-
+    fn atomic_write<P: AsRef<Path>>(&self, path: P, json: &str) -> Result<(), KeyProfileError> {
         let dir = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
         let mut temp_file = NamedTempFile::new_in(dir)?;
 
@@ -146,55 +201,297 @@ impl KeyProfile {
     }
 
     fn persist(&self) -> Result<(), KeyProfileError> {
-        match &self.path {
-            Some(p) => self.atomic_write(p),
-            None => Err(KeyProfileError::NotFound(PathBuf::from("[unknown path]"))),
+        let Some(path) = &self.path else {
+            return Err(KeyProfileError::NotFound(PathBuf::from("[unknown path]")));
+        };
+
+        match self.index {
+            Some(index) => {
+                let file =
+                    File::open(path).map_err(|e| KeyProfileError::FileOpen(path.clone(), e))?;
+                let mut profiles: Vec<KeyProfile> =
+                    serde_json::from_reader(BufReader::new(file))
+                        .map_err(|e| KeyProfileError::FileParse(path.clone(), e))?;
+                match profiles.get_mut(index) {
+                    Some(profile) => *profile = self.clone(),
+                    None => profiles.push(self.clone()),
+                }
+                self.atomic_write(path, &serde_json::to_string(&profiles)?)
+            }
+            None => self.atomic_write(path, &serde_json::to_string(self)?),
         }
     }
 
     pub async fn build_metadata(
-        &mut self,
+        &self,
         metadata: &Metadata,
+        force: bool,
     ) -> Result<Option<Event>, KeyProfileError> {
-        if self.metadata.is_none() {
-            let keys = self.keys()?;
-            let event = EventBuilder::metadata(metadata).sign(&keys).await?;
-            self.metadata = Some(event.clone());
-            self.persist()?;
-            Ok(Some(event))
-        } else {
-            Ok(None)
+        let unchanged = self.metadata.as_ref().is_some_and(|event| {
+            Metadata::from_json(&event.content).is_ok_and(|published| &published == metadata)
+        });
+        if unchanged && !force {
+            return Ok(None);
         }
+
+        let keys = self.keys()?;
+        let event = EventBuilder::metadata(metadata).sign(&keys).await?;
+        Ok(Some(event))
     }
 
-    pub async fn build_application_handler(&mut self) -> Result<Option<Event>, KeyProfileError> {
-        if self.application_handler.is_none() {
-            let keys = self.keys()?;
-            let kind_0_content = self
-                .metadata
-                .as_ref()
-                .expect(&format!(
-                    "The kind 0 metadata must be initialized before kind {} descriptor",
-                    KIND_APPLICATION_HANDLER.to_string()
-                ))
-                .content
-                .clone();
-
-            let tags: Vec<Tag> = vec![
-                Tag::custom(TagKind::Custom("k".into()), [KIND_JOB_REQUEST.to_string()]),
-                Tag::identifier(self.identifier.to_string()),
-            ];
-
-            let event = EventBuilder::new(Kind::Custom(KIND_APPLICATION_HANDLER), kind_0_content)
-                .tags(tags)
-                .sign(&keys)
-                .await?;
+    pub async fn build_application_handler(
+        &self,
+        metadata: &Metadata,
+        kinds: &[u16],
+        relays: &[RelaySettings],
+        force: bool,
+    ) -> Result<Option<Event>, KeyProfileError> {
+        let kind_0_content = metadata.as_json();
+
+        let mut tags: Vec<Tag> = kinds
+            .iter()
+            .map(|kind| Tag::custom(TagKind::Custom("k".into()), [kind.to_string()]))
+            .collect();
+        tags.push(Tag::identifier(self.identifier.to_string()));
+        tags.extend(
+            relays
+                .iter()
+                .filter(|relay| relay.role != RelayRole::Write)
+                .filter_map(|relay| RelayUrl::parse(&relay.url).ok())
+                .map(|url| Tag::from_standardized(TagStandard::Relay(url))),
+        );
+
+        let unchanged = self.application_handler.as_ref().is_some_and(|event| {
+            event.content == kind_0_content && event.tags.iter().eq(tags.iter())
+        });
+        if unchanged && !force {
+            return Ok(None);
+        }
+
+        let keys = self.keys()?;
+        let event = EventBuilder::new(Kind::Custom(KIND_APPLICATION_HANDLER), kind_0_content)
+            .tags(tags)
+            .sign(&keys)
+            .await?;
+        Ok(Some(event))
+    }
 
+    /// Records an event built by this profile once it has been sent, so it is not rebuilt
+    /// until its content changes.
+    pub fn mark_published(&mut self, event: &Event) -> Result<(), KeyProfileError> {
+        if event.kind == Kind::Metadata {
+            self.metadata = Some(event.clone());
+        } else if event.kind == Kind::Custom(KIND_APPLICATION_HANDLER) {
             self.application_handler = Some(event.clone());
-            self.persist()?;
-            Ok(Some(event))
         } else {
-            Ok(None)
+            return Ok(());
+        }
+        self.persist()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_profile(dir: &tempfile::TempDir) -> KeyProfile {
+        let path = dir.path().join("keys.json");
+        KeyProfile::init(path.to_str().unwrap(), true, Some("rhi-test".into())).unwrap()
+    }
+
+    fn metadata() -> Metadata {
+        Metadata {
+            name: Some("rhi".into()),
+            display_name: Some("rhizome".into()),
+            about: Some("Prices classified listings".into()),
+            picture: Some("https://rhi.test/picture.png".into()),
+            banner: Some("https://rhi.test/banner.png".into()),
+            website: Some("https://rhi.test".into()),
+            lud06: Some("lnurl1rhi".into()),
+            lud16: Some("tips@rhi.test".into()),
+            ..Default::default()
         }
     }
+
+    #[tokio::test]
+    async fn build_metadata_carries_profile_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile = key_profile(&dir);
+
+        let event = profile
+            .build_metadata(&metadata(), false)
+            .await
+            .unwrap()
+            .unwrap();
+        let published = Metadata::from_json(&event.content).unwrap();
+
+        assert_eq!(event.kind, Kind::Metadata);
+        assert_eq!(published.display_name.as_deref(), Some("rhizome"));
+        assert_eq!(
+            published.about.as_deref(),
+            Some("Prices classified listings")
+        );
+        assert_eq!(
+            published.picture.as_deref(),
+            Some("https://rhi.test/picture.png")
+        );
+        assert_eq!(
+            published.banner.as_deref(),
+            Some("https://rhi.test/banner.png")
+        );
+        assert_eq!(published.website.as_deref(), Some("https://rhi.test"));
+        assert_eq!(published.lud06.as_deref(), Some("lnurl1rhi"));
+        assert_eq!(published.lud16.as_deref(), Some("tips@rhi.test"));
+    }
+
+    #[tokio::test]
+    async fn application_handler_advertises_job_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile = key_profile(&dir);
+
+        let event = profile
+            .build_application_handler(&metadata(), &[5300, 5301], &[], false)
+            .await
+            .unwrap()
+            .unwrap();
+        let kinds: Vec<&str> = event
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .filter(|t| t[0] == "k")
+            .map(|t| t[1].as_str())
+            .collect();
+
+        assert_eq!(event.kind, Kind::Custom(KIND_APPLICATION_HANDLER));
+        assert_eq!(kinds, ["5300", "5301"]);
+    }
+
+    #[tokio::test]
+    async fn application_handler_advertises_listening_relays() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile = key_profile(&dir);
+        let relay = |url: &str, role| RelaySettings {
+            url: url.into(),
+            role,
+            auth: false,
+        };
+        let relays = [
+            relay("wss://read.test", RelayRole::Read),
+            relay("wss://write.test", RelayRole::Write),
+            relay("wss://both.test", RelayRole::Both),
+            relay("not a relay", RelayRole::Both),
+        ];
+
+        let event = profile
+            .build_application_handler(&metadata(), &[5300], &relays, false)
+            .await
+            .unwrap()
+            .unwrap();
+        let advertised: Vec<&str> = event
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .filter(|t| t[0] == "relay")
+            .map(|t| t[1].as_str())
+            .collect();
+
+        assert_eq!(advertised, ["wss://read.test", "wss://both.test"]);
+    }
+
+    async fn build_events(
+        profile: &KeyProfile,
+        metadata: &Metadata,
+        force: bool,
+    ) -> (Option<Event>, Option<Event>) {
+        let kind_0 = profile.build_metadata(metadata, force).await.unwrap();
+        let handler = profile
+            .build_application_handler(metadata, &[5300], &[], force)
+            .await
+            .unwrap();
+        (kind_0, handler)
+    }
+
+    #[tokio::test]
+    async fn unchanged_events_rebuilt_until_published() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut profile = key_profile(&dir);
+
+        let (kind_0, handler) = build_events(&profile, &metadata(), false).await;
+        assert!(build_events(&profile, &metadata(), false).await.0.is_some());
+
+        profile.mark_published(&kind_0.unwrap()).unwrap();
+        profile.mark_published(&handler.unwrap()).unwrap();
+
+        assert!(matches!(
+            build_events(&profile, &metadata(), false).await,
+            (None, None)
+        ));
+        assert!(matches!(
+            build_events(&profile, &metadata(), true).await,
+            (Some(_), Some(_))
+        ));
+        let changed = Metadata {
+            about: Some("Quotes classified listings".into()),
+            ..metadata()
+        };
+        assert!(matches!(
+            build_events(&profile, &changed, false).await,
+            (Some(_), Some(_))
+        ));
+
+        let path = dir.path().join("keys.json");
+        let reloaded = KeyProfile::init(path.to_str().unwrap(), false, None).unwrap();
+        assert!(matches!(
+            build_events(&reloaded, &metadata(), false).await,
+            (None, None)
+        ));
+    }
+
+    #[test]
+    fn init_all_loads_each_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.json");
+        let (first, second) = (Keys::generate(), Keys::generate());
+        let profiles = serde_json::json!([
+            { "key": first.secret_key().to_secret_hex(), "identifier": "rhi-one" },
+            { "key": second.secret_key().to_secret_hex(), "identifier": "" },
+        ]);
+        fs::write(&path, profiles.to_string()).unwrap();
+
+        let loaded =
+            KeyProfile::init_all(path.to_str().unwrap(), false, Some("rhi-fallback".into()))
+                .unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].identifier(), "rhi-one");
+        assert!(Uuid::parse_str(loaded[1].identifier()).is_ok());
+        assert_eq!(loaded[0].keys().unwrap().public_key(), first.public_key());
+        assert_eq!(loaded[1].keys().unwrap().public_key(), second.public_key());
+    }
+
+    #[test]
+    fn init_all_gives_later_profiles_their_own_identifier() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.json");
+        let profiles = serde_json::json!([
+            { "key": Keys::generate().secret_key().to_secret_hex(), "identifier": "" },
+            { "key": Keys::generate().secret_key().to_secret_hex(), "identifier": "" },
+            { "key": Keys::generate().secret_key().to_secret_hex(), "identifier": "" },
+        ]);
+        fs::write(&path, profiles.to_string()).unwrap();
+
+        let loaded =
+            KeyProfile::init_all(path.to_str().unwrap(), false, Some("rhi-fallback".into()))
+                .unwrap();
+
+        assert_eq!(loaded[0].identifier(), "rhi-fallback");
+        assert!(Uuid::parse_str(loaded[1].identifier()).is_ok());
+        assert!(Uuid::parse_str(loaded[2].identifier()).is_ok());
+        assert_ne!(loaded[1].identifier(), loaded[2].identifier());
+
+        let reloaded = KeyProfile::init_all(path.to_str().unwrap(), false, None).unwrap();
+        let identifiers: Vec<&str> = reloaded.iter().map(KeyProfile::identifier).collect();
+        let expected: Vec<&str> = loaded.iter().map(KeyProfile::identifier).collect();
+        assert_eq!(identifiers, expected);
+    }
 }
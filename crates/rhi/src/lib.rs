@@ -1,6 +1,10 @@
+pub mod audit;
 pub mod config;
 pub mod events;
 pub mod handlers;
+pub mod health;
 pub mod keys;
+pub mod logging;
+pub mod metrics;
 pub mod models;
 pub mod utils;
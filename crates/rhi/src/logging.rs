@@ -0,0 +1,70 @@
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::config::LogFormat;
+
+pub fn init_tracing(format: LogFormat) {
+    // Logs stay off stdout, which carries `price` and `--print-config` output.
+    let subscriber = tracing_subscriber::fmt().with_writer(std::io::stderr);
+    match format {
+        LogFormat::Pretty => subscriber.pretty().init(),
+        LogFormat::Compact => subscriber.compact().init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+/// Logs through a default subscriber until the configured one is installed.
+pub fn with_startup_tracing<W, T>(writer: W, f: impl FnOnce() -> T) -> T
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let subscriber = tracing_subscriber::fmt().with_writer(writer).finish();
+    tracing::subscriber::with_default(subscriber, f)
+}
+
+/// Captures log output in memory, for tests asserting on what was logged.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct LogBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl LogBuffer {
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Settings;
+
+    use super::*;
+
+    #[test]
+    fn startup_warnings_logged_before_tracing_init() {
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+
+        let loaded = with_startup_tracing(
+            move || writer.clone(),
+            || {
+                tracing::warn!("Could not read config file");
+                Settings::default()
+            },
+        );
+
+        assert!(logs.contents().contains("Could not read config file"));
+        assert_eq!(loaded.log_format, LogFormat::default());
+    }
+}
@@ -1,26 +1,62 @@
-use anyhow::Result;
-use clap::Parser;
-use nostr::event::Event;
-use nostr_sdk::Client;
-use rhi::{config::Settings, events, keys::KeyProfile};
-use tokio::signal::unix::{SignalKind, signal};
+use anyhow::{Result, anyhow};
+use clap::{Parser, Subcommand};
+use nostr::{Keys, ToBech32, event::Event};
+use rhi::{
+    audit::{AuditDirection, AuditLog},
+    config::{RelaySettings, Settings, config_file_load},
+    events::{
+        self, job_request_state::JobRequestState, job_request_store::JobRequestStore,
+        listing_index::ListingIndex,
+    },
+    health::{self, HealthState},
+    keys::KeyProfile,
+    logging::{init_tracing, with_startup_tracing},
+    models::{
+        event_classified::{EventClassified, OrderContext},
+        order_result::OrderClassifiedResult,
+    },
+    utils::{
+        backoff::{Backoff, backoff_loop},
+        cache::TtlCache,
+        nostr::{nostr_client_add_relays, nostr_client_connect, nostr_client_new},
+        price::OrderSpec,
+    },
+};
+use serde_json::json;
+use std::{io::Write, time::Duration};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
-fn init_tracing() {
-    tracing_subscriber::fmt::init();
-}
-
 #[derive(Parser)]
 #[command(
     about = env!("CARGO_PKG_DESCRIPTION"),
     author = env!("CARGO_PKG_AUTHORS"),
-    version = env!("CARGO_PKG_VERSION")
+    version = env!("CARGO_PKG_VERSION"),
+    subcommand_negates_reqs = true
 )]
 pub struct Args {
-    #[arg(long, help = "Adds the keys profiles file path", required = true)]
-    pub keys: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    #[arg(long, help = "Adds nostr relays to the subscription", required = true)]
+    #[arg(
+        long,
+        env = "RHI_KEYS",
+        help = "Adds the keys profiles file path, the flag takes precedence over RHI_KEYS",
+        required = true
+    )]
+    pub keys: Option<String>,
+
+    #[arg(
+        long,
+        env = "RHI_RELAYS",
+        value_delimiter = ',',
+        help = "Adds nostr relays to the subscription, merged with relays from the config file. The flag takes precedence over comma-separated RHI_RELAYS",
+        required = false
+    )]
     pub relays: Vec<String>,
 
     #[arg(
@@ -32,83 +68,647 @@ pub struct Args {
 
     #[arg(
         long,
-        help = "(Optional) Adds the application handler identifier tag (NIP-89)",
+        help = "(Optional) Adds the application handler identifier tag (NIP-89) to the first profile if it has none",
         required = false
     )]
     pub identifier: Option<String>,
 
     #[arg(
         long,
-        help = "(Optional) Adds the config file path. Defaults to 'config.toml'",
+        env = "RHI_CONFIG",
+        help = "(Optional) Adds the config file path. Defaults to 'config.toml', the flag takes precedence over RHI_CONFIG",
         required = false
     )]
     pub config: Option<String>,
+
+    #[arg(
+        long,
+        help = "(Optional) Logs events instead of publishing them",
+        required = false
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        help = "(Optional) Serves /healthz and /readyz on this address",
+        required = false
+    )]
+    pub health_addr: Option<String>,
+
+    #[arg(
+        long,
+        help = "(Optional) Prints the resolved config and key profiles as JSON and exits",
+        required = false,
+        conflicts_with = "generate_keys"
+    )]
+    pub print_config: bool,
+
+    #[arg(
+        long,
+        help = "(Optional) Republishes the metadata and application handler events even when unchanged",
+        required = false
+    )]
+    pub force_republish: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Prices an order against a listing file without connecting to relays")]
+    Price {
+        #[arg(long, help = "Adds the listing file path (JSON or TOML)")]
+        listing: String,
+
+        #[arg(long, help = "Adds the order file path (JSON or TOML)")]
+        order: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_tracing();
-
     let args = Args::parse();
-    let config = Settings::load(&args.config)?;
+    let mut config = with_startup_tracing(std::io::stderr, || Settings::load(&args.config))?;
+    init_tracing(config.log_format);
 
-    let relays = args.relays.clone();
+    if let Some(command) = &args.command {
+        return run_command(&config, command, &mut std::io::stdout());
+    }
+
+    config.dry_run |= args.dry_run;
+    if args.health_addr.is_some() {
+        config.health_addr = args.health_addr.clone();
+    }
+
+    let relays = config.relays(&args.relays)?;
+
+    let keys_path = args.keys.ok_or_else(|| anyhow!("--keys is required"))?;
+    let mut key_profiles = KeyProfile::init_all(keys_path, args.generate_keys, args.identifier)?;
+
+    if args.print_config {
+        return print_config(&config, &relays, &key_profiles);
+    }
 
     info!("Starting");
 
-    let mut key_profile = KeyProfile::init(args.keys, args.generate_keys, args.identifier)?;
+    let health = HealthState::default();
+
+    if let Some(addr) = config.health_addr.clone() {
+        let health = health.clone();
+        tokio::spawn(async move {
+            if let Err(e) = health::serve(&addr, health).await {
+                error!("Error on health server: {e}");
+            }
+        });
+    }
+
+    let state = JobRequestState {
+        store: JobRequestStore::open(config.store_path.as_deref(), &config.store).await?,
+        audit: AuditLog::open(config.audit_log.as_deref())?,
+        ..JobRequestState::from(&config)
+    };
+    let shutdown = CancellationToken::new();
+    let mut subscribers = Vec::with_capacity(key_profiles.len() + 1);
+
+    // A single index serves every profile, subscribing with the first profile's keys.
+    if config.listing_index.is_some() {
+        subscribers.push(spawn_listing_index(
+            key_profiles[0].keys()?,
+            relays.clone(),
+            config.clone(),
+            state.listings.clone(),
+            shutdown.clone(),
+        ));
+    }
+
+    for (index, key_profile) in key_profiles.iter_mut().enumerate() {
+        let keys = key_profile.keys()?;
+        publish_key_profile(
+            key_profile,
+            &keys,
+            &config,
+            &state.audit,
+            &relays,
+            args.force_republish,
+        )
+        .await?;
+        subscribers.push(spawn_subscriber(
+            keys,
+            relays.clone(),
+            config.for_profile(index),
+            health.clone(),
+            shutdown.clone(),
+            state.clone(),
+        ));
+    }
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = sigterm.recv() => {
+            info!("Received SIGTERM. Shutting down...");
+        },
+        _ = sigint.recv() => {
+            info!("Received SIGINT. Shutting down...");
+        }
+    }
+
+    shutdown.cancel();
+    for subscriber in subscribers {
+        let _ = subscriber.await;
+    }
+    state.audit.flush();
+
+    Ok(())
+}
+
+fn run_command(config: &Settings, command: &Command, out: &mut impl Write) -> Result<()> {
+    match command {
+        Command::Price { listing, order } => {
+            let result = price_files(config, listing, order)?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&result)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn price_files(config: &Settings, listing: &str, order: &str) -> Result<OrderClassifiedResult> {
+    let listing = EventClassified::from_file(listing)?;
+    let order: OrderSpec = config_file_load(order)?;
+    let ctx = OrderContext {
+        tax_rate_percent: config.tax_rate_percent,
+        rounding: config.rounding,
+        price_policy: config.price_policy,
+        tier_selection: config.tier_selection,
+        ..OrderContext::now()
+    };
 
-    let keys = key_profile.keys()?;
+    Ok(listing.calculate_order(&order, &ctx)?)
+}
 
-    let metadata = config.metadata.clone();
+fn print_config(
+    config: &Settings,
+    relays: &[RelaySettings],
+    key_profiles: &[KeyProfile],
+) -> Result<()> {
+    let output = config_json(config, relays, key_profiles)?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
 
+fn config_json(
+    config: &Settings,
+    relays: &[RelaySettings],
+    key_profiles: &[KeyProfile],
+) -> Result<serde_json::Value> {
+    let profiles = key_profiles
+        .iter()
+        .map(|profile| {
+            Ok(json!({
+                "npub": profile.keys()?.public_key().to_bech32()?,
+                "identifier": profile.identifier(),
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(json!({
+        "metadata": config.metadata,
+        "relays": relays,
+        "profiles": profiles,
+    }))
+}
+
+async fn publish_key_profile(
+    key_profile: &mut KeyProfile,
+    keys: &Keys,
+    config: &Settings,
+    audit: &AuditLog,
+    relays: &[RelaySettings],
+    force: bool,
+) -> Result<()> {
     let mut events: Vec<Event> = vec![];
 
-    if let Some(event) = key_profile.build_metadata(&metadata).await? {
+    if let Some(event) = key_profile.build_metadata(&config.metadata, force).await? {
         events.push(event);
     }
 
-    if let Some(event) = key_profile.build_application_handler().await? {
+    if let Some(event) = key_profile
+        .build_application_handler(&config.metadata, &config.job_kinds, relays, force)
+        .await?
+    {
         events.push(event);
     }
 
-    if !events.is_empty() {
-        let client = Client::new(keys.clone());
-        for relay in relays.iter() {
-            client.add_relay(relay).await?;
+    if config.dry_run {
+        for event in &events {
+            info!(
+                "Dry run, not sending kind {} event for key profile",
+                event.kind
+            );
         }
-        client.connect().await;
+    } else if !events.is_empty() {
+        let client = nostr_client_new(keys.clone(), relays);
+        nostr_client_add_relays(&client, relays).await?;
+        nostr_client_connect(
+            &client,
+            config.connect_timeout(),
+            config.min_connected_relays,
+        )
+        .await?;
         for event in events {
             client.send_event(&event).await?;
+            audit.record(AuditDirection::Out, &event);
+            key_profile.mark_published(&event)?;
             info!("Sent kind {} event for key profile", { event.clone().kind })
         }
         client.disconnect().await;
     }
 
-    let keys_sub = keys.clone();
-    let relays_sub = relays.clone();
+    Ok(())
+}
 
+fn spawn_subscriber(
+    keys: Keys,
+    relays: Vec<RelaySettings>,
+    config: Settings,
+    health: HealthState,
+    shutdown: CancellationToken,
+    state: JobRequestState,
+) -> JoinHandle<()> {
     tokio::spawn(async move {
-        loop {
-            if let Err(e) =
-                events::job_request::subscriber(keys_sub.clone(), relays_sub.clone()).await
-            {
-                error!("Error on job request subscription: {e}");
+        let mut backoff = Backoff::from(&config.reconnect);
+        let reset_after = Duration::from_secs(config.reconnect.reset_after_secs);
+        let seen = TtlCache::from(&config.dedup);
+
+        backoff_loop(&mut backoff, reset_after, &shutdown, || {
+            let public_key = keys.public_key();
+            let subscriber = events::job_request::subscriber(
+                keys.clone(),
+                relays.clone(),
+                config.clone(),
+                health.clone(),
+                shutdown.clone(),
+                state.clone(),
+                seen.clone(),
+            );
+            async move {
+                if let Err(e) = subscriber.await {
+                    error!("Error on job request subscription for {public_key}: {e}");
+                }
             }
-        }
-    });
+        })
+        .await;
+    })
+}
 
-    let mut sigterm = signal(SignalKind::terminate())?;
-    let mut sigint = signal(SignalKind::interrupt())?;
+fn spawn_listing_index(
+    keys: Keys,
+    relays: Vec<RelaySettings>,
+    config: Settings,
+    index: ListingIndex,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut backoff = Backoff::from(&config.reconnect);
+        let reset_after = Duration::from_secs(config.reconnect.reset_after_secs);
 
-    tokio::select! {
-        _ = sigterm.recv() => {
-            info!("Received SIGTERM. Shutting down...");
+        backoff_loop(&mut backoff, reset_after, &shutdown, || {
+            let subscriber = events::listing_index::subscriber(
+                keys.clone(),
+                relays.clone(),
+                config.clone(),
+                index.clone(),
+                shutdown.clone(),
+            );
+            async move {
+                if let Err(e) = subscriber.await {
+                    error!("Error on listing index subscription: {e}");
+                }
+            }
+        })
+        .await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use nostr::{EventId, Kind, PublicKey, filter::Filter};
+    use nostr_relay_builder::MockRelay;
+    use nostr_sdk::{Client, RelayPoolNotification};
+    use rhi::{
+        config::RelayRole,
+        events::{
+            job_request::{JobRequestInputMarker, JobRequestInputType},
+            job_request_builder::JobRequestBuilder,
         },
-        _ = sigint.recv() => {
-            info!("Received SIGINT. Shutting down...");
+    };
+
+    use super::*;
+
+    #[test]
+    fn config_json_lists_profiles_without_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.json");
+        let profiles =
+            KeyProfile::init_all(path.to_str().unwrap(), true, Some("rhi".into())).unwrap();
+        let config = Settings::default();
+        let relays = config.relays(&["wss://relay.test".to_string()]).unwrap();
+        let keys = profiles[0].keys().unwrap();
+
+        let output = config_json(&config, &relays, &profiles).unwrap();
+
+        assert_eq!(output["relays"][0]["url"], "wss://relay.test");
+        assert_eq!(output["profiles"][0]["identifier"], "rhi");
+        assert_eq!(
+            output["profiles"][0]["npub"],
+            keys.public_key().to_bech32().unwrap()
+        );
+        assert!(
+            !output
+                .to_string()
+                .contains(&keys.secret_key().to_secret_hex())
+        );
+    }
+
+    /// Held by every test that parses `Args`, the only code in this binary that reads the
+    /// RHI_* variables `args_read_env_with_flag_precedence` sets.
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    fn env_lock() -> std::sync::MutexGuard<'static, ()> {
+        ENV_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    #[test]
+    fn price_command_prices_listing_and_order() {
+        let _env = env_lock();
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        let listing = dir.path().join("listing.json");
+        let order = dir.path().join("order.toml");
+        std::fs::write(&config, "").unwrap();
+        let listing_json = json!({
+            "id": nostr::EventId::all_zeros(),
+            "prices": [{
+                "amount": 12.0,
+                "currency": "USD",
+                "quantity_amount": 1.0,
+                "quantity_unit": "kg",
+            }],
+            "quantities": [{ "amount": 500.0, "unit": "g", "label": "bag" }],
+        });
+        std::fs::write(&listing, listing_json.to_string()).unwrap();
+        std::fs::write(
+            &order,
+            r#"
+[quantity]
+amount = 500.0
+unit = "g"
+label = "bag"
+count = 3
+
+[price]
+amount = 12.0
+currency = "USD"
+quantity_amount = 1.0
+quantity_unit = "kg"
+"#,
+        )
+        .unwrap();
+
+        let args = Args::try_parse_from([
+            "rhi",
+            "--config",
+            config.to_str().unwrap(),
+            "price",
+            "--listing",
+            listing.to_str().unwrap(),
+            "--order",
+            order.to_str().unwrap(),
+        ])
+        .unwrap();
+        let config = Settings::load(&args.config).unwrap();
+        let mut out = Vec::new();
+
+        run_command(&config, args.command.as_ref().unwrap(), &mut out).unwrap();
+
+        let result: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(result["order"]["subtotal"]["quantity_amount"], 1500.0);
+        assert_eq!(result["order"]["total"]["price_amount"], 18.0);
+    }
+
+    #[tokio::test]
+    async fn publish_key_profile_skips_unchanged_after_send() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.json");
+        let mut profile = KeyProfile::init(path.to_str().unwrap(), true, None).unwrap();
+        let keys = profile.keys().unwrap();
+        let relay = MockRelay::run().await.unwrap();
+        let config = Settings::default();
+        // Write-only relays are not advertised, so the handler is unchanged across relays.
+        let write_relay = |url: String| {
+            vec![RelaySettings {
+                url,
+                role: RelayRole::Write,
+                auth: false,
+            }]
+        };
+        let relays = write_relay(relay.url().to_string());
+        let audit = AuditLog::default();
+        let dry_run = Settings {
+            dry_run: true,
+            ..Settings::default()
+        };
+
+        publish_key_profile(&mut profile, &keys, &dry_run, &audit, &relays, false)
+            .await
+            .unwrap();
+        assert!(profile.metadata.is_none());
+        assert!(profile.application_handler.is_none());
+
+        publish_key_profile(&mut profile, &keys, &config, &audit, &relays, false)
+            .await
+            .unwrap();
+        assert!(profile.metadata.is_some());
+        assert!(profile.application_handler.is_some());
+
+        // Sending anything would fail to connect to a closed port.
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let unreachable = write_relay(format!("ws://{}", closed.local_addr().unwrap()));
+        drop(closed);
+        publish_key_profile(&mut profile, &keys, &config, &audit, &unreachable, false)
+            .await
+            .unwrap();
+    }
+
+    /// Sends one request addressed to each profile, keyed by the provider expected to answer.
+    async fn send_addressed_requests(
+        requester: &Client,
+        profiles: &[Keys],
+    ) -> HashMap<EventId, PublicKey> {
+        let mut requests = HashMap::new();
+        // An order input that is not JSON makes each profile answer with processing and error
+        // feedback.
+        for keys in profiles {
+            let request = JobRequestBuilder::new()
+                .input(
+                    "not-an-order".to_string(),
+                    JobRequestInputType::Text,
+                    None,
+                    Some(JobRequestInputMarker::Order),
+                )
+                .service_provider(keys.public_key())
+                .build();
+            let sent = requester.send_event_builder(request).await.unwrap();
+            requests.insert(sent.val, keys.public_key());
         }
+        requests
     }
 
-    Ok(())
+    async fn await_answers(
+        notifications: &mut tokio::sync::broadcast::Receiver<RelayPoolNotification>,
+        answered: &mut HashMap<EventId, HashSet<PublicKey>>,
+        requests: &HashMap<EventId, PublicKey>,
+    ) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !requests.keys().all(|id| answered.contains_key(id)) {
+                if let Ok(RelayPoolNotification::Event { event, .. }) = notifications.recv().await {
+                    let request = event
+                        .tags
+                        .event_ids()
+                        .next()
+                        .copied()
+                        .expect("feedback references its request");
+                    answered.entry(request).or_default().insert(event.pubkey);
+                }
+            }
+        })
+        .await
+        .expect("every request answered");
+    }
+
+    #[tokio::test]
+    async fn profiles_answer_only_their_own_requests() {
+        let relay = MockRelay::run().await.unwrap();
+        let relays = vec![RelaySettings {
+            url: relay.url().to_string(),
+            role: RelayRole::Both,
+            auth: false,
+        }];
+        let config = Settings {
+            respond_to_unaddressed: false,
+            ..Settings::default()
+        };
+        let profiles = [Keys::generate(), Keys::generate()];
+        let health = [HealthState::default(), HealthState::default()];
+        let shutdown = CancellationToken::new();
+        let subscribers: Vec<_> = profiles
+            .iter()
+            .zip(&health)
+            .enumerate()
+            .map(|(index, (keys, health))| {
+                spawn_subscriber(
+                    keys.clone(),
+                    relays.clone(),
+                    config.for_profile(index),
+                    health.clone(),
+                    shutdown.clone(),
+                    JobRequestState::from(&config),
+                )
+            })
+            .collect();
+
+        // Subscribers build their filter before connecting, so requests sent once every
+        // profile is ready fall inside it.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            for health in &health {
+                while !health.is_ready().await {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        })
+        .await
+        .expect("every profile connected");
+
+        let requester = Client::builder().signer(Keys::generate()).build();
+        requester.add_relay(relay.url()).await.unwrap();
+        requester.connect().await;
+        requester.wait_for_connection(Duration::from_secs(5)).await;
+        requester
+            .subscribe(Filter::new().kind(Kind::JobFeedback), None)
+            .await
+            .unwrap();
+        let mut notifications = requester.notifications();
+
+        let mut answered = HashMap::new();
+        let requests = send_addressed_requests(&requester, &profiles).await;
+        await_answers(&mut notifications, &mut answered, &requests).await;
+        // Each profile skips requests in relay order, so once both have answered a later
+        // round they have already passed over the other profile's first request.
+        let sentinels = send_addressed_requests(&requester, &profiles).await;
+        await_answers(&mut notifications, &mut answered, &sentinels).await;
+
+        shutdown.cancel();
+        for subscriber in subscribers {
+            subscriber.await.unwrap();
+        }
+
+        for (request, provider) in requests.into_iter().chain(sentinels) {
+            assert_eq!(answered[&request], HashSet::from([provider]));
+        }
+    }
+
+    #[test]
+    fn args_read_env_with_flag_precedence() {
+        let _env = env_lock();
+        // SAFETY: std synchronizes its own environment reads with these writes, and the tests
+        // that read the RHI_* variables, by parsing `Args`, wait on `ENV_LOCK`.
+        unsafe {
+            std::env::set_var("RHI_KEYS", "env-keys.json");
+            std::env::set_var("RHI_RELAYS", "wss://a.test,wss://b.test");
+            std::env::set_var("RHI_CONFIG", "env.toml");
+        }
+
+        let args = Args::try_parse_from(["rhi"]).unwrap();
+        assert_eq!(args.keys.as_deref(), Some("env-keys.json"));
+        assert_eq!(args.relays, ["wss://a.test", "wss://b.test"]);
+        assert_eq!(args.config.as_deref(), Some("env.toml"));
+
+        let args = Args::try_parse_from([
+            "rhi",
+            "--keys",
+            "flag-keys.json",
+            "--relays",
+            "wss://flag.test",
+        ])
+        .unwrap();
+        assert_eq!(args.keys.as_deref(), Some("flag-keys.json"));
+        assert_eq!(args.relays, ["wss://flag.test"]);
+
+        // SAFETY: `ENV_LOCK` is still held, as above.
+        unsafe {
+            std::env::remove_var("RHI_KEYS");
+            std::env::remove_var("RHI_RELAYS");
+            std::env::remove_var("RHI_CONFIG");
+        }
+    }
+
+    #[test]
+    fn print_config_rejects_generate_keys() {
+        let _env = env_lock();
+
+        let err = Args::try_parse_from([
+            "rhi",
+            "--keys",
+            "keys.json",
+            "--print-config",
+            "--generate-keys",
+        ])
+        .err()
+        .unwrap();
+
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
 }
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+mod registry {
+    use std::sync::LazyLock;
+
+    use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+    pub static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+    pub static JOBS_RECEIVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        counter(
+            "rhi_job_requests_received_total",
+            "Job requests received for processing",
+        )
+    });
+
+    pub static JOBS_SUCCEEDED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        counter(
+            "rhi_job_requests_succeeded_total",
+            "Job requests processed successfully",
+        )
+    });
+
+    pub static JOBS_FAILED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        counter(
+            "rhi_job_requests_failed_total",
+            "Job requests that failed processing",
+        )
+    });
+
+    pub static JOB_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+        let histogram = HistogramVec::new(
+            HistogramOpts::new(
+                "rhi_job_request_duration_seconds",
+                "Job request handler latency",
+            ),
+            &["marker"],
+        )
+        .expect("valid histogram");
+        REGISTRY
+            .register(Box::new(histogram.clone()))
+            .expect("unique histogram");
+        histogram
+    });
+
+    fn counter(name: &str, help: &str) -> IntCounterVec {
+        let counter =
+            IntCounterVec::new(Opts::new(name, help), &["marker"]).expect("valid counter");
+        REGISTRY
+            .register(Box::new(counter.clone()))
+            .expect("unique counter");
+        counter
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub fn job_received(marker: &str) {
+    registry::JOBS_RECEIVED.with_label_values(&[marker]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn job_succeeded(marker: &str, duration: Duration) {
+    registry::JOBS_SUCCEEDED.with_label_values(&[marker]).inc();
+    registry::JOB_DURATION
+        .with_label_values(&[marker])
+        .observe(duration.as_secs_f64());
+}
+
+#[cfg(feature = "metrics")]
+pub fn job_failed(marker: &str, duration: Duration) {
+    registry::JOBS_FAILED.with_label_values(&[marker]).inc();
+    registry::JOB_DURATION
+        .with_label_values(&[marker])
+        .observe(duration.as_secs_f64());
+}
+
+#[cfg(feature = "metrics")]
+pub fn render() -> Option<String> {
+    use prometheus::{Encoder, TextEncoder};
+
+    let mut buf = Vec::new();
+    TextEncoder::new()
+        .encode(&registry::REGISTRY.gather(), &mut buf)
+        .ok()?;
+    String::from_utf8(buf).ok()
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn job_received(_marker: &str) {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn job_succeeded(_marker: &str, _duration: Duration) {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn job_failed(_marker: &str, _duration: Duration) {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn render() -> Option<String> {
+    None
+}
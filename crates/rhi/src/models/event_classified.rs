@@ -1,26 +1,26 @@
 use anyhow::Result;
-use nostr::{EventId, event::Event};
+use nostr::{EventId, Timestamp, event::Event};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+use typeshare::typeshare;
 
 use crate::{
+    config::{PricePolicy, RoundingMode, SettingsError, TierSelection, config_file_load},
     handlers::job_request_order::JobRequestOrderError,
+    models::{order_explanation::OrderExplanation, order_result::OrderClassifiedResult},
     utils::{
+        geo::{geohash_decode, is_valid_geohash},
         nostr::{
-            nostr_tag_match_geohash, nostr_tag_match_l, nostr_tag_match_location,
-            nostr_tag_match_summary, nostr_tag_match_title, nostr_tags_match,
+            nostr_tag_match_expiration, nostr_tag_match_geohash, nostr_tag_match_l,
+            nostr_tag_match_location, nostr_tag_match_summary, nostr_tag_match_title,
+            nostr_tags_match,
         },
-        unit::{MassUnit, convert_mass},
+        price::{OrderSpec, PRICE_REL_TOLERANCE, approx_eq, explain_order, price_order},
+        unit::{MassUnit, Unit, convert_mass},
     },
 };
 
-use radroots_common::models::{
-    listing_order::{
-        ListingOrder, ListingOrderDiscount, ListingOrderPrice, ListingOrderQuantity,
-        ListingOrderSubtotal, ListingOrderTotal,
-    },
-    listing_order_request::ListingOrderRequestPayload,
-};
-
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct EventClassifiedGeolocation {
     pub geohash: Option<String>,
@@ -28,6 +28,7 @@ pub struct EventClassifiedGeolocation {
     pub lng: f64,
 }
 
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct EventClassifiedLocation {
     pub address: String,
@@ -35,6 +36,7 @@ pub struct EventClassifiedLocation {
     pub country: String,
 }
 
+// Internally tagged to keep the wire format, shared through `EventClassifiedDiscountWire`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type")]
 pub enum EventClassifiedDiscount {
@@ -44,6 +46,10 @@ pub enum EventClassifiedDiscount {
         currency: String,
         value: f64,
         is_percent: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        valid_from: Option<Timestamp>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        valid_until: Option<Timestamp>,
     },
     #[serde(rename = "mass")]
     Mass {
@@ -52,6 +58,12 @@ pub enum EventClassifiedDiscount {
         threshold_unit: String,
         discount_per_unit: f64,
         currency: String,
+        #[serde(default)]
+        is_percent: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        valid_from: Option<Timestamp>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        valid_until: Option<Timestamp>,
     },
     #[serde(rename = "quantity")]
     Quantity {
@@ -59,24 +71,202 @@ pub enum EventClassifiedDiscount {
         min_count: u32,
         discount_per_unit: f64,
         currency: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        valid_from: Option<Timestamp>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        valid_until: Option<Timestamp>,
+    },
+}
+
+impl EventClassifiedDiscount {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Subtotal {
+                threshold,
+                currency,
+                value,
+                is_percent,
+                ..
+            } => {
+                if *is_percent {
+                    format!("{value}% off subtotals of {threshold} {currency} or more")
+                } else {
+                    format!("{value} {currency} off subtotals of {threshold} {currency} or more")
+                }
+            }
+            Self::Mass {
+                discount_unit,
+                threshold,
+                threshold_unit,
+                discount_per_unit,
+                currency,
+                is_percent,
+                ..
+            } => {
+                if *is_percent {
+                    format!(
+                        "{discount_per_unit}% off orders of {threshold} {threshold_unit} or more"
+                    )
+                } else {
+                    format!(
+                        "{discount_per_unit} {currency} off per {discount_unit} on orders of {threshold} {threshold_unit} or more"
+                    )
+                }
+            }
+            Self::Quantity {
+                product_key,
+                min_count,
+                discount_per_unit,
+                currency,
+                ..
+            } => format!(
+                "{discount_per_unit} {currency} off each {product_key} when buying {min_count} or more"
+            ),
+        }
+    }
+
+    pub fn is_active(&self, now: Timestamp) -> bool {
+        let (valid_from, valid_until) = self.window();
+        valid_from.is_none_or(|from| now >= from) && valid_until.is_none_or(|until| now < until)
+    }
+
+    /// When the discount starts and stops applying, open-ended where unset.
+    pub fn window(&self) -> (Option<Timestamp>, Option<Timestamp>) {
+        match self {
+            Self::Subtotal {
+                valid_from,
+                valid_until,
+                ..
+            }
+            | Self::Mass {
+                valid_from,
+                valid_until,
+                ..
+            }
+            | Self::Quantity {
+                valid_from,
+                valid_until,
+                ..
+            } => (*valid_from, *valid_until),
+        }
+    }
+}
+
+fn discount_window_at(values: &[String], index: usize) -> Option<Timestamp> {
+    values
+        .get(index)
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Timestamp::from)
+}
+
+// Internally tagged like `EventClassifiedDiscount`, shared through `EventClassifiedShippingWire`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum EventClassifiedShipping {
+    #[serde(rename = "flat")]
+    Flat { amount: f64, currency: String },
+    #[serde(rename = "per-kg")]
+    PerKg {
+        amount_per_kg: f64,
+        currency: String,
+    },
+    #[serde(rename = "free-above")]
+    FreeAbove {
+        threshold: f64,
+        threshold_unit: MassUnit,
+        amount: f64,
+        currency: String,
     },
 }
 
+impl EventClassifiedShipping {
+    pub fn currency(&self) -> &str {
+        match self {
+            Self::Flat { currency, .. }
+            | Self::PerKg { currency, .. }
+            | Self::FreeAbove { currency, .. } => currency,
+        }
+    }
+
+    pub fn amount(&self, total_kg: f64) -> f64 {
+        match self {
+            Self::Flat { amount, .. } => *amount,
+            Self::PerKg { amount_per_kg, .. } => amount_per_kg * total_kg,
+            Self::FreeAbove {
+                threshold,
+                threshold_unit,
+                amount,
+                ..
+            } => {
+                if convert_mass(total_kg, &MassUnit::Kg, threshold_unit) >= *threshold {
+                    0.0
+                } else {
+                    *amount
+                }
+            }
+        }
+    }
+
+    fn from_tag_values(values: &[String]) -> Option<Self> {
+        match values {
+            [kind, amount, currency, ..] if kind == "flat" => Some(Self::Flat {
+                amount: amount.parse().ok()?,
+                currency: currency.clone(),
+            }),
+            [kind, amount, currency, ..] if kind == "per-kg" => Some(Self::PerKg {
+                amount_per_kg: amount.parse().ok()?,
+                currency: currency.clone(),
+            }),
+            [kind, threshold, threshold_unit, amount, currency, ..] if kind == "free-above" => {
+                Some(Self::FreeAbove {
+                    threshold: threshold.parse().ok()?,
+                    threshold_unit: threshold_unit.to_lowercase().parse().ok()?,
+                    amount: amount.parse().ok()?,
+                    currency: currency.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EventClassifiedQuantity {
     pub amount: f64,
-    pub unit: MassUnit,
+    pub unit: Unit,
     pub label: String,
 }
 
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EventClassifiedStock {
+    pub amount: f64,
+    pub unit: Unit,
+}
+
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EventClassifiedPrice {
     pub amount: f64,
     pub currency: String,
     pub quantity_amount: f64,
-    pub quantity_unit: MassUnit,
+    pub quantity_unit: Unit,
 }
 
+impl EventClassifiedPrice {
+    pub fn same_tier(&self, other: &EventClassifiedPrice) -> bool {
+        self.quantity_unit == other.quantity_unit
+            && approx_eq(
+                self.quantity_amount,
+                other.quantity_amount,
+                PRICE_REL_TOLERANCE,
+            )
+            && self.currency.eq_ignore_ascii_case(&other.currency)
+    }
+}
+
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct EventClassifiedListing {
     pub key: String,
@@ -87,26 +277,105 @@ pub struct EventClassifiedListing {
     pub year: Option<String>,
 }
 
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct EventClassifiedBasis {
     pub title: String,
     pub summary: String,
 }
 
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EventClassified {
+    #[typeshare(serialized_as = "String")]
     pub id: EventId,
+    #[serde(default)]
     pub basis: EventClassifiedBasis,
+    #[serde(default)]
     pub listing: EventClassifiedListing,
     pub prices: Vec<EventClassifiedPrice>,
     pub quantities: Vec<EventClassifiedQuantity>,
+    #[serde(default)]
+    #[typeshare(serialized_as = "Vec<EventClassifiedDiscountWire>")]
     pub discounts: Vec<EventClassifiedDiscount>,
     pub location: Option<EventClassifiedLocation>,
     pub geolocation: Option<EventClassifiedGeolocation>,
+    #[typeshare(serialized_as = "Option<number>")]
+    pub expires_at: Option<Timestamp>,
+    pub tax_rate_percent: Option<f64>,
+    #[typeshare(serialized_as = "Option<EventClassifiedShippingWire>")]
+    pub shipping: Option<EventClassifiedShipping>,
+    #[serde(default)]
+    pub ambiguous_prices: Vec<EventClassifiedPrice>,
+    pub min_order: Option<u32>,
+    pub max_order: Option<u32>,
+    pub stock: Option<EventClassifiedStock>,
+    #[serde(default)]
+    pub accepted_currencies: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderContext {
+    pub now: Timestamp,
+    pub tax_rate_percent: Option<f64>,
+    pub rounding: RoundingMode,
+    pub price_policy: PricePolicy,
+    pub tier_selection: TierSelection,
+    pub currency: Option<String>,
+}
+
+impl OrderContext {
+    pub fn now() -> Self {
+        Self {
+            now: Timestamp::now(),
+            tax_rate_percent: None,
+            rounding: RoundingMode::default(),
+            price_policy: PricePolicy::default(),
+            tier_selection: TierSelection::default(),
+            currency: None,
+        }
+    }
 }
 
 impl EventClassified {
-    pub fn from_event(event: &Event) -> Result<Self> {
+    pub fn new(
+        id: EventId,
+        basis: EventClassifiedBasis,
+        listing: EventClassifiedListing,
+        prices: Vec<EventClassifiedPrice>,
+        quantities: Vec<EventClassifiedQuantity>,
+        discounts: Vec<EventClassifiedDiscount>,
+    ) -> Self {
+        Self {
+            id,
+            basis,
+            listing,
+            prices,
+            quantities,
+            discounts,
+            location: None,
+            geolocation: None,
+            expires_at: None,
+            tax_rate_percent: None,
+            shipping: None,
+            ambiguous_prices: Vec::new(),
+            min_order: None,
+            max_order: None,
+            stock: None,
+            accepted_currencies: Vec::new(),
+        }
+    }
+
+    pub fn is_listing(&self) -> bool {
+        !self.prices.is_empty() && !self.quantities.is_empty()
+    }
+
+    pub fn from_event(
+        event: &Event,
+        default_currency: Option<&str>,
+        default_unit: Option<&Unit>,
+        min_geohash_precision: Option<usize>,
+    ) -> Result<Self> {
         let mut prices = Vec::new();
         let mut quantities = Vec::new();
         let mut basis = EventClassifiedBasis::default();
@@ -119,6 +388,14 @@ impl EventClassified {
         let mut lng: Option<f64> = None;
         let mut geohash: Option<String> = None;
         let mut discounts: Vec<EventClassifiedDiscount> = Vec::new();
+        let mut expires_at: Option<Timestamp> = None;
+        let mut tax_rate_percent: Option<f64> = None;
+        let mut shipping: Option<EventClassifiedShipping> = None;
+        let mut ambiguous_prices: Vec<EventClassifiedPrice> = Vec::new();
+        let mut min_order: Option<u32> = None;
+        let mut max_order: Option<u32> = None;
+        let mut stock: Option<EventClassifiedStock> = None;
+        let mut accepted_currencies: Vec<String> = Vec::new();
 
         for tag in event.tags.iter() {
             if let Some((key, values)) = nostr_tags_match(tag) {
@@ -128,9 +405,18 @@ impl EventClassified {
                         let unit_str = &values[1];
                         let label = &values[2];
 
-                        if let (Ok(amount), Ok(unit)) =
-                            (amount_str.parse::<f64>(), unit_str.parse::<MassUnit>())
-                        {
+                        let unit = match (unit_str.is_empty(), default_unit) {
+                            (true, Some(unit)) => {
+                                warn!(
+                                    "classified {} quantity is missing a unit, using default {unit}",
+                                    event.id
+                                );
+                                Ok(unit.clone())
+                            }
+                            _ => unit_str.parse::<Unit>(),
+                        };
+
+                        if let (Ok(amount), Ok(unit)) = (amount_str.parse::<f64>(), unit) {
                             quantities.push(EventClassifiedQuantity {
                                 amount,
                                 unit,
@@ -140,21 +426,46 @@ impl EventClassified {
                     }
                     "price" if values.len() >= 4 => {
                         let amount_str = &values[0];
-                        let currency = &values[1];
+                        let currency = match (values[1].is_empty(), default_currency) {
+                            (true, Some(currency)) => {
+                                warn!(
+                                    "classified {} price is missing a currency, using default {currency}",
+                                    event.id
+                                );
+                                currency
+                            }
+                            _ => values[1].as_str(),
+                        };
                         let quantity_amount_str = &values[2];
                         let quantity_unit_str = &values[3];
 
                         if let (Ok(amount), Ok(quantity_amount), Ok(quantity_unit)) = (
                             amount_str.parse::<f64>(),
                             quantity_amount_str.parse::<f64>(),
-                            quantity_unit_str.to_lowercase().parse::<MassUnit>(),
+                            quantity_unit_str.to_lowercase().parse::<Unit>(),
                         ) {
-                            prices.push(EventClassifiedPrice {
+                            let price = EventClassifiedPrice {
                                 amount,
-                                currency: currency.clone(),
+                                currency: currency.to_string(),
                                 quantity_amount,
                                 quantity_unit,
-                            });
+                            };
+
+                            match prices.iter().find(|p| p.same_tier(&price)) {
+                                Some(p)
+                                    if approx_eq(p.amount, price.amount, PRICE_REL_TOLERANCE) => {}
+                                Some(_) => {
+                                    warn!(
+                                        "classified {} has conflicting price tiers for {} {} {}",
+                                        event.id,
+                                        price.quantity_amount,
+                                        price.quantity_unit,
+                                        price.currency
+                                    );
+                                    ambiguous_prices.push(price);
+                                }
+                                None => prices.push(price),
+                            }
                         }
                     }
                     "key" if !values.is_empty() => listing.key = values[0].clone(),
@@ -163,6 +474,36 @@ impl EventClassified {
                     "lot" if !values.is_empty() => listing.lot = Some(values[0].clone()),
                     "profile" if !values.is_empty() => listing.profile = Some(values[0].clone()),
                     "year" if !values.is_empty() => listing.year = Some(values[0].clone()),
+                    "price-tax" if !values.is_empty() => {
+                        tax_rate_percent = values[0].parse().ok();
+                    }
+                    "min-order" if !values.is_empty() => {
+                        min_order = values[0].parse().ok();
+                    }
+                    "max-order" if !values.is_empty() => {
+                        max_order = values[0].parse().ok();
+                    }
+                    "stock" if values.len() >= 2 => {
+                        if let (Ok(amount), Ok(unit)) =
+                            (values[0].parse::<f64>(), values[1].parse::<Unit>())
+                        {
+                            stock = Some(EventClassifiedStock { amount, unit });
+                        }
+                    }
+                    "accepted-currencies" => {
+                        accepted_currencies.extend(
+                            values
+                                .iter()
+                                .flat_map(|v| v.split(','))
+                                .map(|c| c.trim().to_string())
+                                .filter(|c| !c.is_empty()),
+                        );
+                    }
+                    "price-shipping" => {
+                        if let Some(s) = EventClassifiedShipping::from_tag_values(values) {
+                            shipping = Some(s);
+                        }
+                    }
                     "price-discount-subtotal" if values.len() >= 4 => {
                         let threshold = values[0].parse().unwrap_or(0.0);
                         let currency = values[1].clone();
@@ -173,6 +514,8 @@ impl EventClassified {
                             currency,
                             value,
                             is_percent,
+                            valid_from: discount_window_at(values, 4),
+                            valid_until: discount_window_at(values, 5),
                         });
                     }
                     "price-discount-mass" if values.len() >= 5 => {
@@ -181,12 +524,16 @@ impl EventClassified {
                         let threshold_unit = values[2].clone();
                         let discount_per_unit = values[3].parse().unwrap_or(0.0);
                         let currency = values[4].clone();
+                        let is_percent = values.get(5).is_some_and(|v| v == "%");
                         discounts.push(EventClassifiedDiscount::Mass {
                             discount_unit,
                             threshold,
                             threshold_unit,
                             discount_per_unit,
                             currency,
+                            is_percent,
+                            valid_from: discount_window_at(values, 6),
+                            valid_until: discount_window_at(values, 7),
                         });
                     }
                     "price-discount-quantity" if values.len() >= 4 => {
@@ -199,6 +546,8 @@ impl EventClassified {
                             min_count,
                             discount_per_unit,
                             currency,
+                            valid_from: discount_window_at(values, 4),
+                            valid_until: discount_window_at(values, 5),
                         });
                     }
                     _ => {}
@@ -209,7 +558,7 @@ impl EventClassified {
                 let precision = value.to_string().split('.').nth(1).map_or(0, |s| s.len());
 
                 match kind {
-                    "dd.lat" => {
+                    "dd.lat" if (-90.0..=90.0).contains(&value) => {
                         let current_precision = lat
                             .map(|v| v.to_string().split('.').nth(1).map_or(0, |s| s.len()))
                             .unwrap_or(0);
@@ -217,7 +566,7 @@ impl EventClassified {
                             lat = Some(value);
                         }
                     }
-                    "dd.lon" => {
+                    "dd.lon" if (-180.0..=180.0).contains(&value) => {
                         let current_precision = lng
                             .map(|v| v.to_string().split('.').nth(1).map_or(0, |s| s.len()))
                             .unwrap_or(0);
@@ -235,7 +584,7 @@ impl EventClassified {
                 country = Some(coun.to_string());
             }
 
-            if let Some(g) = nostr_tag_match_geohash(tag) {
+            if let Some(g) = nostr_tag_match_geohash(tag).filter(|g| is_valid_geohash(g)) {
                 if geohash
                     .as_ref()
                     .map_or(true, |current| g.len() > current.len())
@@ -251,6 +600,10 @@ impl EventClassified {
             if let Some(summary) = nostr_tag_match_summary(tag) {
                 basis.summary = summary;
             }
+
+            if let Some(timestamp) = nostr_tag_match_expiration(tag) {
+                expires_at = Some(timestamp);
+            }
         }
 
         let location = if address.is_some() || region.is_some() || country.is_some() {
@@ -263,10 +616,32 @@ impl EventClassified {
             None
         };
 
-        let geolocation = if let (Some(lat), Some(lng)) = (lat, lng) {
-            Some(EventClassifiedGeolocation { geohash, lat, lng })
-        } else {
-            None
+        let decoded = geohash.as_deref().and_then(geohash_decode);
+        let imprecise = match (&geohash, min_geohash_precision) {
+            (Some(g), Some(min)) => g.len() < min,
+            _ => false,
+        };
+
+        let geolocation = match (lat, lng, decoded) {
+            (Some(lat), Some(lng), Some((geo_lat, geo_lng, err))) => {
+                if (lat - geo_lat).abs() > err || (lng - geo_lng).abs() > err {
+                    warn!(
+                        "classified {} coordinates {},{} fall outside geohash {:?}",
+                        event.id, lat, lng, geohash
+                    );
+                }
+                Some(EventClassifiedGeolocation { geohash, lat, lng })
+            }
+            (Some(lat), Some(lng), None) => Some(EventClassifiedGeolocation { geohash, lat, lng }),
+            (_, _, Some(_)) if imprecise => {
+                warn!(
+                    "classified {} geohash {:?} below minimum precision {:?}, ignoring geolocation",
+                    event.id, geohash, min_geohash_precision
+                );
+                None
+            }
+            (_, _, Some((lat, lng, _))) => Some(EventClassifiedGeolocation { geohash, lat, lng }),
+            _ => None,
         };
 
         Ok(Self {
@@ -278,184 +653,494 @@ impl EventClassified {
             discounts,
             location,
             geolocation,
+            expires_at,
+            tax_rate_percent,
+            shipping,
+            ambiguous_prices,
+            min_order,
+            max_order,
+            stock,
+            accepted_currencies,
         })
     }
 
+    pub fn accepted_currencies(&self) -> Vec<String> {
+        if !self.accepted_currencies.is_empty() {
+            return self.accepted_currencies.clone();
+        }
+
+        let mut currencies: Vec<String> = Vec::new();
+        for price in &self.prices {
+            if !currencies
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(&price.currency))
+            {
+                currencies.push(price.currency.clone());
+            }
+        }
+        currencies
+    }
+
+    pub fn accepts_currency(&self, currency: &str) -> bool {
+        self.accepted_currencies()
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(currency))
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, SettingsError> {
+        config_file_load(path)
+    }
+
     pub fn calculate_order(
         &self,
-        order: &ListingOrderRequestPayload,
-    ) -> Result<ListingOrder, JobRequestOrderError> {
-        let quantity = &order.quantity;
-        let price = &order.price;
-
-        let qty_unit = quantity
-            .unit
-            .parse::<MassUnit>()
-            .map_err(|_| JobRequestOrderError::Unsatisfiable("invalid quantity unit".into()))?;
-        let price_unit = price.quantity_unit.parse::<MassUnit>().map_err(|_| {
-            JobRequestOrderError::Unsatisfiable("invalid price quantity unit".into())
-        })?;
-
-        let total_qty = quantity.amount * quantity.count as f64;
-
-        let matched_packaging = self
-            .quantities
-            .iter()
-            .any(|q| q.unit == qty_unit && (q.amount - quantity.amount).abs() < f64::EPSILON);
+        order: &OrderSpec,
+        ctx: &OrderContext,
+    ) -> Result<OrderClassifiedResult, JobRequestOrderError> {
+        price_order(self, order, ctx)
+    }
 
-        if !matched_packaging {
-            return Err(JobRequestOrderError::Unsatisfiable(format!(
-                "requested packaging {} {} not available",
-                quantity.amount, quantity.unit
-            )));
-        }
+    pub fn explain_order(
+        &self,
+        order: &OrderSpec,
+        ctx: &OrderContext,
+    ) -> Result<OrderExplanation, JobRequestOrderError> {
+        explain_order(self, order, ctx)
+    }
+}
 
-        let matched_tier = self.prices.iter().find(|p| {
-            p.quantity_unit == price_unit
-                && (p.quantity_amount - price.quantity_amount).abs() < f64::EPSILON
-                && p.currency.to_lowercase() == price.currency.to_lowercase()
-        });
-
-        let tier = matched_tier.ok_or_else(|| {
-            JobRequestOrderError::Unsatisfiable(format!(
-                "no matching price tier {} {} found",
-                price.quantity_amount, price.quantity_unit
-            ))
-        })?;
-
-        if (tier.amount - price.amount).abs() > f64::EPSILON {
-            return Err(JobRequestOrderError::Unsatisfiable(format!(
-                "price mismatch: expected {}, got {}",
-                tier.amount, price.amount
-            )));
-        }
+#[cfg(test)]
+mod tests {
+    use nostr::{
+        event::{EventBuilder, Kind, Tag, TagKind, TagStandard},
+        key::Keys,
+    };
+
+    use super::*;
+    use crate::handlers::job_request_order::OrderUnsatisfiable;
+
+    fn classified_event(tags: Vec<Tag>) -> Event {
+        EventBuilder::new(Kind::Custom(30402), "")
+            .tags(tags)
+            .sign_with_keys(&Keys::generate())
+            .unwrap()
+    }
 
-        let converted_qty = convert_mass(total_qty, &qty_unit, &price_unit);
-        let unit_price = tier.amount / tier.quantity_amount;
-        let subtotal = (unit_price * converted_qty * 100.0).round() / 100.0;
+    fn geohash_tag(geohash: &str) -> Tag {
+        Tag::from_standardized(TagStandard::Geohash(geohash.into()))
+    }
+
+    #[test]
+    fn discount_descriptions() {
+        let subtotal = |is_percent| EventClassifiedDiscount::Subtotal {
+            threshold: 50.0,
+            currency: "USD".into(),
+            value: 10.0,
+            is_percent,
+            valid_from: None,
+            valid_until: None,
+        };
+        let mass = |is_percent| EventClassifiedDiscount::Mass {
+            discount_unit: "kg".into(),
+            threshold: 5.0,
+            threshold_unit: "kg".into(),
+            discount_per_unit: 2.5,
+            currency: "USD".into(),
+            is_percent,
+            valid_from: None,
+            valid_until: None,
+        };
+        let quantity = EventClassifiedDiscount::Quantity {
+            product_key: "coffee".into(),
+            min_count: 3,
+            discount_per_unit: 1.0,
+            currency: "EUR".into(),
+            valid_from: None,
+            valid_until: None,
+        };
 
-        let mut discounts: Vec<ListingOrderDiscount> = Vec::new();
-        let package_key = format!(
-            "{}-{}-{}",
-            quantity.amount,
-            quantity.unit.to_lowercase(),
-            quantity.label
+        assert_eq!(
+            subtotal(true).describe(),
+            "10% off subtotals of 50 USD or more"
+        );
+        assert_eq!(
+            subtotal(false).describe(),
+            "10 USD off subtotals of 50 USD or more"
+        );
+        assert_eq!(mass(true).describe(), "2.5% off orders of 5 kg or more");
+        assert_eq!(
+            mass(false).describe(),
+            "2.5 USD off per kg on orders of 5 kg or more"
         );
+        assert_eq!(
+            quantity.describe(),
+            "1 EUR off each coffee when buying 3 or more"
+        );
+    }
 
-        for d in &self.discounts {
-            match d {
-                EventClassifiedDiscount::Subtotal {
-                    threshold,
-                    currency,
-                    value,
-                    is_percent,
-                } => {
-                    if subtotal < *threshold {
-                        continue;
-                    }
-                    let amt = if *is_percent {
-                        (subtotal * value / 100.0 * 100.0).round() / 100.0
-                    } else {
-                        (*value * 100.0).round() / 100.0
-                    };
-                    discounts.push(ListingOrderDiscount {
-                        discount_type: "subtotal".into(),
-                        threshold: Some(*threshold),
-                        threshold_unit: None,
-                        discount_per_unit: None,
-                        discount_unit: None,
-                        discount_percent: if *is_percent { Some(*value) } else { None },
-                        discount_amount: amt,
-                        currency: currency.clone(),
-                    });
-                }
-                EventClassifiedDiscount::Mass {
-                    discount_unit,
-                    threshold,
-                    threshold_unit,
-                    discount_per_unit,
-                    currency,
-                } => {
-                    let th_unit = threshold_unit.parse::<MassUnit>().map_err(|_| {
-                        JobRequestOrderError::Unsatisfiable("invalid threshold unit".into())
-                    })?;
-                    let dis_unit = discount_unit.parse::<MassUnit>().map_err(|_| {
-                        JobRequestOrderError::Unsatisfiable("invalid discount unit".into())
-                    })?;
-
-                    let qty_in_th = convert_mass(total_qty, &qty_unit, &th_unit);
-                    if qty_in_th < *threshold {
-                        continue;
-                    }
+    #[test]
+    fn from_event_prefers_longest_valid_geohash() {
+        let event = classified_event(vec![
+            l_tag(57.64911, "dd.lat"),
+            l_tag(10.40744, "dd.lon"),
+            geohash_tag("u4pr"),
+            geohash_tag("u4pruydqqvj"),
+            geohash_tag("hello!hello!"),
+        ]);
 
-                    let qty_in_dis = convert_mass(total_qty, &qty_unit, &dis_unit);
-                    let amt = (qty_in_dis * discount_per_unit * 100.0).round() / 100.0;
-
-                    discounts.push(ListingOrderDiscount {
-                        discount_type: "mass".into(),
-                        threshold: Some(*threshold),
-                        threshold_unit: Some(threshold_unit.clone()),
-                        discount_per_unit: Some(*discount_per_unit),
-                        discount_unit: Some(discount_unit.clone()),
-                        discount_percent: None,
-                        discount_amount: amt,
-                        currency: currency.clone(),
-                    });
-                }
-                EventClassifiedDiscount::Quantity {
-                    product_key,
-                    min_count,
-                    discount_per_unit,
-                    currency,
-                } => {
-                    if product_key != &package_key || quantity.count < *min_count {
-                        continue;
-                    }
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
 
-                    let amt = (*discount_per_unit * quantity.count as f64 * 100.0).round() / 100.0;
-
-                    discounts.push(ListingOrderDiscount {
-                        discount_type: "quantity".into(),
-                        threshold: Some(*min_count as f64),
-                        threshold_unit: None,
-                        discount_per_unit: Some(*discount_per_unit),
-                        discount_unit: None,
-                        discount_percent: None,
-                        discount_amount: amt,
-                        currency: currency.clone(),
-                    });
-                }
+        let geolocation = classified.geolocation.unwrap();
+        assert_eq!(geolocation.geohash.as_deref(), Some("u4pruydqqvj"));
+    }
+
+    #[test]
+    fn from_event_rejects_only_invalid_geohash() {
+        let event = classified_event(vec![
+            l_tag(57.64911, "dd.lat"),
+            l_tag(10.40744, "dd.lon"),
+            geohash_tag("hello!"),
+        ]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        let geolocation = classified.geolocation.unwrap();
+        assert_eq!(geolocation.lat, 57.64911);
+        assert!(geolocation.geohash.is_none());
+    }
+
+    #[test]
+    fn from_event_ignores_geohash_below_min_precision() {
+        let event = classified_event(vec![geohash_tag("u4pr")]);
+
+        let classified = EventClassified::from_event(&event, None, None, Some(5)).unwrap();
+        assert!(classified.geolocation.is_none());
+
+        let classified = EventClassified::from_event(&event, None, None, Some(4)).unwrap();
+        assert!(classified.geolocation.is_some());
+
+        let event = classified_event(vec![geohash_tag("u4pruydqqvj")]);
+        let classified = EventClassified::from_event(&event, None, None, Some(5)).unwrap();
+        assert!(classified.geolocation.is_some());
+    }
+
+    #[test]
+    fn from_event_keeps_tagged_coordinates_with_imprecise_geohash() {
+        let event = classified_event(vec![
+            geohash_tag("u4"),
+            l_tag(57.64911, "dd.lat"),
+            l_tag(10.40744, "dd.lon"),
+        ]);
+
+        let classified = EventClassified::from_event(&event, None, None, Some(5)).unwrap();
+
+        let geolocation = classified.geolocation.unwrap();
+        assert_eq!(geolocation.lat, 57.64911);
+        assert_eq!(geolocation.lng, 10.40744);
+    }
+
+    fn l_tag(value: f64, label: &str) -> Tag {
+        Tag::custom(
+            TagKind::Custom("l".into()),
+            [value.to_string(), label.to_string()],
+        )
+    }
+
+    #[test]
+    fn from_event_derives_coordinates_from_geohash() {
+        let event = classified_event(vec![geohash_tag("u4pruydqqvj")]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        let geolocation = classified.geolocation.unwrap();
+        assert!((geolocation.lat - 57.64911).abs() < 1e-4);
+        assert!((geolocation.lng - 10.40744).abs() < 1e-4);
+    }
+
+    #[test]
+    fn from_event_keeps_tagged_coordinates_over_geohash() {
+        let event = classified_event(vec![
+            geohash_tag("u4pruydqqvj"),
+            l_tag(40.7128, "dd.lat"),
+            l_tag(-74.006, "dd.lon"),
+        ]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        let geolocation = classified.geolocation.unwrap();
+        assert_eq!(geolocation.lat, 40.7128);
+        assert_eq!(geolocation.lng, -74.006);
+        assert_eq!(geolocation.geohash.as_deref(), Some("u4pruydqqvj"));
+    }
+
+    #[test]
+    fn from_event_ignores_out_of_range_coordinates() {
+        let event = classified_event(vec![l_tag(91.0, "dd.lat"), l_tag(-74.006, "dd.lon")]);
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+        assert!(classified.geolocation.is_none());
+
+        let event = classified_event(vec![
+            geohash_tag("u4pruydqqvj"),
+            l_tag(40.7128, "dd.lat"),
+            l_tag(-181.0, "dd.lon"),
+        ]);
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+        let geolocation = classified.geolocation.unwrap();
+        assert!((geolocation.lng - 10.40744).abs() < 1e-4);
+
+        let event = classified_event(vec![l_tag(-90.0, "dd.lat"), l_tag(180.0, "dd.lon")]);
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+        let geolocation = classified.geolocation.unwrap();
+        assert_eq!((geolocation.lat, geolocation.lng), (-90.0, 180.0));
+    }
+
+    #[test]
+    fn discount_wire_format_internally_tagged() {
+        let discount = EventClassifiedDiscount::Quantity {
+            product_key: "bag".into(),
+            min_count: 3,
+            discount_per_unit: 1.0,
+            currency: "USD".into(),
+            valid_from: None,
+            valid_until: Some(Timestamp::from(1_700_000_000)),
+        };
+
+        let value = serde_json::to_value(&discount).unwrap();
+
+        assert_eq!(value["type"], "quantity");
+        assert_eq!(value["min_count"], 3);
+        assert_eq!(value["valid_until"], 1_700_000_000);
+        assert!(value.get("content").is_none());
+    }
+
+    #[test]
+    fn shipping_wire_format_reads_previous_shape() {
+        let shipping: EventClassifiedShipping =
+            serde_json::from_str(r#"{"type":"flat","amount":5.0,"currency":"USD"}"#).unwrap();
+
+        assert!(matches!(
+            shipping,
+            EventClassifiedShipping::Flat { amount, .. } if amount == 5.0
+        ));
+        assert_eq!(serde_json::to_value(&shipping).unwrap()["type"], "flat");
+    }
+
+    #[test]
+    fn shipping_from_tag_values() {
+        let values = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert!(matches!(
+            EventClassifiedShipping::from_tag_values(&values(&["flat", "5", "USD"])),
+            Some(EventClassifiedShipping::Flat { amount, .. }) if amount == 5.0
+        ));
+        assert!(matches!(
+            EventClassifiedShipping::from_tag_values(&values(&[
+                "free-above",
+                "2",
+                "KG",
+                "5",
+                "USD"
+            ])),
+            Some(EventClassifiedShipping::FreeAbove {
+                threshold_unit: MassUnit::Kg,
+                ..
+            })
+        ));
+        assert!(
+            EventClassifiedShipping::from_tag_values(&values(&["per-kg", "x", "USD"])).is_none()
+        );
+        assert!(
+            EventClassifiedShipping::from_tag_values(&values(&["drone", "5", "USD"])).is_none()
+        );
+    }
+
+    fn custom_tag(key: &str, values: &[&str]) -> Tag {
+        Tag::custom(TagKind::custom(key.to_string()), values.iter().copied())
+    }
+
+    #[test]
+    fn from_event_reads_order_bounds() {
+        let event = classified_event(vec![
+            custom_tag("min-order", &["2"]),
+            custom_tag("max-order", &["many"]),
+        ]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert_eq!(classified.min_order, Some(2));
+        assert_eq!(classified.max_order, None);
+    }
+
+    #[test]
+    fn from_event_reads_stock() {
+        let event = classified_event(vec![custom_tag("stock", &["2.5", "kg"])]);
+
+        let stock = EventClassified::from_event(&event, None, None, None)
+            .unwrap()
+            .stock
+            .unwrap();
+
+        assert_eq!(stock.amount, 2.5);
+        assert_eq!(stock.unit, Unit::Mass(MassUnit::Kg));
+    }
+
+    #[test]
+    fn from_event_applies_default_currency_and_unit() {
+        let event = classified_event(vec![
+            custom_tag("price", &["12", "", "1", "kg"]),
+            custom_tag("quantity", &["500", "", "bag"]),
+        ]);
+
+        let classified =
+            EventClassified::from_event(&event, Some("USD"), Some(&Unit::Mass(MassUnit::G)), None)
+                .unwrap();
+
+        assert_eq!(classified.prices[0].currency, "USD");
+        assert_eq!(classified.quantities[0].unit, Unit::Mass(MassUnit::G));
+        assert!(classified.is_listing());
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert!(classified.quantities.is_empty());
+        assert!(!classified.is_listing());
+    }
+
+    #[test]
+    fn accepted_currencies_from_tag_or_prices() {
+        let event = classified_event(vec![
+            custom_tag("price", &["12", "USD", "1", "kg"]),
+            custom_tag("price", &["11", "eur", "1", "kg"]),
+            custom_tag("price", &["6", "usd", "500", "g"]),
+        ]);
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert_eq!(classified.accepted_currencies(), ["USD", "eur"]);
+        assert!(classified.accepts_currency("EUR"));
+        assert!(!classified.accepts_currency("GBP"));
+
+        let event = classified_event(vec![
+            custom_tag("price", &["12", "USD", "1", "kg"]),
+            custom_tag("accepted-currencies", &["USD, GBP"]),
+        ]);
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert_eq!(classified.accepted_currencies(), ["USD", "GBP"]);
+        assert!(classified.accepts_currency("gbp"));
+    }
+
+    #[test]
+    fn mass_discount_tag_percent_marker() {
+        let event = classified_event(vec![
+            custom_tag("price-discount-mass", &["kg", "5", "kg", "10", "USD", "%"]),
+            custom_tag("price-discount-mass", &["kg", "5", "kg", "0.5", "USD"]),
+        ]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert!(matches!(
+            classified.discounts[0],
+            EventClassifiedDiscount::Mass { is_percent: true, discount_per_unit, .. } if discount_per_unit == 10.0
+        ));
+        assert!(matches!(
+            classified.discounts[1],
+            EventClassifiedDiscount::Mass {
+                is_percent: false,
+                ..
             }
+        ));
+    }
+
+    fn windowed(valid_from: u64, valid_until: u64) -> EventClassifiedDiscount {
+        EventClassifiedDiscount::Subtotal {
+            threshold: 0.0,
+            currency: "USD".into(),
+            value: 10.0,
+            is_percent: true,
+            valid_from: Some(Timestamp::from(valid_from)),
+            valid_until: Some(Timestamp::from(valid_until)),
         }
+    }
 
-        let total_discount: f64 = discounts.iter().map(|d| d.discount_amount).sum();
-        let total = ((subtotal - total_discount) * 100.0).round() / 100.0;
+    #[test]
+    fn discount_window_active_expired_and_pending() {
+        let now = Timestamp::from(1_000);
 
-        Ok(ListingOrder {
-            quantity: ListingOrderQuantity {
-                amount: quantity.amount,
-                unit: quantity.unit.clone(),
-                label: quantity.label.clone(),
-            },
-            price: ListingOrderPrice {
-                amount: tier.amount,
-                currency: tier.currency.clone(),
-                quantity_amount: tier.quantity_amount,
-                quantity_unit: price.quantity_unit.clone(),
-            },
-            discounts,
-            subtotal: ListingOrderSubtotal {
-                price_amount: subtotal,
-                price_currency: tier.currency.clone(),
-                quantity_amount: total_qty,
-                quantity_unit: quantity.unit.clone(),
-            },
-            total: ListingOrderTotal {
-                price_amount: total,
-                price_currency: tier.currency.clone(),
-                quantity_amount: total_qty,
-                quantity_unit: quantity.unit.clone(),
+        assert!(windowed(900, 1_100).is_active(now));
+        assert!(windowed(1_000, 1_100).is_active(now));
+        assert!(!windowed(800, 900).is_active(now));
+        assert!(!windowed(900, 1_000).is_active(now));
+        assert!(!windowed(1_100, 1_200).is_active(now));
+    }
+
+    #[test]
+    fn discount_window_parsed_from_tag() {
+        let event = classified_event(vec![custom_tag(
+            "price-discount-subtotal",
+            &["0", "USD", "10", "%", "900", "1100"],
+        )]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert!(matches!(
+            classified.discounts[0],
+            EventClassifiedDiscount::Subtotal {
+                valid_from: Some(from),
+                valid_until: Some(until),
+                ..
+            } if from == Timestamp::from(900) && until == Timestamp::from(1_100)
+        ));
+    }
+
+    #[test]
+    fn identical_price_tiers_collapsed() {
+        let event = classified_event(vec![
+            custom_tag("price", &["10", "USD", "1", "kg"]),
+            custom_tag("price", &["10", "usd", "1000", "g"]),
+        ]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert_eq!(classified.prices.len(), 2);
+        assert!(classified.ambiguous_prices.is_empty());
+
+        let event = classified_event(vec![
+            custom_tag("price", &["10", "USD", "1", "kg"]),
+            custom_tag("price", &["10", "usd", "1", "kg"]),
+        ]);
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert_eq!(classified.prices.len(), 1);
+        assert!(classified.ambiguous_prices.is_empty());
+    }
+
+    #[test]
+    fn conflicting_price_tiers_rejected() {
+        let event = classified_event(vec![
+            custom_tag("price", &["10", "USD", "1", "kg"]),
+            custom_tag("price", &["12", "USD", "1", "kg"]),
+            custom_tag("quantity", &["1", "kg", "bag"]),
+        ]);
+        let order: OrderSpec = serde_json::from_value(serde_json::json!({
+            "quantity": { "amount": 1.0, "unit": "kg", "label": "bag", "count": 1 },
+            "price": {
+                "amount": 10.0,
+                "currency": "USD",
+                "quantity_amount": 1.0,
+                "quantity_unit": "kg",
             },
-        })
+        }))
+        .unwrap();
+
+        let classified = EventClassified::from_event(&event, None, None, None).unwrap();
+
+        assert_eq!(classified.ambiguous_prices.len(), 1);
+        let err = classified
+            .calculate_order(&order, &OrderContext::now())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::AmbiguousTier)
+        ));
     }
 }
@@ -1 +1,5 @@
 pub mod event_classified;
+pub mod order_explanation;
+pub mod order_result;
+pub mod quote_result;
+pub mod wire;
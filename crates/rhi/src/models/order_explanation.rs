@@ -0,0 +1,40 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DiscountSkipReason {
+    Inactive,
+    ThresholdNotMet {
+        threshold: f64,
+        actual: f64,
+    },
+    CurrencyMismatch {
+        currency: String,
+        expected: String,
+    },
+    WrongProduct {
+        product_key: String,
+        package_key: String,
+    },
+    NotMassQuantity,
+    InvalidUnit {
+        field: &'static str,
+    },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderDiscountExplanation {
+    pub description: String,
+    pub triggered: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub skip: Option<DiscountSkipReason>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderExplanation {
+    pub subtotal: f64,
+    pub currency: String,
+    pub discounts: Vec<OrderDiscountExplanation>,
+}
@@ -0,0 +1,190 @@
+use nostr::{EventId, PublicKey};
+use radroots_common::models::listing_order::ListingOrder;
+use serde::Serialize;
+use typeshare::typeshare;
+
+use crate::events::job_request::JobResultRender;
+
+#[typeshare]
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderClassifiedTax {
+    pub rate_percent: f64,
+    pub price_amount: f64,
+    pub price_currency: String,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderClassifiedShipping {
+    pub quantity_kg: f64,
+    pub price_amount: f64,
+    pub price_currency: String,
+}
+
+#[typeshare(serialized_as = "OrderClassifiedResultWire")]
+#[derive(Serialize)]
+pub struct OrderClassifiedResult {
+    #[serde(flatten)]
+    pub order: ListingOrder,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax: Option<OrderClassifiedTax>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shipping: Option<OrderClassifiedShipping>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grand_total: Option<OrderResultTotal>,
+}
+
+impl OrderClassifiedResult {
+    pub fn grand_total(&self) -> OrderResultTotal {
+        self.grand_total
+            .clone()
+            .unwrap_or_else(|| OrderResultTotal {
+                price_amount: self.order.total.price_amount,
+                price_currency: self.order.total.price_currency.clone(),
+            })
+    }
+}
+
+#[typeshare]
+#[derive(Serialize)]
+pub struct OrderResultLine {
+    pub id: String,
+    pub result: OrderClassifiedResult,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderResultTotal {
+    pub price_amount: f64,
+    pub price_currency: String,
+}
+
+#[typeshare]
+#[derive(Serialize)]
+pub struct OrderResult {
+    pub lines: Vec<OrderResultLine>,
+    pub total: OrderResultTotal,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderResultPaymentStatus {
+    Paid,
+    Unpaid,
+    /// No msat amount could be derived, so there is nothing to compare the bid against.
+    Unpriced,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Clone)]
+pub struct OrderResultReceipt {
+    pub priced: bool,
+    #[typeshare(serialized_as = "number")]
+    pub amount_msat: u64,
+    #[typeshare(serialized_as = "Option<number>")]
+    pub bid_msat: Option<u64>,
+    pub status: OrderResultPaymentStatus,
+}
+
+impl OrderResultReceipt {
+    pub fn new(bid_msat: Option<u64>, amount_msat: Option<u64>) -> Self {
+        let status = match amount_msat {
+            None => OrderResultPaymentStatus::Unpriced,
+            Some(amount) if bid_msat.unwrap_or(0) >= amount => OrderResultPaymentStatus::Paid,
+            Some(_) => OrderResultPaymentStatus::Unpaid,
+        };
+        let amount_msat = amount_msat.unwrap_or(0);
+
+        Self {
+            priced: status != OrderResultPaymentStatus::Unpriced,
+            amount_msat,
+            bid_msat,
+            status,
+        }
+    }
+}
+
+#[typeshare]
+#[derive(Debug, Serialize)]
+pub struct OrderResultSummary {
+    #[typeshare(serialized_as = "String")]
+    pub request_id: EventId,
+    #[typeshare(serialized_as = "String")]
+    pub result_id: EventId,
+    pub total: f64,
+    pub currency: String,
+    #[typeshare(serialized_as = "String")]
+    pub requester: PublicKey,
+}
+
+#[typeshare]
+#[derive(Serialize)]
+pub struct OrderResultEnvelope<T> {
+    #[serde(flatten)]
+    pub result: T,
+    pub receipt: OrderResultReceipt,
+}
+
+impl<T: Serialize> JobResultRender for OrderResultEnvelope<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receipt_bid_covers_charge() {
+        let receipt = OrderResultReceipt::new(Some(5_000), Some(4_000));
+
+        assert!(receipt.priced);
+        assert_eq!(receipt.bid_msat, Some(5_000));
+        assert_eq!(receipt.amount_msat, 4_000);
+        assert_eq!(receipt.status, OrderResultPaymentStatus::Paid);
+    }
+
+    #[test]
+    fn receipt_bid_below_charge() {
+        let receipt = OrderResultReceipt::new(Some(3_000), Some(4_000));
+
+        assert_eq!(receipt.amount_msat, 4_000);
+        assert_eq!(receipt.status, OrderResultPaymentStatus::Unpaid);
+    }
+
+    #[test]
+    fn receipt_missing_bid() {
+        let receipt = OrderResultReceipt::new(None, Some(4_000));
+
+        assert_eq!(receipt.bid_msat, None);
+        assert_eq!(receipt.status, OrderResultPaymentStatus::Unpaid);
+    }
+
+    #[test]
+    fn receipt_serializes_status() {
+        let receipt = OrderResultReceipt::new(Some(5_000), Some(4_000));
+        let value = serde_json::to_value(&receipt).unwrap();
+
+        assert_eq!(value["bid_msat"], 5_000);
+        assert_eq!(value["amount_msat"], 4_000);
+        assert_eq!(value["status"], "paid");
+    }
+
+    #[test]
+    fn receipt_unpriced_without_amount() {
+        let receipt = OrderResultReceipt::new(Some(5_000), None);
+        let value = serde_json::to_value(&receipt).unwrap();
+
+        assert_eq!(receipt.status, OrderResultPaymentStatus::Unpriced);
+        assert_eq!(value["priced"], false);
+        assert_eq!(value["amount_msat"], 0);
+        assert_eq!(value["status"], "unpriced");
+    }
+
+    #[test]
+    fn receipt_serializes_priced_amount() {
+        let receipt = OrderResultReceipt::new(None, Some(4_000));
+        let value = serde_json::to_value(&receipt).unwrap();
+
+        assert_eq!(value["priced"], true);
+        assert_eq!(value["amount_msat"], 4_000);
+    }
+}
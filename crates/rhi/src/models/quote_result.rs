@@ -0,0 +1,220 @@
+use nostr::EventId;
+use serde::Serialize;
+use typeshare::typeshare;
+
+use crate::events::job_request::JobResultRender;
+use crate::models::event_classified::{
+    EventClassifiedDiscount, EventClassifiedPrice, EventClassifiedQuantity, EventClassifiedShipping,
+};
+use crate::utils::unit::{MassUnit, convert_mass};
+
+#[typeshare(serialized_as = "QuoteResultDiscountWire")]
+#[derive(Debug, Serialize, Clone)]
+pub struct QuoteResultDiscount {
+    #[serde(flatten)]
+    pub discount: EventClassifiedDiscount,
+    pub description: String,
+}
+
+impl From<&EventClassifiedDiscount> for QuoteResultDiscount {
+    fn from(discount: &EventClassifiedDiscount) -> Self {
+        Self {
+            discount: discount.clone(),
+            description: discount.describe(),
+        }
+    }
+}
+
+#[typeshare(serialized_as = "QuoteResultPriceWire")]
+#[derive(Debug, Serialize, Clone)]
+pub struct QuoteResultPrice {
+    #[serde(flatten)]
+    pub tier: EventClassifiedPrice,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_price_per_gram: Option<f64>,
+}
+
+impl From<EventClassifiedPrice> for QuoteResultPrice {
+    fn from(tier: EventClassifiedPrice) -> Self {
+        let unit_price_per_gram = tier.quantity_unit.as_mass().and_then(|unit| {
+            let grams = convert_mass(tier.quantity_amount, unit, &MassUnit::G);
+            (grams > 0.0).then(|| tier.amount / grams)
+        });
+
+        Self {
+            tier,
+            unit_price_per_gram,
+        }
+    }
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Clone)]
+pub struct QuoteResult {
+    #[typeshare(serialized_as = "String")]
+    pub id: EventId,
+    pub prices: Vec<QuoteResultPrice>,
+    pub quantities: Vec<EventClassifiedQuantity>,
+    pub discounts: Vec<QuoteResultDiscount>,
+    pub accepted_currencies: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax_rate_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[typeshare(serialized_as = "Option<EventClassifiedShippingWire>")]
+    pub shipping: Option<EventClassifiedShipping>,
+}
+
+impl JobResultRender for QuoteResult {
+    const CSV: bool = true;
+
+    fn to_csv(&self) -> Option<String> {
+        let mut csv =
+            String::from("amount,currency,quantity_amount,quantity_unit,unit_price_per_gram\n");
+        for price in &self.prices {
+            let tier = &price.tier;
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                tier.amount,
+                csv_field(&tier.currency),
+                tier.quantity_amount,
+                csv_field(&tier.quantity_unit.to_string()),
+                price
+                    .unit_price_per_gram
+                    .map_or(String::new(), |p| p.to_string())
+            ));
+        }
+        Some(csv)
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::unit::Unit;
+
+    use super::*;
+
+    #[test]
+    fn discount_carries_description() {
+        let discount = EventClassifiedDiscount::Subtotal {
+            threshold: 50.0,
+            currency: "USD".into(),
+            value: 10.0,
+            is_percent: true,
+            valid_from: None,
+            valid_until: None,
+        };
+
+        let value = serde_json::to_value(QuoteResultDiscount::from(&discount)).unwrap();
+
+        assert_eq!(value["type"], "subtotal");
+        assert_eq!(value["description"], "10% off subtotals of 50 USD or more");
+    }
+
+    #[test]
+    fn price_per_gram_for_mass_tiers() {
+        let price = QuoteResultPrice::from(EventClassifiedPrice {
+            amount: 12.0,
+            currency: "USD".into(),
+            quantity_amount: 1.0,
+            quantity_unit: Unit::Mass(MassUnit::Kg),
+        });
+
+        assert_eq!(price.unit_price_per_gram, Some(0.012));
+
+        let price = QuoteResultPrice::from(EventClassifiedPrice {
+            amount: 2.0,
+            currency: "USD".into(),
+            quantity_amount: 1.0,
+            quantity_unit: Unit::Count,
+        });
+
+        assert_eq!(price.unit_price_per_gram, None);
+    }
+
+    #[test]
+    fn quote_lists_price_per_gram() {
+        let quote = QuoteResult {
+            id: EventId::all_zeros(),
+            prices: vec![
+                QuoteResultPrice::from(EventClassifiedPrice {
+                    amount: 12.0,
+                    currency: "USD".into(),
+                    quantity_amount: 1.0,
+                    quantity_unit: Unit::Mass(MassUnit::Kg),
+                }),
+                QuoteResultPrice::from(EventClassifiedPrice {
+                    amount: 2.0,
+                    currency: "USD".into(),
+                    quantity_amount: 1.0,
+                    quantity_unit: Unit::Count,
+                }),
+            ],
+            quantities: vec![],
+            discounts: vec![],
+            accepted_currencies: vec!["USD".into()],
+            tax_rate_percent: None,
+            shipping: None,
+        };
+
+        let value = serde_json::to_value(&quote).unwrap();
+        assert_eq!(value["prices"][0]["amount"], 12.0);
+        assert_eq!(value["prices"][0]["unit_price_per_gram"], 0.012);
+        assert!(value["prices"][1].get("unit_price_per_gram").is_none());
+
+        assert_eq!(
+            quote.to_csv().unwrap(),
+            "amount,currency,quantity_amount,quantity_unit,unit_price_per_gram\n\
+             12,USD,1,kg,0.012\n\
+             2,USD,1,each,\n"
+        );
+
+        for (amount, quantity_amount, unit, per_gram) in [
+            (6.0, 500.0, MassUnit::G, 0.012),
+            (4.5359237, 1.0, MassUnit::Lb, 0.01),
+        ] {
+            let price = QuoteResultPrice::from(EventClassifiedPrice {
+                amount,
+                currency: "USD".into(),
+                quantity_amount,
+                quantity_unit: Unit::Mass(unit),
+            });
+            let unit_price_per_gram = price.unit_price_per_gram.unwrap();
+            assert!(
+                (unit_price_per_gram - per_gram).abs() < 1e-12,
+                "{unit_price_per_gram}"
+            );
+        }
+    }
+
+    #[test]
+    fn csv_fields_quoted() {
+        assert_eq!(csv_field("USD"), "USD");
+        assert_eq!(csv_field("US,D"), "\"US,D\"");
+        assert_eq!(csv_field("say \"hi\"\n"), "\"say \"\"hi\"\"\n\"");
+
+        let quote = QuoteResult {
+            id: EventId::all_zeros(),
+            prices: vec![QuoteResultPrice::from(EventClassifiedPrice {
+                amount: 2.0,
+                currency: "US,D".into(),
+                quantity_amount: 1.0,
+                quantity_unit: Unit::Count,
+            })],
+            quantities: vec![],
+            discounts: vec![],
+            accepted_currencies: vec![],
+            tax_rate_percent: None,
+            shipping: None,
+        };
+
+        assert!(quote.to_csv().unwrap().ends_with("\n2,\"US,D\",1,each,\n"));
+    }
+}
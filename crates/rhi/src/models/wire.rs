@@ -0,0 +1,321 @@
+//! Shapes of serialized types that typeshare cannot generate from their definitions: internally
+//! tagged enums, and types that flatten in structs from crates it does not read. Fields a
+//! variant does not carry are left out.
+
+use nostr::Timestamp;
+use radroots_common::models::listing_order::{
+    ListingOrderDiscount, ListingOrderPrice, ListingOrderQuantity, ListingOrderSubtotal,
+    ListingOrderTotal,
+};
+use serde::{Deserialize, Serialize};
+use typeshare::typeshare;
+
+use crate::{
+    models::order_result::{OrderClassifiedShipping, OrderClassifiedTax, OrderResultTotal},
+    utils::unit::{MassUnit, Unit},
+};
+
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventClassifiedDiscountType {
+    Subtotal,
+    Mass,
+    Quantity,
+}
+
+/// Wire shape of `EventClassifiedDiscount`.
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EventClassifiedDiscountWire {
+    #[serde(rename = "type")]
+    pub discount_type: EventClassifiedDiscountType,
+    pub currency: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold_unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_percent: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discount_unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discount_per_unit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[typeshare(serialized_as = "Option<number>")]
+    pub valid_from: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[typeshare(serialized_as = "Option<number>")]
+    pub valid_until: Option<Timestamp>,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventClassifiedShippingType {
+    Flat,
+    PerKg,
+    FreeAbove,
+}
+
+/// Wire shape of `EventClassifiedShipping`.
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EventClassifiedShippingWire {
+    #[serde(rename = "type")]
+    pub shipping_type: EventClassifiedShippingType,
+    pub currency: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_per_kg: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold_unit: Option<MassUnit>,
+}
+
+/// Wire shape of `QuoteResultDiscount`, a discount with its description.
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuoteResultDiscountWire {
+    #[serde(flatten)]
+    pub discount: EventClassifiedDiscountWire,
+    pub description: String,
+}
+
+/// Wire shape of `QuoteResultPrice`, a price tier with its unit price.
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuoteResultPriceWire {
+    pub amount: f64,
+    pub currency: String,
+    pub quantity_amount: f64,
+    pub quantity_unit: Unit,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_price_per_gram: Option<f64>,
+}
+
+/// Wire shape of `OrderSpec`, a listing order request payload with its part-package count.
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OrderSpecWire {
+    pub quantity: OrderSpecQuantityWire,
+    pub price: ListingOrderPrice,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fractional_count: Option<f64>,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OrderSpecQuantityWire {
+    pub amount: f64,
+    pub unit: String,
+    pub label: String,
+    pub count: u32,
+}
+
+/// Wire shape of `OrderClassifiedResult`, the listing order with tax and shipping alongside.
+#[typeshare]
+#[derive(Serialize)]
+pub struct OrderClassifiedResultWire {
+    pub quantity: ListingOrderQuantity,
+    pub price: ListingOrderPrice,
+    pub discounts: Vec<ListingOrderDiscount>,
+    pub subtotal: ListingOrderSubtotal,
+    pub total: ListingOrderTotal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax: Option<OrderClassifiedTax>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shipping: Option<OrderClassifiedShipping>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grand_total: Option<OrderResultTotal>,
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::EventId;
+    use serde::de::DeserializeOwned;
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::models::{
+        event_classified::{
+            EventClassified, EventClassifiedBasis, EventClassifiedDiscount, EventClassifiedListing,
+            EventClassifiedPrice, EventClassifiedQuantity, EventClassifiedShipping, OrderContext,
+        },
+        quote_result::{QuoteResultDiscount, QuoteResultPrice},
+    };
+    use crate::utils::price::OrderSpec;
+
+    fn assert_wire<W: Serialize + DeserializeOwned>(value: &impl Serialize) {
+        let value = serde_json::to_value(value).unwrap();
+        let wire: W = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&wire).unwrap(), value);
+    }
+
+    fn discounts() -> Vec<EventClassifiedDiscount> {
+        vec![
+            EventClassifiedDiscount::Subtotal {
+                threshold: 20.0,
+                currency: "USD".into(),
+                value: 5.0,
+                is_percent: true,
+                valid_from: Some(Timestamp::from(1_000)),
+                valid_until: None,
+            },
+            EventClassifiedDiscount::Mass {
+                discount_unit: "kg".into(),
+                threshold: 2.0,
+                threshold_unit: "kg".into(),
+                discount_per_unit: 1.5,
+                currency: "USD".into(),
+                is_percent: false,
+                valid_from: None,
+                valid_until: Some(Timestamp::from(2_000)),
+            },
+            EventClassifiedDiscount::Quantity {
+                product_key: "coffee".into(),
+                min_count: 3,
+                discount_per_unit: 0.5,
+                currency: "USD".into(),
+                valid_from: None,
+                valid_until: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn discounts_match_wire() {
+        for discount in discounts() {
+            assert_wire::<EventClassifiedDiscountWire>(&discount);
+            assert_wire::<QuoteResultDiscountWire>(&QuoteResultDiscount::from(&discount));
+        }
+    }
+
+    #[test]
+    fn shipping_matches_wire() {
+        let shipping = [
+            EventClassifiedShipping::Flat {
+                amount: 4.0,
+                currency: "USD".into(),
+            },
+            EventClassifiedShipping::PerKg {
+                amount_per_kg: 1.25,
+                currency: "USD".into(),
+            },
+            EventClassifiedShipping::FreeAbove {
+                threshold: 5.0,
+                threshold_unit: MassUnit::Kg,
+                amount: 6.0,
+                currency: "USD".into(),
+            },
+        ];
+        for shipping in shipping {
+            assert_wire::<EventClassifiedShippingWire>(&shipping);
+        }
+    }
+
+    #[test]
+    fn quote_price_matches_wire() {
+        let price = QuoteResultPrice::from(EventClassifiedPrice {
+            amount: 12.0,
+            currency: "USD".into(),
+            quantity_amount: 1.0,
+            quantity_unit: Unit::Mass(MassUnit::Kg),
+        });
+
+        assert_wire::<QuoteResultPriceWire>(&price);
+    }
+
+    #[test]
+    fn order_spec_matches_wire() {
+        let value = json!({
+            "quantity": { "amount": 1.0, "unit": "kg", "label": "bag", "count": 1 },
+            "price": {
+                "amount": 10.0,
+                "currency": "USD",
+                "quantity_amount": 1.0,
+                "quantity_unit": "kg",
+            },
+            "fractional_count": 1.5,
+        });
+
+        let order: OrderSpec = serde_json::from_value(value.clone()).unwrap();
+        let wire: OrderSpecWire = serde_json::from_value(value.clone()).unwrap();
+
+        assert_eq!(order.count().unwrap(), 1.5);
+        assert_eq!(wire.fractional_count, Some(1.5));
+        assert_eq!(serde_json::to_value(&wire).unwrap(), value);
+    }
+
+    #[test]
+    fn order_result_fields_match_wire() {
+        let mut listing = EventClassified::new(
+            EventId::all_zeros(),
+            EventClassifiedBasis::default(),
+            EventClassifiedListing::default(),
+            vec![EventClassifiedPrice {
+                amount: 10.0,
+                currency: "USD".into(),
+                quantity_amount: 1.0,
+                quantity_unit: Unit::Mass(MassUnit::Kg),
+            }],
+            vec![EventClassifiedQuantity {
+                amount: 1.0,
+                unit: Unit::Mass(MassUnit::Kg),
+                label: "bag".into(),
+            }],
+            vec![],
+        );
+        listing.shipping = Some(EventClassifiedShipping::Flat {
+            amount: 4.0,
+            currency: "USD".into(),
+        });
+        let order: OrderSpec = serde_json::from_value(json!({
+            "quantity": { "amount": 1.0, "unit": "kg", "label": "bag", "count": 2 },
+            "price": {
+                "amount": 10.0,
+                "currency": "USD",
+                "quantity_amount": 1.0,
+                "quantity_unit": "kg",
+            },
+        }))
+        .unwrap();
+        let ctx = OrderContext {
+            tax_rate_percent: Some(10.0),
+            ..OrderContext::now()
+        };
+        let result = listing.calculate_order(&order, &ctx).unwrap();
+
+        let Value::Object(fields) = serde_json::to_value(&result).unwrap() else {
+            panic!("order result is not an object");
+        };
+        let mut fields: Vec<_> = fields.keys().map(String::as_str).collect();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            [
+                "discounts",
+                "grand_total",
+                "price",
+                "quantity",
+                "shipping",
+                "subtotal",
+                "tax",
+                "total"
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::config::ReconnectSettings;
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    multiplier: f64,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration, multiplier: f64) -> Self {
+        Self {
+            base,
+            max: max.max(base),
+            multiplier: multiplier.max(1.0),
+            attempt: 0,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        let factor = self.multiplier.powi(self.attempt as i32);
+        let delay = self.base.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.max.as_secs_f64()))
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.delay();
+        self.attempt = self.attempt.saturating_add(1);
+        with_jitter(delay)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl From<&ReconnectSettings> for Backoff {
+    fn from(settings: &ReconnectSettings) -> Self {
+        Self::new(
+            Duration::from_millis(settings.base_ms),
+            Duration::from_millis(settings.max_ms),
+            settings.multiplier,
+        )
+    }
+}
+
+/// Reruns `run` until `shutdown`, waiting out the next backoff delay between runs.
+pub async fn backoff_loop<F, Fut>(
+    backoff: &mut Backoff,
+    reset_after: Duration,
+    shutdown: &CancellationToken,
+    mut run: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        let started = Instant::now();
+        run().await;
+
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        if started.elapsed() >= reset_after {
+            backoff.reset();
+        }
+
+        let delay = backoff.next_delay();
+        warn!("Restarting job request subscription in {:?}", delay);
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = shutdown.cancelled() => break,
+        }
+    }
+}
+
+fn with_jitter(delay: Duration) -> Duration {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(delay.as_nanos());
+    let ratio = (hasher.finish() % 1_000) as f64 / 1_000.0;
+    delay.mul_f64(0.5 + ratio / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn delay_grows_and_caps() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(10), 2.0);
+
+        let delays: Vec<u64> = (0..6)
+            .map(|_| {
+                let delay = backoff.delay().as_secs();
+                backoff.next_delay();
+                delay
+            })
+            .collect();
+
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        backoff.reset();
+        assert_eq!(backoff.delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_half_delay() {
+        let mut backoff = Backoff::new(Duration::from_secs(4), Duration::from_secs(4), 2.0);
+
+        for _ in 0..100 {
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff_loop_delays_failed_runs() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(4), 2.0);
+        let shutdown = CancellationToken::new();
+        let runs = Arc::new(Mutex::new(Vec::new()));
+
+        backoff_loop(&mut backoff, Duration::from_secs(3_600), &shutdown, || {
+            let runs = runs.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                let mut runs = runs.lock().unwrap();
+                runs.push(tokio::time::Instant::now());
+                if runs.len() == 6 {
+                    shutdown.cancel();
+                }
+            }
+        })
+        .await;
+
+        let runs = runs.lock().unwrap();
+        let gaps: Vec<Duration> = runs.windows(2).map(|w| w[1] - w[0]).collect();
+        let caps = [1, 2, 4, 4, 4].map(Duration::from_secs);
+
+        assert_eq!(gaps.len(), caps.len());
+        for (gap, cap) in gaps.iter().zip(caps) {
+            assert!(*gap >= cap / 2 && *gap <= cap, "{gap:?} outside {cap:?}");
+        }
+    }
+}
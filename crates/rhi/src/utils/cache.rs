@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::CacheSettings;
+
+#[derive(Debug)]
+struct TtlCacheEntry<V> {
+    value: V,
+    inserted: Instant,
+    accessed: Instant,
+}
+
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<K, TtlCacheEntry<V>>>>,
+}
+
+impl<K, V> Clone for TtlCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            capacity: self.capacity,
+            ttl: self.ttl,
+            entries: Arc::clone(&self.entries),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().ok()?;
+        let now = Instant::now();
+
+        match entries.get_mut(key) {
+            Some(entry) if now.duration_since(entry.inserted) < self.ttl => {
+                entry.accessed = now;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        if let Ok(mut entries) = self.entries.lock() {
+            Self::insert_entry(&mut entries, self.capacity, self.ttl, key, value);
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries
+            .lock()
+            .is_ok_and(|entries| entries.contains_key(key))
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().ok()?;
+        entries.remove(key).map(|entry| entry.value)
+    }
+
+    pub fn insert_if_absent(&self, key: K, value: V) -> bool {
+        let Ok(mut entries) = self.entries.lock() else {
+            return true;
+        };
+
+        let live = entries
+            .get(&key)
+            .is_some_and(|entry| entry.inserted.elapsed() < self.ttl);
+
+        if !live {
+            Self::insert_entry(&mut entries, self.capacity, self.ttl, key, value);
+        }
+
+        !live
+    }
+
+    fn insert_entry(
+        entries: &mut HashMap<K, TtlCacheEntry<V>>,
+        capacity: usize,
+        ttl: Duration,
+        key: K,
+        value: V,
+    ) {
+        let now = Instant::now();
+
+        if !entries.contains_key(&key) && entries.len() >= capacity {
+            entries.retain(|_, entry| now.duration_since(entry.inserted) < ttl);
+        }
+
+        if !entries.contains_key(&key) && entries.len() >= capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.accessed)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(
+            key,
+            TtlCacheEntry {
+                value,
+                inserted: now,
+                accessed: now,
+            },
+        );
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> From<&CacheSettings> for TtlCache<K, V> {
+    fn from(settings: &CacheSettings) -> Self {
+        Self::new(settings.capacity, Duration::from_secs(settings.ttl_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let cache = TtlCache::new(4, Duration::from_millis(20));
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(cache.get(&"a"), None);
+        assert!(cache.insert_if_absent("a", 2));
+    }
+
+    #[test]
+    fn least_recently_accessed_evicted_at_capacity() {
+        let cache = TtlCache::new(2, Duration::from_secs(60));
+        cache.insert("a", 1);
+        std::thread::sleep(Duration::from_millis(2));
+        cache.insert("b", 2);
+        std::thread::sleep(Duration::from_millis(2));
+        cache.get(&"a");
+
+        cache.insert("c", 3);
+
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn clones_share_entries() {
+        let cache = TtlCache::new(2, Duration::from_secs(60));
+        let shared = cache.clone();
+
+        shared.insert("a", 1);
+
+        assert_eq!(cache.get(&"a"), Some(1));
+    }
+}
@@ -0,0 +1,93 @@
+pub const GEOHASH_ALPHABET: &str = "0123456789bcdefghjkmnpqrstuvwxyz";
+pub const GEOHASH_MAX_LEN: usize = 12;
+
+pub fn is_valid_geohash(geohash: &str) -> bool {
+    (1..=GEOHASH_MAX_LEN).contains(&geohash.len())
+        && geohash.chars().all(|c| GEOHASH_ALPHABET.contains(c))
+}
+
+/// Decodes a geohash into its cell center latitude, longitude and error in degrees.
+pub fn geohash_decode(geohash: &str) -> Option<(f64, f64, f64)> {
+    if !is_valid_geohash(geohash) {
+        return None;
+    }
+
+    let mut lat = (-90.0_f64, 90.0_f64);
+    let mut lng = (-180.0_f64, 180.0_f64);
+    let mut is_lng = true;
+
+    for c in geohash.chars() {
+        let index = GEOHASH_ALPHABET.find(c)?;
+        for bit in (0..5).rev() {
+            let range = if is_lng { &mut lng } else { &mut lat };
+            let mid = (range.0 + range.1) / 2.0;
+            if (index >> bit) & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            is_lng = !is_lng;
+        }
+    }
+
+    let lat_err = (lat.1 - lat.0) / 2.0;
+    let lng_err = (lng.1 - lng.0) / 2.0;
+
+    Some((lat.0 + lat_err, lng.0 + lng_err, lat_err.max(lng_err)))
+}
+
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+pub fn haversine_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat_a, lng_a) = (a.0.to_radians(), a.1.to_radians());
+    let (lat_b, lng_b) = (b.0.to_radians(), b.1.to_radians());
+
+    let d_lat = lat_b - lat_a;
+    let d_lng = lng_b - lng_a;
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lng / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * h.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geohash_alphabet_and_length() {
+        assert!(is_valid_geohash("u"));
+        assert!(is_valid_geohash("u4pruydqqvj8"));
+        assert!(!is_valid_geohash(""));
+        assert!(!is_valid_geohash("u4pruydqqvj8p"));
+        assert!(!is_valid_geohash("hello!"));
+        assert!(!is_valid_geohash("u4pa"));
+        assert!(!is_valid_geohash("U4PR"));
+    }
+
+    #[test]
+    fn geohash_decode_cell_center() {
+        let (lat, lng, err) = geohash_decode("u4pruydqqvj").unwrap();
+
+        assert!((lat - 57.64911).abs() < err);
+        assert!((lng - 10.40744).abs() < err);
+        assert!(err < 1e-5);
+    }
+
+    #[test]
+    fn geohash_decode_invalid() {
+        assert!(geohash_decode("hello!").is_none());
+        assert!(geohash_decode("").is_none());
+    }
+
+    #[test]
+    fn haversine_known_distance() {
+        let paris = (48.8566, 2.3522);
+        let london = (51.5074, -0.1278);
+
+        let distance = haversine_km(paris, london);
+
+        assert!((distance - 343.5).abs() < 1.0);
+        assert_eq!(haversine_km(paris, paris), 0.0);
+    }
+}
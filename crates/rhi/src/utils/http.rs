@@ -0,0 +1,283 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use reqwest::{Url, header::CONTENT_TYPE, redirect::Policy};
+use serde::Serialize;
+use thiserror::Error;
+
+const HTTP_FETCH_TIMEOUT_SECS: u64 = 10;
+const HTTP_FETCH_MAX_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum HttpError {
+    #[error("Unsupported url: {0}")]
+    UnsupportedUrl(String),
+
+    #[error("Request error: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Could not resolve host: {0}")]
+    Resolve(String),
+
+    #[error("Host resolves to a non-public address: {0}")]
+    NonPublicAddress(String),
+
+    #[error("Response body over {0} bytes")]
+    BodyTooLarge(usize),
+}
+
+pub fn http_url_is_supported(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+pub async fn http_fetch_text(url: &str, allow_private: bool) -> Result<String, HttpError> {
+    let parsed = Url::parse(url)
+        .ok()
+        .filter(|_| http_url_is_supported(url))
+        .ok_or_else(|| HttpError::UnsupportedUrl(url.to_string()))?;
+
+    let mut builder =
+        reqwest::Client::builder().timeout(Duration::from_secs(HTTP_FETCH_TIMEOUT_SECS));
+    if !allow_private {
+        // Pinning the checked addresses keeps a second lookup or a redirect from going elsewhere.
+        let (host, addrs) = http_resolve_public(&parsed).await?;
+        builder = builder
+            .redirect(Policy::none())
+            .resolve_to_addrs(&host, &addrs);
+    }
+
+    let mut response = builder
+        .build()?
+        .get(parsed)
+        .send()
+        .await?
+        .error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > HTTP_FETCH_MAX_BYTES as u64)
+    {
+        return Err(HttpError::BodyTooLarge(HTTP_FETCH_MAX_BYTES));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > HTTP_FETCH_MAX_BYTES {
+            return Err(HttpError::BodyTooLarge(HTTP_FETCH_MAX_BYTES));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+async fn http_resolve_public(url: &Url) -> Result<(String, Vec<SocketAddr>), HttpError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| HttpError::UnsupportedUrl(url.to_string()))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let lookup = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((lookup, port))
+        .await
+        .map_err(|e| HttpError::Resolve(format!("{host}: {e}")))?
+        .collect();
+    if addrs.is_empty() || !addrs.iter().all(|addr| http_addr_is_public(addr.ip())) {
+        return Err(HttpError::NonPublicAddress(host.to_string()));
+    }
+    Ok((host.to_string(), addrs))
+}
+
+fn http_addr_is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            let shared = a == 100 && (b & 0xc0) == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => http_addr_is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+pub async fn http_post_json<T: Serialize>(url: &str, body: &T) -> Result<(), HttpError> {
+    if !http_url_is_supported(url) {
+        return Err(HttpError::UnsupportedUrl(url.to_string()));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(HTTP_FETCH_TIMEOUT_SECS))
+        .build()?;
+    client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(body)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    async fn serve_once(listener: TcpListener, status: &'static str, body: String) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn post_json_sends_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/orders", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_once(listener, "200 OK", String::new()));
+
+        http_post_json(&url, &serde_json::json!({ "total": 18.0 }))
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /orders"));
+        assert!(
+            request
+                .to_lowercase()
+                .contains("content-type: application/json")
+        );
+        assert!(request.ends_with(r#"{"total":18.0}"#));
+    }
+
+    #[tokio::test]
+    async fn post_json_errors() {
+        assert!(matches!(
+            http_post_json("ftp://rhi.test", &()).await,
+            Err(HttpError::UnsupportedUrl(_))
+        ));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_once(
+            listener,
+            "500 Internal Server Error",
+            String::new(),
+        ));
+        assert!(matches!(
+            http_post_json(&url, &()).await,
+            Err(HttpError::Request(_))
+        ));
+    }
+
+    #[test]
+    fn addr_public_ranges() {
+        for ip in ["1.1.1.1", "2606:4700::1111"] {
+            assert!(http_addr_is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!http_addr_is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_text_rejects_private_hosts() {
+        for url in [
+            "http://127.0.0.1:9/order",
+            "http://[::1]:9/order",
+            "http://localhost:9",
+        ] {
+            assert!(
+                matches!(
+                    http_fetch_text(url, false).await,
+                    Err(HttpError::NonPublicAddress(_))
+                ),
+                "{url}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_text_reads_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/order", listener.local_addr().unwrap());
+        tokio::spawn(serve_once(listener, "200 OK", "{}".into()));
+
+        assert_eq!(http_fetch_text(&url, true).await.unwrap(), "{}");
+    }
+
+    #[tokio::test]
+    async fn fetch_text_caps_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/order", listener.local_addr().unwrap());
+        tokio::spawn(serve_once(
+            listener,
+            "200 OK",
+            "x".repeat(HTTP_FETCH_MAX_BYTES + 1),
+        ));
+
+        assert!(matches!(
+            http_fetch_text(&url, true).await,
+            Err(HttpError::BodyTooLarge(_))
+        ));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod backoff;
+pub mod cache;
+pub mod geo;
+pub mod http;
 pub mod nostr;
 pub mod price;
+pub mod rate;
 pub mod unit;
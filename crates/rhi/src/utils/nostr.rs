@@ -1,10 +1,21 @@
-use std::{borrow::Cow, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
-use crate::events::job_request::JobRequestError;
+use crate::audit::{AuditDirection, AuditLog};
+use crate::config::{RelayRole, RelaySettings};
+use crate::events::job_request::{JobRequestError, JobRequestFeedbackError, job_result_event_tag};
+use crate::utils::cache::TtlCache;
 use anyhow::Result;
 use nostr::{
-    event::{Event, EventBuilder, EventId, Kind, Tag, TagKind, TagStandard},
-    filter::Filter,
+    event::{
+        Event, EventBuilder, EventId, Kind, Tag, TagKind, TagStandard,
+        builder::{Error as EventBuilderError, WrongKindError},
+        kind::NIP90_JOB_RESULT_RANGE,
+    },
+    filter::{Alphabet, Filter, SingleLetterTag},
     key::{Keys, PublicKey},
     nips::{
         nip04,
@@ -15,6 +26,36 @@ use nostr::{
 use nostr_sdk::Client;
 use nostr_sdk::prelude::*;
 use thiserror::Error;
+use tracing::{info, warn};
+
+pub type EventCache = TtlCache<EventId, Event>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Payment {
+    #[default]
+    None,
+    Bolt11(String),
+    Bolt12(String),
+}
+
+impl Payment {
+    pub fn bolt11(&self) -> Option<String> {
+        match self {
+            Self::Bolt11(invoice) => Some(invoice.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn tag(&self) -> Option<Tag> {
+        match self {
+            Self::Bolt12(offer) => Some(Tag::custom(TagKind::custom("bolt12"), [offer.clone()])),
+            _ => None,
+        }
+    }
+}
+
+const NOSTR_RELAY_HINTS_MAX: usize = 16;
+const NOSTR_RELAY_HINT_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Error)]
 pub enum NostrUtilsError {
@@ -29,6 +70,12 @@ pub enum NostrUtilsError {
 
     #[error("Event builder failure: {0}")]
     EventBuildError(#[from] nostr::event::builder::Error),
+
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+
+    #[error("Connected to {connected} relays, below the minimum of {required}")]
+    RelayQuorum { connected: usize, required: usize },
 }
 
 #[derive(Debug, Error)]
@@ -46,18 +93,78 @@ pub enum NostrTagsResolveError {
     ParseError(#[from] serde_json::Error),
 }
 
+/// NIP-42 authentication is a client-wide switch in nostr-sdk, so one relay with `auth`
+/// set makes the client answer AUTH challenges from every relay in the pool.
+pub fn nostr_client_new(keys: Keys, relays: &[RelaySettings]) -> Client {
+    let opts = ClientOptions::new().automatic_authentication(relays.iter().any(|r| r.auth));
+    Client::builder().signer(keys).opts(opts).build()
+}
+
+pub async fn nostr_client_add_relays(
+    client: &Client,
+    relays: &[RelaySettings],
+) -> Result<(), NostrUtilsError> {
+    for relay in relays {
+        match relay.role {
+            RelayRole::Read => client.add_read_relay(&relay.url).await?,
+            RelayRole::Write => client.add_write_relay(&relay.url).await?,
+            RelayRole::Both => client.add_relay(&relay.url).await?,
+        };
+    }
+    Ok(())
+}
+
+pub async fn nostr_client_connect(
+    client: &Client,
+    timeout: Duration,
+    min_connected: usize,
+) -> Result<usize, NostrUtilsError> {
+    client.connect().await;
+    client.wait_for_connection(timeout).await;
+
+    let connected = client
+        .relays()
+        .await
+        .values()
+        .filter(|relay| relay.is_connected())
+        .count();
+
+    if connected < min_connected {
+        client.disconnect().await;
+        return Err(NostrUtilsError::RelayQuorum {
+            connected,
+            required: min_connected,
+        });
+    }
+
+    Ok(connected)
+}
+
 pub fn nostr_kind(kind: u16) -> Kind {
     Kind::Custom(kind)
 }
 
-pub fn nostr_filter_kind(kind: u16) -> Filter {
-    Filter::new().kind(Kind::Custom(kind))
+pub fn nostr_filter_kinds(kinds: &[u16]) -> Filter {
+    Filter::new().kinds(kinds.iter().map(|kind| Kind::Custom(*kind)))
+}
+
+/// Matches NIP-09 deletions whose `k` tag names one of `kinds`.
+pub fn nostr_filter_deletions(kinds: &[u16]) -> Filter {
+    Filter::new().kind(Kind::EventDeletion).custom_tags(
+        SingleLetterTag::lowercase(Alphabet::K),
+        kinds.iter().map(|kind| kind.to_string()),
+    )
 }
 
 pub fn nostr_filter_new_events(filter: Filter) -> Filter {
     filter.since(Timestamp::now())
 }
 
+pub fn nostr_filter_recent_events(filter: Filter, lookback: Duration) -> Filter {
+    let since = Timestamp::now().as_u64().saturating_sub(lookback.as_secs());
+    filter.since(Timestamp::from(since))
+}
+
 pub fn nostr_tag_first_value(tag: &Tag, key: &str) -> Option<String> {
     if tag.kind() == TagKind::custom(key) {
         tag.content().map(|v| v.to_string())
@@ -118,6 +225,13 @@ pub fn nostr_tag_match_geohash(tag: &Tag) -> Option<String> {
     }
 }
 
+pub fn nostr_tag_match_expiration(tag: &Tag) -> Option<Timestamp> {
+    match tag.as_standardized()? {
+        TagStandard::Expiration(timestamp) => Some(*timestamp),
+        _ => None,
+    }
+}
+
 pub fn nostr_tag_match_title(tag: &Tag) -> Option<String> {
     match tag.as_standardized()? {
         TagStandard::Title(title) => Some(title.clone()),
@@ -136,48 +250,276 @@ pub fn nostr_event_job_result(
     job_request: &Event,
     payload: impl Into<String>,
     millisats: u64,
-    bolt11: Option<String>,
+    payment: Payment,
     tags: Option<Vec<Tag>>,
 ) -> Result<EventBuilder, NostrUtilsError> {
-    let builder = EventBuilder::job_result(job_request.clone(), payload, millisats, bolt11)?
-        .tags(tags.unwrap_or_default());
-    Ok(builder)
+    let kind = job_request.kind + 1000;
+    if !kind.is_job_result() {
+        return Err(EventBuilderError::WrongKind {
+            received: kind,
+            expected: WrongKindError::Range(NIP90_JOB_RESULT_RANGE),
+        }
+        .into());
+    }
+
+    // Same tags as `EventBuilder::job_result`, with the request `e` tag marked instead.
+    let mut result_tags: Vec<Tag> = job_request
+        .tags
+        .iter()
+        .filter(|t| t.kind() == TagKind::i())
+        .cloned()
+        .collect();
+    result_tags.extend([
+        job_result_event_tag(&job_request.id, "request"),
+        Tag::public_key(job_request.pubkey),
+        Tag::from_standardized_without_cell(TagStandard::Request(job_request.clone())),
+        Tag::from_standardized_without_cell(TagStandard::Amount {
+            millisats,
+            bolt11: payment.bolt11(),
+        }),
+    ]);
+    result_tags.extend(tags.unwrap_or_default());
+    result_tags.extend(payment.tag());
+    Ok(EventBuilder::new(kind, payload).tags(result_tags))
+}
+
+pub fn nostr_event_job_result_encrypted(
+    job_request: &Event,
+    keys: &Keys,
+    payload: impl Into<String>,
+    millisats: u64,
+    payment: Payment,
+    tags: Option<Vec<Tag>>,
+) -> Result<EventBuilder, NostrUtilsError> {
+    let payload = nip04::encrypt(keys.secret_key(), &job_request.pubkey, payload.into())
+        .map_err(|e| NostrUtilsError::EncryptionError(e.to_string()))?;
+    let mut tags = tags.unwrap_or_default();
+    tags.push(Tag::from_standardized(TagStandard::Encrypted));
+    nostr_event_job_result(job_request, payload, millisats, payment, Some(tags))
+}
+
+pub fn nostr_event_is_encrypted(event: &Event) -> bool {
+    event.tags.iter().any(|t| t.kind() == TagKind::Encrypted)
 }
 
 pub fn nostr_event_job_feedback(
     job_request: &Event,
-    error: JobRequestError,
-    status: &str,
+    status: DataVendingMachineStatus,
+    error: Option<JobRequestError>,
+    amount: Option<(u64, Payment)>,
     tags: Option<Vec<Tag>>,
 ) -> Result<EventBuilder, NostrUtilsError> {
-    let status = status
-        .parse::<DataVendingMachineStatus>()
-        .unwrap_or(DataVendingMachineStatus::Error);
-    let feedback_data =
-        JobFeedbackData::new(&job_request.clone(), status).extra_info(error.to_string());
-    let builder = EventBuilder::job_feedback(feedback_data).tags(tags.unwrap_or_default());
+    let mut feedback_data = JobFeedbackData::new(job_request, status);
+    let mut tags = tags.unwrap_or_default();
+    if let Some((millisats, payment)) = amount {
+        feedback_data = feedback_data.amount(millisats, payment.bolt11());
+        tags.extend(payment.tag());
+    }
+    if let Some(error) = error {
+        let extra_info = serde_json::to_string(&JobRequestFeedbackError::from(&error))
+            .unwrap_or_else(|_| error.to_string());
+        feedback_data = feedback_data.extra_info(extra_info);
+    }
+    let builder = EventBuilder::job_feedback(feedback_data).tags(tags);
     Ok(builder)
 }
 
 pub async fn nostr_send_event(
     client: Client,
     event: EventBuilder,
+    audit: &AuditLog,
+    dry_run: bool,
+) -> Result<Output<EventId>, NostrUtilsError> {
+    if dry_run {
+        let event = client.sign_event_builder(event).await?;
+        info!("dry run, not sending event: {}", event.as_json());
+        return Ok(Output {
+            val: event.id,
+            success: HashSet::new(),
+            failed: HashMap::new(),
+        });
+    }
+
+    let event = client.sign_event_builder(event).await?;
+    let output = client.send_event(&event).await?;
+    audit.record(AuditDirection::Out, &event);
+    Ok(output)
+}
+
+pub async fn nostr_send_event_with_relays(
+    client: Client,
+    event: EventBuilder,
+    relays: &[String],
+    audit: &AuditLog,
+    dry_run: bool,
 ) -> Result<Output<EventId>, NostrUtilsError> {
-    Ok(client.send_event_builder(event).await?)
+    if relays.is_empty() || dry_run {
+        return nostr_send_event(client, event, audit, dry_run).await;
+    }
+
+    let event = client.sign_event_builder(event).await?;
+    let output = client.send_event(&event).await?;
+    audit.record(AuditDirection::Out, &event);
+
+    let pool: HashSet<RelayUrl> = client.relays().await.into_keys().collect();
+    let hints: Vec<String> = nostr_relay_hints(relays, &pool)
+        .into_iter()
+        .cloned()
+        .collect();
+
+    // Hints are best effort, so a slow or dead one never holds up the publish.
+    tokio::spawn(async move {
+        if let Some(hint_client) = nostr_hint_client(hints, NOSTR_RELAY_HINT_TIMEOUT).await {
+            if let Err(e) = hint_client.send_event(&event).await {
+                warn!("failed to send event to relay hints: {e}");
+            }
+            hint_client.shutdown().await;
+        }
+    });
+
+    Ok(output)
+}
+
+pub async fn nostr_fetch_event_by_id(
+    client: Client,
+    cache: &EventCache,
+    event_id: &EventId,
+    relay: Option<&str>,
+    timeout: Duration,
+) -> Result<Option<Event>, NostrUtilsError> {
+    if let Some(event) = cache.get(event_id) {
+        return Ok(Some(event));
+    }
+
+    let filter = Filter::new().id(*event_id);
+
+    let mut event = match relay {
+        Some(relay) => nostr_fetch_events_from_relay(&client, relay, filter.clone(), timeout)
+            .await
+            .and_then(|events| events.into_iter().next()),
+        None => None,
+    };
+
+    if event.is_none() {
+        let Some(events) = nostr_fetch_events(&client, filter, timeout).await? else {
+            return Ok(None);
+        };
+        event = events.into_iter().next();
+    }
+
+    if let Some(event) = &event {
+        cache.insert(*event_id, event.clone());
+    }
+    Ok(event)
 }
 
-pub async fn nostr_fetch_event_by_id(client: Client, id: &str) -> Result<Event, NostrUtilsError> {
-    let event_id = EventId::parse(id)?;
-    let filter = Filter::new().id(event_id);
-    let events = client.fetch_events(filter, Duration::from_secs(10)).await?;
-    let event = events
-        .first()
-        .ok_or_else(|| NostrUtilsError::EventNotFound(event_id.to_hex()))?;
-    Ok(event.clone())
+pub async fn nostr_fetch_job_result(
+    client: Client,
+    job_request_id: &EventId,
+    timeout: Duration,
+) -> Result<Option<Event>, NostrUtilsError> {
+    let filter = Filter::new().event(*job_request_id);
+    let Some(events) = nostr_fetch_events(&client, filter, timeout).await? else {
+        return Ok(None);
+    };
+
+    Ok(events
+        .into_iter()
+        .filter(|e| e.kind.is_job_result())
+        .max_by_key(|e| e.created_at))
+}
+
+async fn nostr_fetch_events_from_relay(
+    client: &Client,
+    relay: &str,
+    filter: Filter,
+    timeout: Duration,
+) -> Option<Events> {
+    let Ok(url) = RelayUrl::parse(relay) else {
+        warn!("ignoring invalid relay hint {relay}");
+        return None;
+    };
+
+    let events = if client.relays().await.contains_key(&url) {
+        tokio::time::timeout(
+            timeout,
+            client.fetch_events_from([url.clone()], filter, timeout),
+        )
+        .await
+    } else {
+        let hint_client = nostr_hint_client([relay], timeout).await?;
+        let events = tokio::time::timeout(timeout, hint_client.fetch_events(filter, timeout)).await;
+        hint_client.shutdown().await;
+        events
+    };
+
+    match events {
+        Ok(Ok(events)) => Some(events),
+        Ok(Err(e)) => {
+            warn!("failed to fetch from relay hint {url}: {e}");
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Hints not already in the shared pool, capped so a request cannot fan a result out to any
+/// number of relays.
+fn nostr_relay_hints<'a>(relays: &'a [String], pool: &HashSet<RelayUrl>) -> Vec<&'a String> {
+    let mut hints: Vec<&String> = relays
+        .iter()
+        .filter(|r| !RelayUrl::parse(r).is_ok_and(|url| pool.contains(&url)))
+        .collect();
+    if hints.len() > NOSTR_RELAY_HINTS_MAX {
+        warn!(
+            "too many relay hints, ignoring {}",
+            hints.split_off(NOSTR_RELAY_HINTS_MAX).len()
+        );
+    }
+    hints
+}
+
+/// Connects a short-lived client to relay hints, so they never join the shared client's pool.
+/// It has no signer and never authenticates, whatever the configured relays' `auth`.
+async fn nostr_hint_client<I, S>(relays: I, timeout: Duration) -> Option<Client>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let client = Client::default();
+    let mut added = false;
+    for relay in relays {
+        let relay = relay.as_ref();
+        let Ok(url) = RelayUrl::parse(relay) else {
+            warn!("ignoring invalid relay hint {relay}");
+            continue;
+        };
+        match client.add_relay(url.clone()).await {
+            Ok(added_now) => added |= added_now,
+            Err(e) => warn!("failed to add relay hint {url}: {e}"),
+        }
+    }
+
+    if !added {
+        return None;
+    }
+    client.try_connect(timeout).await;
+    Some(client)
+}
+
+async fn nostr_fetch_events(
+    client: &Client,
+    filter: Filter,
+    timeout: Duration,
+) -> Result<Option<Events>, NostrUtilsError> {
+    match tokio::time::timeout(timeout, client.fetch_events(filter, timeout)).await {
+        Ok(events) => Ok(Some(events?)),
+        Err(_) => Ok(None),
+    }
 }
 
 pub fn nostr_tags_resolve(event: &Event, keys: &Keys) -> Result<Vec<Tag>, NostrTagsResolveError> {
-    if event.tags.iter().any(|t| t.kind() == TagKind::Encrypted) {
+    if nostr_event_is_encrypted(event) {
         let recipient = event
             .tags
             .iter()
@@ -204,3 +546,641 @@ pub fn nostr_tags_resolve(event: &Event, keys: &Keys) -> Result<Vec<Tag>, NostrT
         Ok(event.clone().tags.to_vec())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nostr_relay_builder::{
+        LocalRelay, MockRelay,
+        builder::{RelayBuilder, RelayBuilderNip42, RelayBuilderNip42Mode},
+    };
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    fn relay_url(relay: &MockRelay) -> RelayUrl {
+        RelayUrl::parse(&relay.url().to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn relay_roles_split_reads_and_writes() {
+        let read = MockRelay::run().await.unwrap();
+        let write = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let relays = [
+            RelaySettings {
+                url: relay_url(&read).to_string(),
+                role: RelayRole::Read,
+                auth: false,
+            },
+            RelaySettings {
+                url: relay_url(&write).to_string(),
+                role: RelayRole::Write,
+                auth: false,
+            },
+        ];
+
+        let client = nostr_client_new(keys.clone(), &relays);
+        nostr_client_add_relays(&client, &relays).await.unwrap();
+        nostr_client_connect(&client, Duration::from_secs(5), 2)
+            .await
+            .unwrap();
+
+        let event = EventBuilder::text_note("rhi")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let sent = client.send_event(&event).await.unwrap();
+        assert!(sent.success.contains(&relay_url(&write)));
+        assert!(!sent.success.contains(&relay_url(&read)));
+
+        let subscribed = client
+            .subscribe(Filter::new().kind(Kind::TextNote), None)
+            .await
+            .unwrap();
+        assert!(subscribed.success.contains(&relay_url(&read)));
+        assert!(!subscribed.success.contains(&relay_url(&write)));
+    }
+
+    fn relay_addr(relay: &MockRelay) -> String {
+        let url = relay_url(relay).to_string();
+        url.trim_start_matches("ws://")
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    /// Forwards connections to `relay`, counting each one; every hint fetch opens its own.
+    async fn counting_proxy(relay: &MockRelay) -> (String, Arc<AtomicUsize>) {
+        let upstream = relay_addr(relay);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let upstream = upstream.clone();
+                tokio::spawn(async move {
+                    if let Ok(mut outbound) = tokio::net::TcpStream::connect(upstream).await {
+                        let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                    }
+                });
+            }
+        });
+        (url, fetches)
+    }
+
+    #[tokio::test]
+    async fn fetch_event_served_from_cache() {
+        let relay = MockRelay::run().await.unwrap();
+        let (proxy, fetches) = counting_proxy(&relay).await;
+        let event = EventBuilder::text_note("rhi")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let cache = EventCache::new(4, Duration::from_secs(60));
+        cache.insert(event.id, event.clone());
+
+        let fetched = nostr_fetch_event_by_id(
+            Client::default(),
+            &cache,
+            &event.id,
+            Some(&proxy),
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(fetched.map(|e| e.id), Some(event.id));
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+    }
+
+    async fn connected_client(relay: &MockRelay, keys: &Keys) -> Client {
+        let client = Client::builder().signer(keys.clone()).build();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        client
+    }
+
+    #[tokio::test]
+    async fn fetched_event_cached() {
+        let relay = MockRelay::run().await.unwrap();
+        let (proxy, fetches) = counting_proxy(&relay).await;
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("rhi")
+            .sign_with_keys(&keys)
+            .unwrap();
+        connected_client(&relay, &keys)
+            .await
+            .send_event(&event)
+            .await
+            .unwrap();
+        let cache = EventCache::new(4, Duration::from_secs(60));
+
+        for _ in 0..2 {
+            let fetched = nostr_fetch_event_by_id(
+                Client::default(),
+                &cache,
+                &event.id,
+                Some(&proxy),
+                Duration::from_secs(2),
+            )
+            .await
+            .unwrap();
+            assert_eq!(fetched.map(|e| e.id), Some(event.id));
+        }
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(cache.get(&event.id).is_some());
+    }
+
+    #[tokio::test]
+    async fn fetch_from_hint_leaves_shared_pool_untouched() {
+        let hint = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("rhi")
+            .sign_with_keys(&keys)
+            .unwrap();
+        connected_client(&hint, &keys)
+            .await
+            .send_event(&event)
+            .await
+            .unwrap();
+        let client = Client::default();
+        let cache = EventCache::new(4, Duration::from_secs(60));
+
+        let fetched = nostr_fetch_event_by_id(
+            client.clone(),
+            &cache,
+            &event.id,
+            Some(&hint.url().to_string()),
+            Duration::from_secs(2),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(fetched.map(|e| e.id), Some(event.id));
+        assert!(client.relays().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_to_hint_leaves_shared_pool_untouched() {
+        let shared = MockRelay::run().await.unwrap();
+        let hint = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = connected_client(&shared, &keys).await;
+
+        let sent = nostr_send_event_with_relays(
+            client.clone(),
+            EventBuilder::text_note("rhi"),
+            &[hint.url().to_string()],
+            &AuditLog::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(client.relays().await.len(), 1);
+        assert!(hint_received(&hint, &sent.val).await);
+    }
+
+    /// Polls `relay` for `id`, since results reach relay hints in the background.
+    async fn hint_received(relay: &MockRelay, id: &EventId) -> bool {
+        let client = connected_client(relay, &Keys::generate()).await;
+        for _ in 0..20 {
+            let events = client
+                .fetch_events(Filter::new().id(*id), Duration::from_millis(500))
+                .await
+                .unwrap();
+            if !events.is_empty() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        false
+    }
+
+    #[test]
+    fn relay_hints_skip_pool_and_capped() {
+        let shared = RelayUrl::parse("wss://shared.test").unwrap();
+        let mut relays = vec![shared.to_string()];
+        relays.extend((0..NOSTR_RELAY_HINTS_MAX + 4).map(|i| format!("wss://hint{i}.test")));
+
+        let hints = nostr_relay_hints(&relays, &HashSet::from([shared]));
+
+        assert_eq!(hints.len(), NOSTR_RELAY_HINTS_MAX);
+        assert_eq!(hints[0], "wss://hint0.test");
+    }
+
+    #[tokio::test]
+    async fn client_new_signs_with_profile_keys() {
+        let keys = Keys::generate();
+        let relays = [RelaySettings {
+            url: "wss://private.test".into(),
+            role: RelayRole::Both,
+            auth: true,
+        }];
+
+        let client = nostr_client_new(keys.clone(), &relays);
+
+        let signer = client.signer().await.unwrap();
+        assert_eq!(signer.get_public_key().await.unwrap(), keys.public_key());
+    }
+
+    #[tokio::test]
+    async fn auth_relay_delivers_after_authentication() {
+        let relay = LocalRelay::run(RelayBuilder::default().nip42(RelayBuilderNip42 {
+            mode: RelayBuilderNip42Mode::Read,
+        }))
+        .await
+        .unwrap();
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("rhi")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let publisher = Client::default();
+        publisher.add_relay(relay.url()).await.unwrap();
+        publisher.connect().await;
+        publisher.wait_for_connection(Duration::from_secs(5)).await;
+        publisher.send_event(&event).await.unwrap();
+        let id = event.id;
+
+        let fetch = |auth: bool| {
+            let relays = [RelaySettings {
+                url: relay.url().to_string(),
+                role: RelayRole::Both,
+                auth,
+            }];
+            let keys = keys.clone();
+            async move {
+                let client = nostr_client_new(keys, &relays);
+                nostr_client_add_relays(&client, &relays).await.unwrap();
+                nostr_client_connect(&client, Duration::from_secs(5), 1)
+                    .await
+                    .unwrap();
+                client
+                    .fetch_events(Filter::new().id(id), Duration::from_secs(2))
+                    .await
+                    .map(|events| events.len())
+                    .unwrap_or(0)
+            }
+        };
+
+        assert_eq!(fetch(false).await, 0);
+        assert_eq!(fetch(true).await, 1);
+    }
+
+    #[tokio::test]
+    async fn auth_switch_applies_to_every_relay() {
+        let private = LocalRelay::run(RelayBuilder::default().nip42(RelayBuilderNip42 {
+            mode: RelayBuilderNip42Mode::Read,
+        }))
+        .await
+        .unwrap();
+        let public = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("rhi")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let publisher = Client::default();
+        publisher.add_relay(private.url()).await.unwrap();
+        publisher.connect().await;
+        publisher.wait_for_connection(Duration::from_secs(5)).await;
+        publisher.send_event(&event).await.unwrap();
+
+        // Only the public relay sets auth, yet the private one is answered too.
+        let relays = [
+            RelaySettings {
+                url: private.url().to_string(),
+                role: RelayRole::Both,
+                auth: false,
+            },
+            RelaySettings {
+                url: public.url().to_string(),
+                role: RelayRole::Both,
+                auth: true,
+            },
+        ];
+        let client = nostr_client_new(keys, &relays);
+        nostr_client_add_relays(&client, &relays).await.unwrap();
+        nostr_client_connect(&client, Duration::from_secs(5), 2)
+            .await
+            .unwrap();
+
+        let fetched = client
+            .fetch_events(Filter::new().id(event.id), Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(fetched.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn connect_requires_min_relays() {
+        let relay = MockRelay::run().await.unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_url = format!("ws://{}", closed.local_addr().unwrap());
+        drop(closed);
+
+        let client = Client::default();
+        client.add_relay(relay.url()).await.unwrap();
+        client.add_relay(closed_url).await.unwrap();
+
+        let timeout = Duration::from_millis(500);
+        assert_eq!(nostr_client_connect(&client, timeout, 1).await.unwrap(), 1);
+        assert!(matches!(
+            nostr_client_connect(&client, timeout, 2).await,
+            Err(NostrUtilsError::RelayQuorum {
+                connected: 1,
+                required: 2
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn fetch_event_times_out_on_silent_relay() {
+        let relay = MockRelay::run().await.unwrap();
+        let upstream = relay_addr(&relay);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        // Completes the websocket handshake, then swallows every request frame.
+        tokio::spawn(async move {
+            while let Ok((inbound, _)) = listener.accept().await {
+                let upstream = upstream.clone();
+                tokio::spawn(async move {
+                    let Ok(outbound) = tokio::net::TcpStream::connect(upstream).await else {
+                        return;
+                    };
+                    let (mut in_read, mut in_write) = inbound.into_split();
+                    let (mut out_read, mut out_write) = outbound.into_split();
+                    let mut handshake = [0u8; 4096];
+                    let Ok(n) = in_read.read(&mut handshake).await else {
+                        return;
+                    };
+                    if out_write.write_all(&handshake[..n]).await.is_err() {
+                        return;
+                    }
+                    tokio::spawn(async move {
+                        let _ = tokio::io::copy(&mut out_read, &mut in_write).await;
+                    });
+                    let _ = tokio::io::copy(&mut in_read, &mut tokio::io::sink()).await;
+                });
+            }
+        });
+        let client = Client::default();
+        client.add_relay(url).await.unwrap();
+        client.connect().await;
+        client.wait_for_connection(Duration::from_secs(5)).await;
+        let cache = EventCache::new(4, Duration::from_secs(60));
+
+        let fetched = tokio::time::timeout(
+            Duration::from_secs(5),
+            nostr_fetch_event_by_id(
+                client,
+                &cache,
+                &EventId::all_zeros(),
+                None,
+                Duration::from_millis(200),
+            ),
+        )
+        .await
+        .expect("fetch outlived its timeout");
+
+        assert!(matches!(fetched, Ok(None)));
+    }
+
+    #[test]
+    fn recent_events_filter_looks_back() {
+        let now = Timestamp::now().as_u64();
+
+        let filter = nostr_filter_recent_events(Filter::new(), Duration::from_secs(600));
+        let since = filter.since.unwrap().as_u64();
+        assert!((now - 600..=now - 599).contains(&since));
+
+        let filter = nostr_filter_recent_events(Filter::new(), Duration::ZERO);
+        assert!(filter.since.unwrap().as_u64() >= now);
+    }
+
+    #[test]
+    fn filter_kinds_matches_each_kind() {
+        let filter = nostr_filter_kinds(&[5300, 5301]);
+        let kinds = filter.kinds.unwrap();
+
+        assert_eq!(kinds.len(), 2);
+        assert!(kinds.contains(&Kind::Custom(5300)));
+        assert!(kinds.contains(&Kind::Custom(5301)));
+    }
+
+    #[test]
+    fn job_result_marks_single_request_tag() {
+        let keys = Keys::generate();
+        let job_request = EventBuilder::new(Kind::JobRequest(5300), "")
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        let result = nostr_event_job_result(&job_request, "{}", 0, Payment::None, None)
+            .unwrap()
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        let request_tags: Vec<&[String]> = result
+            .tags
+            .iter()
+            .filter(|t| t.kind() == TagKind::e())
+            .map(|t| t.as_slice())
+            .collect();
+        assert_eq!(request_tags.len(), 1);
+        assert_eq!(request_tags[0][1], job_request.id.to_hex());
+        assert_eq!(request_tags[0][3], "request");
+        assert_eq!(result.kind, Kind::JobResult(6300));
+    }
+
+    #[test]
+    fn job_result_rejects_non_request_kind() {
+        let note = EventBuilder::text_note("rhi")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+
+        assert!(nostr_event_job_result(&note, "{}", 0, Payment::None, None).is_err());
+    }
+
+    #[test]
+    fn encrypted_request_gets_encrypted_result() {
+        let requester = Keys::generate();
+        let provider = Keys::generate();
+        let input = Tag::parse(["i", "{}", "text"]).unwrap();
+        let cleartext = serde_json::to_string(&vec![input.clone()]).unwrap();
+        let content =
+            nip04::encrypt(requester.secret_key(), &provider.public_key(), cleartext).unwrap();
+        let request = EventBuilder::new(Kind::JobRequest(5300), content)
+            .tags([
+                Tag::public_key(provider.public_key()),
+                Tag::from_standardized(TagStandard::Encrypted),
+            ])
+            .sign_with_keys(&requester)
+            .unwrap();
+
+        let tags = nostr_tags_resolve(&request, &provider).unwrap();
+        assert_eq!(tags, vec![input]);
+
+        let output = Tag::parse(["output", "application/json"]).unwrap();
+        let result = nostr_event_job_result_encrypted(
+            &request,
+            &provider,
+            r#"{"total":1}"#,
+            0,
+            Payment::None,
+            Some(vec![output]),
+        )
+        .unwrap()
+        .sign_with_keys(&provider)
+        .unwrap();
+
+        assert!(nostr_event_is_encrypted(&result));
+        let names: Vec<&str> = result
+            .tags
+            .iter()
+            .map(|t| t.as_slice()[0].as_str())
+            .collect();
+        assert_eq!(
+            names,
+            ["e", "p", "request", "amount", "output", "encrypted"]
+        );
+        assert!(
+            result
+                .tags
+                .public_keys()
+                .any(|pk| *pk == requester.public_key())
+        );
+        let payload = nip04::decrypt(
+            requester.secret_key(),
+            &provider.public_key(),
+            &result.content,
+        )
+        .unwrap();
+        assert_eq!(payload, r#"{"total":1}"#);
+    }
+
+    fn feedback_tag(event: &Event, name: &str) -> Vec<String> {
+        event
+            .tags
+            .iter()
+            .map(|t| t.as_slice())
+            .find(|t| t[0] == name)
+            .map(|t| t[1..].to_vec())
+            .unwrap_or_default()
+    }
+
+    fn feedback_status(event: &Event) -> Vec<String> {
+        feedback_tag(event, "status")
+    }
+
+    #[test]
+    fn job_feedback_keeps_success_status() {
+        let keys = Keys::generate();
+        let job_request = EventBuilder::new(Kind::JobRequest(5300), "")
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        let feedback = nostr_event_job_feedback(
+            &job_request,
+            DataVendingMachineStatus::Success,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+        .sign_with_keys(&keys)
+        .unwrap();
+
+        assert_eq!(feedback.kind, Kind::JobFeedback);
+        assert_eq!(feedback_status(&feedback), vec!["success".to_string()]);
+    }
+
+    #[test]
+    fn job_feedback_error_carries_extra_info() {
+        let keys = Keys::generate();
+        let job_request = EventBuilder::new(Kind::JobRequest(5300), "")
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        let feedback = nostr_event_job_feedback(
+            &job_request,
+            DataVendingMachineStatus::Error,
+            Some(JobRequestError::MissingInputMarker("id".into())),
+            None,
+            None,
+        )
+        .unwrap()
+        .sign_with_keys(&keys)
+        .unwrap();
+
+        let status = feedback_status(&feedback);
+        assert_eq!(status[0], "error");
+        assert!(status[1].contains("MISSING_INPUT_MARKER"));
+    }
+
+    #[test]
+    fn job_feedback_carries_amount_and_bolt11() {
+        let keys = Keys::generate();
+        let job_request = EventBuilder::new(Kind::JobRequest(5300), "")
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        let feedback = nostr_event_job_feedback(
+            &job_request,
+            DataVendingMachineStatus::PaymentRequired,
+            None,
+            Some((21_000, Payment::Bolt11("lnbc210n1rhi".into()))),
+            None,
+        )
+        .unwrap()
+        .sign_with_keys(&keys)
+        .unwrap();
+
+        assert_eq!(feedback_status(&feedback), ["payment-required"]);
+        assert_eq!(feedback_tag(&feedback, "amount"), ["21000", "lnbc210n1rhi"]);
+    }
+
+    #[test]
+    fn job_feedback_and_result_carry_bolt12() {
+        let keys = Keys::generate();
+        let job_request = EventBuilder::new(Kind::JobRequest(5300), "")
+            .sign_with_keys(&keys)
+            .unwrap();
+        let offer = || Payment::Bolt12("lno1rhi".into());
+
+        let feedback = nostr_event_job_feedback(
+            &job_request,
+            DataVendingMachineStatus::PaymentRequired,
+            None,
+            Some((21_000, offer())),
+            None,
+        )
+        .unwrap()
+        .sign_with_keys(&keys)
+        .unwrap();
+
+        assert_eq!(feedback_tag(&feedback, "amount"), ["21000"]);
+        assert_eq!(feedback_tag(&feedback, "bolt12"), ["lno1rhi"]);
+
+        let result = nostr_event_job_result(&job_request, "{}", 21_000, offer(), None)
+            .unwrap()
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        assert_eq!(feedback_tag(&result, "amount"), ["21000"]);
+        assert_eq!(feedback_tag(&result, "bolt12"), ["lno1rhi"]);
+
+        let unpaid = nostr_event_job_result(&job_request, "{}", 0, Payment::None, None)
+            .unwrap()
+            .sign_with_keys(&keys)
+            .unwrap();
+
+        assert!(feedback_tag(&unpaid, "bolt12").is_empty());
+    }
+}
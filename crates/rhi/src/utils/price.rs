@@ -1,4 +1,51 @@
-use super::unit::{MassUnit, convert_mass};
+use radroots_common::models::{
+    listing_order::{
+        ListingOrder, ListingOrderDiscount, ListingOrderPrice, ListingOrderQuantity,
+        ListingOrderSubtotal, ListingOrderTotal,
+    },
+    listing_order_request::ListingOrderRequestPayload,
+};
+use serde::Deserialize;
+
+use super::unit::{MassUnit, Unit, convert_mass};
+use crate::{
+    config::{RoundingMode, TierSelection},
+    handlers::job_request_order::{JobRequestOrderError, OrderUnsatisfiable},
+    models::{
+        event_classified::{
+            EventClassified, EventClassifiedDiscount, EventClassifiedPrice,
+            EventClassifiedShipping, OrderContext,
+        },
+        order_explanation::{DiscountSkipReason, OrderDiscountExplanation, OrderExplanation},
+        order_result::{
+            OrderClassifiedResult, OrderClassifiedShipping, OrderClassifiedTax, OrderResultTotal,
+        },
+    },
+};
+
+#[derive(Deserialize)]
+pub struct OrderSpec {
+    #[serde(flatten)]
+    pub payload: ListingOrderRequestPayload,
+    /// Overrides `quantity.count` for orders of part packages, e.g. `1.5` bags. When set,
+    /// `quantity.count` is ignored and only needs to be present for the payload to parse.
+    #[serde(default)]
+    pub fractional_count: Option<f64>,
+}
+
+impl OrderSpec {
+    pub fn count(&self) -> Result<f64, JobRequestOrderError> {
+        let Some(count) = self.fractional_count else {
+            return Ok(self.payload.quantity.count as f64);
+        };
+        if !(count.is_finite() && count > 0.0) {
+            return Err(JobRequestOrderError::InvalidOrder(format!(
+                "quantity fractional count {count} must be positive"
+            )));
+        }
+        Ok(count)
+    }
+}
 
 pub fn calculate_total_price(
     quantity_amount: f64,
@@ -13,3 +60,1544 @@ pub fn calculate_total_price(
     let price_per_quantity_unit = price_amount / price_quantity_amount;
     price_per_quantity_unit * total_mass_in_price_unit
 }
+
+fn best_fit_tier<'a>(
+    listing: &'a EventClassified,
+    total_qty: f64,
+    qty_unit: &Unit,
+    currency: &str,
+) -> Option<&'a EventClassifiedPrice> {
+    listing
+        .prices
+        .iter()
+        .filter(|p| p.currency.eq_ignore_ascii_case(currency) && p.quantity_amount > 0.0)
+        .filter_map(|p| {
+            let tier_qty = unit_amount_convert(p.quantity_amount, &p.quantity_unit, qty_unit)?;
+            let fits = tier_qty <= total_qty || approx_eq(tier_qty, total_qty, PRICE_REL_TOLERANCE);
+            fits.then_some((p, p.amount / tier_qty))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(p, _)| p)
+}
+
+/// Builds the `amount-unit-label` key (e.g. `1-kg-bag`) that quantity discounts match on.
+pub fn package_key(amount: f64, unit: &str, label: &str) -> String {
+    product_key_canonical(&format!("{amount}-{unit}-{label}"))
+}
+
+pub fn product_key_canonical(key: &str) -> String {
+    key.trim().to_lowercase()
+}
+
+pub fn unit_amount_convert(amount: f64, from: &Unit, to: &Unit) -> Option<f64> {
+    match (from, to) {
+        (Unit::Mass(from), Unit::Mass(to)) => Some(convert_mass(amount, from, to)),
+        (Unit::Count, Unit::Count) => Some(amount),
+        _ => None,
+    }
+}
+
+pub const PRICE_REL_TOLERANCE: f64 = 1e-9;
+
+pub fn approx_eq(a: f64, b: f64, rel_tol: f64) -> bool {
+    (a - b).abs() <= rel_tol * a.abs().max(b.abs())
+}
+
+pub fn round_amount(value: f64, mode: RoundingMode, places: u32) -> f64 {
+    let factor = 10f64.powi(places as i32);
+    let scaled = value * factor;
+    let rounded = match mode {
+        RoundingMode::HalfUp => scaled.round(),
+        RoundingMode::HalfEven => scaled.round_ties_even(),
+    };
+    rounded / factor
+}
+
+struct OrderSubtotal {
+    /// The tier priced, at the amount charged for it.
+    tier: EventClassifiedPrice,
+    qty_unit: Unit,
+    count: f64,
+    total_qty: f64,
+    subtotal: f64,
+    package_key: String,
+}
+
+fn order_subtotal(
+    listing: &EventClassified,
+    order: &OrderSpec,
+    ctx: &OrderContext,
+) -> Result<OrderSubtotal, JobRequestOrderError> {
+    let round = |value: f64| round_amount(value, ctx.rounding, 2);
+
+    if let Some(expires_at) = listing.expires_at.filter(|e| *e <= ctx.now) {
+        return Err(OrderUnsatisfiable::ListingExpired { expires_at }.into());
+    }
+
+    let quantity = &order.payload.quantity;
+    let price = &order.payload.price;
+    let count = order.count()?;
+
+    if let Some(min_order) = listing.min_order.filter(|min| count < *min as f64) {
+        return Err(OrderUnsatisfiable::BelowMinimumOrder {
+            count,
+            min: min_order,
+        }
+        .into());
+    }
+
+    if let Some(max_order) = listing.max_order.filter(|max| count > *max as f64) {
+        return Err(OrderUnsatisfiable::AboveMaximumOrder {
+            count,
+            max: max_order,
+        }
+        .into());
+    }
+
+    let qty_unit = quantity
+        .unit
+        .parse::<Unit>()
+        .map_err(|_| OrderUnsatisfiable::InvalidUnit { field: "quantity" })?;
+    let price_unit =
+        price
+            .quantity_unit
+            .parse::<Unit>()
+            .map_err(|_| OrderUnsatisfiable::InvalidUnit {
+                field: "price quantity",
+            })?;
+
+    let total_qty = quantity.amount * count;
+
+    // Listings are immutable, so stock is only checked as published, never decremented.
+    if let Some(stock) = &listing.stock {
+        let ordered = unit_amount_convert(total_qty, &qty_unit, &stock.unit).ok_or_else(|| {
+            OrderUnsatisfiable::StockUnitMismatch {
+                quantity_unit: qty_unit.to_string(),
+                stock_unit: stock.unit.to_string(),
+            }
+        })?;
+        if ordered > stock.amount && !approx_eq(ordered, stock.amount, PRICE_REL_TOLERANCE) {
+            return Err(OrderUnsatisfiable::OutOfStock.into());
+        }
+    }
+
+    let matched_packaging = listing
+        .quantities
+        .iter()
+        .any(|q| q.unit == qty_unit && approx_eq(q.amount, quantity.amount, PRICE_REL_TOLERANCE));
+
+    if !matched_packaging {
+        return Err(OrderUnsatisfiable::PackagingUnavailable {
+            amount: quantity.amount,
+            unit: quantity.unit.clone(),
+        }
+        .into());
+    }
+
+    let currency = ctx.currency.as_deref().unwrap_or(&price.currency);
+
+    if !listing.accepts_currency(currency) {
+        return Err(OrderUnsatisfiable::CurrencyNotAccepted {
+            currency: currency.to_string(),
+            accepted: listing.accepted_currencies(),
+        }
+        .into());
+    }
+
+    let matched_tier = listing.prices.iter().find(|p| {
+        unit_amount_convert(p.quantity_amount, &p.quantity_unit, &price_unit)
+            .is_some_and(|amount| approx_eq(amount, price.quantity_amount, PRICE_REL_TOLERANCE))
+            && p.currency.eq_ignore_ascii_case(currency)
+    });
+
+    let tier = match ctx.tier_selection {
+        TierSelection::Exact => matched_tier,
+        TierSelection::BestFit => {
+            matched_tier.or_else(|| best_fit_tier(listing, total_qty, &qty_unit, currency))
+        }
+    }
+    .ok_or_else(|| OrderUnsatisfiable::NoMatchingTier {
+        quantity_amount: price.quantity_amount,
+        quantity_unit: price.quantity_unit.clone(),
+        currency: currency.to_string(),
+    })?;
+
+    if listing.ambiguous_prices.iter().any(|p| p.same_tier(tier)) {
+        return Err(OrderUnsatisfiable::AmbiguousTier.into());
+    }
+
+    // Compared per unit, so a best-fit tier sized unlike the requested one is guarded too.
+    let tier_qty = unit_amount_convert(tier.quantity_amount, &tier.quantity_unit, &price_unit)
+        .filter(|qty| *qty > 0.0)
+        .ok_or_else(|| OrderUnsatisfiable::UnitMismatch {
+            quantity_unit: price_unit.to_string(),
+            price_unit: tier.quantity_unit.to_string(),
+        })?;
+    let listed = tier.amount * price.quantity_amount / tier_qty;
+    let priced = tier.currency.eq_ignore_ascii_case(&price.currency);
+    if priced
+        && !ctx
+            .price_policy
+            .accepts(price.amount, listed, PRICE_REL_TOLERANCE)
+    {
+        return Err(OrderUnsatisfiable::PriceMismatch {
+            expected: listed,
+            got: price.amount,
+        }
+        .into());
+    }
+
+    // A price the policy accepts away from the listed one is what the requester agreed to pay.
+    let tier = EventClassifiedPrice {
+        amount: if priced && !approx_eq(price.amount, listed, PRICE_REL_TOLERANCE) {
+            price.amount * tier_qty / price.quantity_amount
+        } else {
+            tier.amount
+        },
+        ..tier.clone()
+    };
+
+    let converted_qty =
+        unit_amount_convert(total_qty, &qty_unit, &tier.quantity_unit).ok_or_else(|| {
+            OrderUnsatisfiable::UnitMismatch {
+                quantity_unit: qty_unit.to_string(),
+                price_unit: tier.quantity_unit.to_string(),
+            }
+        })?;
+    let unit_price = tier.amount / tier.quantity_amount;
+    let subtotal = round(unit_price * converted_qty);
+
+    Ok(OrderSubtotal {
+        tier,
+        qty_unit,
+        count,
+        total_qty,
+        subtotal,
+        package_key: package_key(quantity.amount, &quantity.unit, &quantity.label),
+    })
+}
+
+fn discount_evaluate(
+    discount: &EventClassifiedDiscount,
+    line: &OrderSubtotal,
+    ctx: &OrderContext,
+) -> (Option<ListingOrderDiscount>, Option<DiscountSkipReason>) {
+    let round = |value: f64| round_amount(value, ctx.rounding, 2);
+    let tier_currency = &line.tier.currency;
+    let currency_mismatch = |currency: &str| DiscountSkipReason::CurrencyMismatch {
+        currency: currency.to_string(),
+        expected: tier_currency.clone(),
+    };
+
+    match discount {
+        EventClassifiedDiscount::Subtotal {
+            threshold,
+            currency,
+            value,
+            is_percent,
+            ..
+        } => {
+            let amt = if *is_percent {
+                round(line.subtotal * value / 100.0)
+            } else {
+                round(*value)
+            };
+            let skip = if !currency.eq_ignore_ascii_case(tier_currency) {
+                Some(currency_mismatch(currency))
+            } else if line.subtotal < *threshold {
+                Some(DiscountSkipReason::ThresholdNotMet {
+                    threshold: *threshold,
+                    actual: line.subtotal,
+                })
+            } else {
+                None
+            };
+            let discount = ListingOrderDiscount {
+                discount_type: "subtotal".into(),
+                threshold: Some(*threshold),
+                threshold_unit: None,
+                discount_per_unit: None,
+                discount_unit: None,
+                discount_percent: if *is_percent { Some(*value) } else { None },
+                discount_amount: amt,
+                currency: currency.clone(),
+            };
+            (Some(discount), skip)
+        }
+        EventClassifiedDiscount::Mass {
+            discount_unit,
+            threshold,
+            threshold_unit,
+            discount_per_unit,
+            currency,
+            is_percent,
+            ..
+        } => {
+            // A percentage of a mass is priced in the tier currency, whatever the discount names.
+            if !*is_percent && !currency.eq_ignore_ascii_case(tier_currency) {
+                return (None, Some(currency_mismatch(currency)));
+            }
+            let Ok(th_unit) = threshold_unit.parse::<MassUnit>() else {
+                let field = "threshold";
+                return (None, Some(DiscountSkipReason::InvalidUnit { field }));
+            };
+            let Ok(dis_unit) = discount_unit.parse::<MassUnit>() else {
+                let field = "discount";
+                return (None, Some(DiscountSkipReason::InvalidUnit { field }));
+            };
+            let Some(qty_mass_unit) = line.qty_unit.as_mass() else {
+                return (None, Some(DiscountSkipReason::NotMassQuantity));
+            };
+
+            let qty_in_th = convert_mass(line.total_qty, qty_mass_unit, &th_unit);
+            let amt = if *is_percent {
+                round(line.subtotal * discount_per_unit / 100.0)
+            } else {
+                let qty_in_dis = convert_mass(line.total_qty, qty_mass_unit, &dis_unit);
+                round(qty_in_dis * discount_per_unit)
+            };
+            let skip = (qty_in_th < *threshold).then(|| DiscountSkipReason::ThresholdNotMet {
+                threshold: *threshold,
+                actual: qty_in_th,
+            });
+            let discount = ListingOrderDiscount {
+                discount_type: "mass".into(),
+                threshold: Some(*threshold),
+                threshold_unit: Some(threshold_unit.clone()),
+                discount_per_unit: if *is_percent {
+                    None
+                } else {
+                    Some(*discount_per_unit)
+                },
+                discount_unit: Some(discount_unit.clone()),
+                discount_percent: if *is_percent {
+                    Some(*discount_per_unit)
+                } else {
+                    None
+                },
+                discount_amount: amt,
+                currency: currency.clone(),
+            };
+            (Some(discount), skip)
+        }
+        EventClassifiedDiscount::Quantity {
+            product_key,
+            min_count,
+            discount_per_unit,
+            currency,
+            ..
+        } => {
+            let amt = round(*discount_per_unit * line.count);
+            let skip = if !currency.eq_ignore_ascii_case(tier_currency) {
+                Some(currency_mismatch(currency))
+            } else if product_key_canonical(product_key) != line.package_key {
+                Some(DiscountSkipReason::WrongProduct {
+                    product_key: product_key.clone(),
+                    package_key: line.package_key.clone(),
+                })
+            } else if line.count < *min_count as f64 {
+                Some(DiscountSkipReason::ThresholdNotMet {
+                    threshold: *min_count as f64,
+                    actual: line.count,
+                })
+            } else {
+                None
+            };
+            let discount = ListingOrderDiscount {
+                discount_type: "quantity".into(),
+                threshold: Some(*min_count as f64),
+                threshold_unit: None,
+                discount_per_unit: Some(*discount_per_unit),
+                discount_unit: None,
+                discount_percent: None,
+                discount_amount: amt,
+                currency: currency.clone(),
+            };
+            (Some(discount), skip)
+        }
+    }
+}
+
+pub fn explain_order(
+    listing: &EventClassified,
+    order: &OrderSpec,
+    ctx: &OrderContext,
+) -> Result<OrderExplanation, JobRequestOrderError> {
+    let line = order_subtotal(listing, order, ctx)?;
+
+    let discounts = listing
+        .discounts
+        .iter()
+        .map(|d| {
+            let (discount, skip) = discount_evaluate(d, &line, ctx);
+            let skip = if d.is_active(ctx.now) {
+                skip
+            } else {
+                Some(DiscountSkipReason::Inactive)
+            };
+            OrderDiscountExplanation {
+                description: d.describe(),
+                triggered: skip.is_none(),
+                amount: discount.map(|d| d.discount_amount),
+                skip,
+            }
+        })
+        .collect();
+
+    Ok(OrderExplanation {
+        subtotal: line.subtotal,
+        currency: line.tier.currency.clone(),
+        discounts,
+    })
+}
+
+/// Applies a discount total to a subtotal, bounded to `0..=subtotal` whatever the discounts sum to.
+pub fn discounted_amount(subtotal: f64, total_discount: f64, mode: RoundingMode) -> f64 {
+    let discounted = (subtotal - total_discount.max(0.0)).clamp(0.0, subtotal.max(0.0));
+    round_amount(discounted, mode, 2)
+}
+
+/// Prices an order against a listing's packaging, tiers and discounts.
+///
+/// ```
+/// use nostr::EventId;
+/// use rhi::{
+///     models::event_classified::{
+///         EventClassified, EventClassifiedBasis, EventClassifiedListing, EventClassifiedPrice,
+///         EventClassifiedQuantity, OrderContext,
+///     },
+///     utils::{
+///         price::{OrderSpec, price_order},
+///         unit::{MassUnit, Unit},
+///     },
+/// };
+///
+/// let listing = EventClassified::new(
+///     EventId::all_zeros(),
+///     EventClassifiedBasis::default(),
+///     EventClassifiedListing::default(),
+///     vec![EventClassifiedPrice {
+///         amount: 12.0,
+///         currency: "USD".into(),
+///         quantity_amount: 1.0,
+///         quantity_unit: Unit::Mass(MassUnit::Kg),
+///     }],
+///     vec![EventClassifiedQuantity {
+///         amount: 500.0,
+///         unit: Unit::Mass(MassUnit::G),
+///         label: "bag".into(),
+///     }],
+///     vec![],
+/// );
+/// let order: OrderSpec = serde_json::from_value(serde_json::json!({
+///     "quantity": { "amount": 500.0, "unit": "g", "label": "bag", "count": 3 },
+///     "price": { "amount": 12.0, "currency": "USD", "quantity_amount": 1.0, "quantity_unit": "kg" },
+/// }))
+/// .unwrap();
+///
+/// let result = price_order(&listing, &order, &OrderContext::now()).unwrap();
+/// assert_eq!(result.order.total.price_amount, 18.0);
+/// ```
+pub fn price_order(
+    listing: &EventClassified,
+    order: &OrderSpec,
+    ctx: &OrderContext,
+) -> Result<OrderClassifiedResult, JobRequestOrderError> {
+    let round = |value: f64| round_amount(value, ctx.rounding, 2);
+    let quantity = &order.payload.quantity;
+
+    let line = order_subtotal(listing, order, ctx)?;
+    let tier = &line.tier;
+    let total_qty = line.total_qty;
+    let subtotal = line.subtotal;
+
+    let mut discounts: Vec<ListingOrderDiscount> = Vec::new();
+    for d in listing.discounts.iter().filter(|d| d.is_active(ctx.now)) {
+        // Skipped discounts, one in another currency included, are those `explain_order` reports.
+        match discount_evaluate(d, &line, ctx) {
+            (Some(discount), None) => discounts.push(discount),
+            (_, Some(DiscountSkipReason::InvalidUnit { field })) => {
+                return Err(OrderUnsatisfiable::InvalidUnit { field }.into());
+            }
+            _ => continue,
+        }
+    }
+
+    let total_discount: f64 = discounts.iter().map(|d| d.discount_amount).sum();
+    let discounted = discounted_amount(subtotal, total_discount, ctx.rounding);
+
+    // When the clamp applies, report only what was taken off, in the order discounts applied.
+    if total_discount > subtotal {
+        let mut remaining = round(subtotal - discounted);
+        for d in &mut discounts {
+            d.discount_amount = d.discount_amount.min(remaining);
+            remaining = round(remaining - d.discount_amount);
+        }
+    }
+
+    let tax = listing
+        .tax_rate_percent
+        .or(ctx.tax_rate_percent)
+        .filter(|rate| *rate > 0.0)
+        .map(|rate_percent| OrderClassifiedTax {
+            rate_percent,
+            price_amount: round(discounted * rate_percent / 100.0),
+            price_currency: tier.currency.clone(),
+        });
+
+    let total_tax = tax.as_ref().map_or(0.0, |t| t.price_amount);
+    let total = round(discounted + total_tax);
+
+    let shipping = match &listing.shipping {
+        Some(s) => {
+            if !s.currency().eq_ignore_ascii_case(&tier.currency) {
+                return Err(OrderUnsatisfiable::CurrencyMismatch {
+                    component: "shipping",
+                    currency: s.currency().to_string(),
+                    expected: tier.currency.clone(),
+                }
+                .into());
+            }
+
+            let quantity_kg = match &line.qty_unit {
+                Unit::Mass(unit) => convert_mass(total_qty, unit, &MassUnit::Kg),
+                Unit::Count if matches!(s, EventClassifiedShipping::Flat { .. }) => 0.0,
+                Unit::Count => {
+                    return Err(OrderUnsatisfiable::ShippingUnavailable.into());
+                }
+            };
+            Some(OrderClassifiedShipping {
+                quantity_kg,
+                price_amount: round(s.amount(quantity_kg)),
+                price_currency: tier.currency.clone(),
+            })
+        }
+        None => None,
+    };
+
+    let grand_total = shipping.as_ref().map(|s| OrderResultTotal {
+        price_amount: round(total + s.price_amount),
+        price_currency: tier.currency.clone(),
+    });
+
+    let order = ListingOrder {
+        quantity: ListingOrderQuantity {
+            amount: quantity.amount,
+            unit: quantity.unit.clone(),
+            label: quantity.label.clone(),
+        },
+        price: ListingOrderPrice {
+            amount: tier.amount,
+            currency: tier.currency.clone(),
+            quantity_amount: tier.quantity_amount,
+            quantity_unit: tier.quantity_unit.to_string(),
+        },
+        discounts,
+        subtotal: ListingOrderSubtotal {
+            price_amount: subtotal,
+            price_currency: tier.currency.clone(),
+            quantity_amount: total_qty,
+            quantity_unit: quantity.unit.clone(),
+        },
+        total: ListingOrderTotal {
+            price_amount: total,
+            price_currency: tier.currency.clone(),
+            quantity_amount: total_qty,
+            quantity_unit: quantity.unit.clone(),
+        },
+    };
+
+    Ok(OrderClassifiedResult {
+        order,
+        tax,
+        shipping,
+        grand_total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{EventId, Timestamp};
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::PricePolicy;
+    use crate::models::event_classified::{
+        EventClassifiedBasis, EventClassifiedListing, EventClassifiedQuantity, EventClassifiedStock,
+    };
+
+    fn listing() -> EventClassified {
+        EventClassified::new(
+            EventId::all_zeros(),
+            EventClassifiedBasis::default(),
+            EventClassifiedListing::default(),
+            vec![EventClassifiedPrice {
+                amount: 12.0,
+                currency: "USD".into(),
+                quantity_amount: 1.0,
+                quantity_unit: Unit::Mass(MassUnit::Kg),
+            }],
+            vec![EventClassifiedQuantity {
+                amount: 500.0,
+                unit: Unit::Mass(MassUnit::G),
+                label: "bag".into(),
+            }],
+            vec![],
+        )
+    }
+
+    fn order(count: u32) -> OrderSpec {
+        serde_json::from_value(json!({
+            "quantity": { "amount": 500.0, "unit": "g", "label": "bag", "count": count },
+            "price": {
+                "amount": 12.0,
+                "currency": "USD",
+                "quantity_amount": 1.0,
+                "quantity_unit": "kg",
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn price_order_from_plain_fields() {
+        let result = price_order(&listing(), &order(3), &OrderContext::now()).unwrap();
+
+        assert_eq!(result.order.subtotal.price_amount, 18.0);
+        assert_eq!(result.order.subtotal.quantity_amount, 1500.0);
+        assert_eq!(result.order.total.price_amount, 18.0);
+        assert_eq!(result.order.total.price_currency, "USD");
+        assert!(result.tax.is_none());
+        assert!(result.shipping.is_none());
+    }
+
+    #[test]
+    fn price_order_rejects_unlisted_packaging() {
+        let mut order = order(1);
+        order.payload.quantity.amount = 250.0;
+
+        let err = price_order(&listing(), &order, &OrderContext::now())
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::PackagingUnavailable { .. })
+        ));
+    }
+
+    #[test]
+    fn price_order_caps_discounts_at_subtotal() {
+        let subtotal_off = |value| EventClassifiedDiscount::Subtotal {
+            threshold: 0.0,
+            currency: "USD".into(),
+            value,
+            is_percent: false,
+            valid_from: None,
+            valid_until: None,
+        };
+        let mut listing = listing();
+        listing.discounts = vec![subtotal_off(10.0), subtotal_off(15.0)];
+
+        let result = price_order(&listing, &order(3), &OrderContext::now()).unwrap();
+
+        assert_eq!(result.order.total.price_amount, 0.0);
+        let amounts: Vec<f64> = result
+            .order
+            .discounts
+            .iter()
+            .map(|d| d.discount_amount)
+            .collect();
+        assert_eq!(amounts, vec![10.0, 8.0]);
+    }
+
+    #[test]
+    fn price_order_adds_tax_after_discounts() {
+        let mut listing = listing();
+        listing.tax_rate_percent = Some(10.0);
+        listing.discounts = vec![EventClassifiedDiscount::Subtotal {
+            threshold: 0.0,
+            currency: "USD".into(),
+            value: 3.0,
+            is_percent: false,
+            valid_from: None,
+            valid_until: None,
+        }];
+
+        let result = price_order(&listing, &order(3), &OrderContext::now()).unwrap();
+
+        let tax = result.tax.unwrap();
+        assert_eq!(tax.rate_percent, 10.0);
+        assert_eq!(tax.price_amount, 1.5);
+        assert_eq!(result.order.total.price_amount, 16.5);
+    }
+
+    #[test]
+    fn price_order_tax_rate_from_context() {
+        let ctx = OrderContext {
+            tax_rate_percent: Some(20.0),
+            ..OrderContext::now()
+        };
+
+        let result = price_order(&listing(), &order(3), &ctx).unwrap();
+        assert_eq!(result.tax.unwrap().price_amount, 3.6);
+        assert_eq!(result.order.total.price_amount, 21.6);
+
+        let mut listing = listing();
+        listing.tax_rate_percent = Some(5.0);
+        let result = price_order(&listing, &order(3), &ctx).unwrap();
+        assert_eq!(result.tax.unwrap().rate_percent, 5.0);
+    }
+
+    fn shipped(shipping: EventClassifiedShipping, count: u32) -> OrderClassifiedResult {
+        let mut listing = listing();
+        listing.shipping = Some(shipping);
+        price_order(&listing, &order(count), &OrderContext::now()).unwrap()
+    }
+
+    #[test]
+    fn price_order_shipping_per_kg() {
+        let result = shipped(
+            EventClassifiedShipping::PerKg {
+                amount_per_kg: 4.0,
+                currency: "USD".into(),
+            },
+            3,
+        );
+
+        let shipping = result.shipping.unwrap();
+        assert_eq!(shipping.quantity_kg, 1.5);
+        assert_eq!(shipping.price_amount, 6.0);
+        assert_eq!(result.grand_total.unwrap().price_amount, 24.0);
+    }
+
+    #[test]
+    fn price_order_shipping_free_above_threshold() {
+        let free_above = || EventClassifiedShipping::FreeAbove {
+            threshold: 2.0,
+            threshold_unit: MassUnit::Kg,
+            amount: 5.0,
+            currency: "USD".into(),
+        };
+
+        assert_eq!(shipped(free_above(), 3).shipping.unwrap().price_amount, 5.0);
+        assert_eq!(shipped(free_above(), 4).shipping.unwrap().price_amount, 0.0);
+    }
+
+    #[test]
+    fn price_order_shipping_currency_mismatch() {
+        let mut listing = listing();
+        listing.shipping = Some(EventClassifiedShipping::Flat {
+            amount: 5.0,
+            currency: "EUR".into(),
+        });
+
+        let err = price_order(&listing, &order(1), &OrderContext::now())
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::CurrencyMismatch {
+                component: "shipping",
+                ..
+            })
+        ));
+    }
+
+    fn count_listing() -> EventClassified {
+        let mut listing = listing();
+        listing.prices = vec![EventClassifiedPrice {
+            amount: 3.0,
+            currency: "USD".into(),
+            quantity_amount: 1.0,
+            quantity_unit: Unit::Count,
+        }];
+        listing.quantities.push(EventClassifiedQuantity {
+            amount: 1.0,
+            unit: Unit::Count,
+            label: "box".into(),
+        });
+        listing
+    }
+
+    fn count_order(unit: &str, amount: f64, count: u32) -> OrderSpec {
+        serde_json::from_value(json!({
+            "quantity": { "amount": amount, "unit": unit, "label": "box", "count": count },
+            "price": {
+                "amount": 3.0,
+                "currency": "USD",
+                "quantity_amount": 1.0,
+                "quantity_unit": "each",
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn price_order_count_units() {
+        let result = price_order(
+            &count_listing(),
+            &count_order("each", 1.0, 5),
+            &OrderContext::now(),
+        )
+        .unwrap();
+
+        assert_eq!(result.order.subtotal.price_amount, 15.0);
+        assert_eq!(result.order.total.quantity_amount, 5.0);
+    }
+
+    #[test]
+    fn price_order_mass_quantity_count_price_rejected() {
+        let err = price_order(
+            &count_listing(),
+            &count_order("g", 500.0, 1),
+            &OrderContext::now(),
+        )
+        .err()
+        .unwrap();
+
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::UnitMismatch { .. })
+        ));
+    }
+
+    fn mass_discount(discount_per_unit: f64, is_percent: bool) -> EventClassifiedDiscount {
+        EventClassifiedDiscount::Mass {
+            discount_unit: "kg".into(),
+            threshold: 1.0,
+            threshold_unit: "kg".into(),
+            discount_per_unit,
+            currency: "USD".into(),
+            is_percent,
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    fn discounted(discount: EventClassifiedDiscount, count: u32) -> ListingOrder {
+        let mut listing = listing();
+        listing.discounts = vec![discount];
+        price_order(&listing, &order(count), &OrderContext::now())
+            .unwrap()
+            .order
+    }
+
+    #[test]
+    fn mass_discount_fixed_per_unit() {
+        let order = discounted(mass_discount(2.0, false), 3);
+
+        assert_eq!(order.discounts[0].discount_amount, 3.0);
+        assert_eq!(order.total.price_amount, 15.0);
+    }
+
+    #[test]
+    fn mass_discount_percent_of_subtotal() {
+        let order = discounted(mass_discount(10.0, true), 3);
+
+        assert_eq!(order.discounts[0].discount_amount, 1.8);
+        assert_eq!(order.discounts[0].discount_percent, Some(10.0));
+        assert_eq!(order.total.price_amount, 16.2);
+    }
+
+    #[test]
+    fn mass_discount_below_threshold_skipped() {
+        let order = discounted(mass_discount(10.0, true), 1);
+
+        assert!(order.discounts.is_empty());
+        assert_eq!(order.total.price_amount, 6.0);
+    }
+
+    #[test]
+    fn explain_order_reports_each_discount() {
+        let mut listing = listing();
+        let mut expired = mass_discount(5.0, true);
+        if let EventClassifiedDiscount::Mass { valid_until, .. } = &mut expired {
+            *valid_until = Some(Timestamp::from(1));
+        }
+        listing.discounts = vec![
+            mass_discount(10.0, true),
+            EventClassifiedDiscount::Subtotal {
+                threshold: 20.0,
+                currency: "USD".into(),
+                value: 2.0,
+                is_percent: false,
+                valid_from: None,
+                valid_until: None,
+            },
+            EventClassifiedDiscount::Quantity {
+                product_key: "1-kg-bag".into(),
+                min_count: 1,
+                discount_per_unit: 1.0,
+                currency: "USD".into(),
+                valid_from: None,
+                valid_until: None,
+            },
+            expired,
+        ];
+
+        let explanation = explain_order(&listing, &order(3), &OrderContext::now()).unwrap();
+
+        assert_eq!(explanation.subtotal, 18.0);
+        assert_eq!(explanation.currency, "USD");
+        let [mass, subtotal, quantity, inactive] = &explanation.discounts[..] else {
+            panic!("expected four discounts");
+        };
+        assert!(mass.triggered);
+        assert_eq!(mass.amount, Some(1.8));
+        assert!(!subtotal.triggered);
+        assert!(matches!(
+            subtotal.skip,
+            Some(DiscountSkipReason::ThresholdNotMet { threshold, actual })
+                if threshold == 20.0 && actual == 18.0
+        ));
+        assert!(matches!(
+            &quantity.skip,
+            Some(DiscountSkipReason::WrongProduct { package_key, .. }) if package_key == "500-g-bag"
+        ));
+        assert!(matches!(inactive.skip, Some(DiscountSkipReason::Inactive)));
+
+        let json = serde_json::to_value(subtotal).unwrap();
+        assert_eq!(json["reason"], "threshold_not_met");
+        assert_eq!(json["threshold"], 20.0);
+    }
+
+    fn eur_mass_discount(discount_per_unit: f64, is_percent: bool) -> EventClassifiedDiscount {
+        let mut discount = mass_discount(discount_per_unit, is_percent);
+        if let EventClassifiedDiscount::Mass { currency, .. } = &mut discount {
+            *currency = "EUR".into();
+        }
+        discount
+    }
+
+    #[test]
+    fn discount_currency_mismatch_skipped() {
+        let subtotal = EventClassifiedDiscount::Subtotal {
+            threshold: 0.0,
+            currency: "EUR".into(),
+            value: 1.0,
+            is_percent: false,
+            valid_from: None,
+            valid_until: None,
+        };
+        let quantity = EventClassifiedDiscount::Quantity {
+            product_key: "500-g-bag".into(),
+            min_count: 1,
+            discount_per_unit: 1.0,
+            currency: "EUR".into(),
+            valid_from: None,
+            valid_until: None,
+        };
+        let fixed_mass = eur_mass_discount(2.0, false);
+
+        for discount in [subtotal, quantity, fixed_mass] {
+            let priced = discounted(discount.clone(), 3);
+            assert!(priced.discounts.is_empty());
+            assert_eq!(priced.total.price_amount, 18.0);
+
+            let mut listing = listing();
+            listing.discounts = vec![discount];
+            let explanation = explain_order(&listing, &order(3), &OrderContext::now()).unwrap();
+            assert!(matches!(
+                &explanation.discounts[0].skip,
+                Some(DiscountSkipReason::CurrencyMismatch { currency, expected })
+                    if currency == "EUR" && expected == "USD"
+            ));
+        }
+
+        let percent_mass = eur_mass_discount(10.0, true);
+        assert_eq!(discounted(percent_mass, 3).total.price_amount, 16.2);
+    }
+
+    #[test]
+    fn approx_eq_tolerates_float_noise() {
+        assert!(approx_eq(0.1 + 0.2, 0.3, PRICE_REL_TOLERANCE));
+        assert!(approx_eq(0.0, 0.0, PRICE_REL_TOLERANCE));
+        assert!(approx_eq(1e12 + 1e-3, 1e12, PRICE_REL_TOLERANCE));
+        assert!(!approx_eq(0.0, 1e-12, PRICE_REL_TOLERANCE));
+    }
+
+    #[test]
+    fn approx_eq_bounded_by_relative_tolerance() {
+        let inside = 12.0 * (1.0 + PRICE_REL_TOLERANCE / 2.0);
+        let outside = 12.0 * (1.0 + PRICE_REL_TOLERANCE * 2.0);
+
+        assert!(approx_eq(inside, 12.0, PRICE_REL_TOLERANCE));
+        assert!(approx_eq(12.0, inside, PRICE_REL_TOLERANCE));
+        assert!(!approx_eq(outside, 12.0, PRICE_REL_TOLERANCE));
+        assert!(!approx_eq(12.0, outside, PRICE_REL_TOLERANCE));
+    }
+
+    #[test]
+    fn price_order_accepts_price_with_float_noise() {
+        let mut order = order(1);
+        order.payload.price.amount = 0.1 + 0.2;
+        let mut listing = listing();
+        listing.prices[0].amount = 0.3;
+
+        assert!(price_order(&listing, &order, &OrderContext::now()).is_ok());
+
+        order.payload.price.amount = 0.3 * (1.0 + PRICE_REL_TOLERANCE * 2.0);
+        assert!(price_order(&listing, &order, &OrderContext::now()).is_err());
+    }
+
+    #[test]
+    fn price_order_policy_against_under_and_over_priced_orders() {
+        let priced = |price_policy: PricePolicy, requested: f64| {
+            let mut order = order(3);
+            order.payload.price.amount = requested;
+            let ctx = OrderContext {
+                price_policy,
+                ..OrderContext::now()
+            };
+            price_order(&listing(), &order, &ctx).map(|r| r.order)
+        };
+        let rejected = |result: Result<ListingOrder, JobRequestOrderError>, got: f64| {
+            matches!(
+                result,
+                Err(JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::PriceMismatch {
+                    expected,
+                    got: g,
+                })) if expected == 12.0 && g == got
+            )
+        };
+
+        assert!(rejected(priced(PricePolicy::Exact, 11.0), 11.0));
+        assert!(rejected(priced(PricePolicy::Exact, 13.0), 13.0));
+        assert!(rejected(priced(PricePolicy::AtMost, 13.0), 13.0));
+        assert!(rejected(priced(PricePolicy::AtLeast, 11.0), 11.0));
+
+        // An accepted price is the one charged and reported, not the listed one.
+        let under = priced(PricePolicy::AtMost, 11.0).unwrap();
+        assert_eq!(under.price.amount, 11.0);
+        assert_eq!(under.subtotal.price_amount, 16.5);
+        assert_eq!(under.total.price_amount, 16.5);
+
+        let over = priced(PricePolicy::AtLeast, 13.0).unwrap();
+        assert_eq!(over.price.amount, 13.0);
+        assert_eq!(over.total.price_amount, 19.5);
+
+        let listed = priced(PricePolicy::AtMost, 12.0).unwrap();
+        assert_eq!(listed.price.amount, 12.0);
+        assert_eq!(listed.total.price_amount, 18.0);
+    }
+
+    #[test]
+    fn price_order_selects_tier_by_context_currency() {
+        let mut listing = listing();
+        listing.prices.push(EventClassifiedPrice {
+            amount: 11.0,
+            currency: "EUR".into(),
+            quantity_amount: 1.0,
+            quantity_unit: Unit::Mass(MassUnit::Kg),
+        });
+        let ctx = |currency: &str| OrderContext {
+            currency: Some(currency.into()),
+            ..OrderContext::now()
+        };
+
+        let result = price_order(&listing, &order(2), &ctx("eur")).unwrap();
+        assert_eq!(result.order.subtotal.price_amount, 11.0);
+        assert_eq!(result.order.total.price_currency, "EUR");
+
+        let result = price_order(&listing, &order(2), &OrderContext::now()).unwrap();
+        assert_eq!(result.order.total.price_currency, "USD");
+
+        assert!(price_order(&listing, &order(2), &ctx("GBP")).is_err());
+    }
+
+    #[test]
+    fn price_order_best_fit_tier() {
+        let mut listing = listing();
+        listing.prices.extend([
+            EventClassifiedPrice {
+                amount: 5.0,
+                currency: "USD".into(),
+                quantity_amount: 500.0,
+                quantity_unit: Unit::Mass(MassUnit::G),
+            },
+            EventClassifiedPrice {
+                amount: 80.0,
+                currency: "USD".into(),
+                quantity_amount: 10.0,
+                quantity_unit: Unit::Mass(MassUnit::Kg),
+            },
+        ]);
+        let order: OrderSpec = serde_json::from_value(json!({
+            "quantity": { "amount": 500.0, "unit": "g", "label": "bag", "count": 3 },
+            "price": {
+                "amount": 3.0,
+                "currency": "USD",
+                "quantity_amount": 250.0,
+                "quantity_unit": "g",
+            },
+        }))
+        .unwrap();
+
+        let err = price_order(&listing, &order, &OrderContext::now())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::NoMatchingTier { .. })
+        ));
+
+        let best_fit = |price_policy| OrderContext {
+            tier_selection: TierSelection::BestFit,
+            price_policy,
+            ..OrderContext::now()
+        };
+
+        // The 500 g tier lists 250 g at 2.5, under the 3.0 the requester priced.
+        let err = price_order(&listing, &order, &best_fit(PricePolicy::Exact))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::PriceMismatch { expected, got })
+                if expected == 2.5 && got == 3.0
+        ));
+
+        let result = price_order(&listing, &order, &best_fit(PricePolicy::AtLeast)).unwrap();
+        assert_eq!(result.order.price.amount, 6.0);
+        assert_eq!(result.order.total.price_amount, 18.0);
+
+        let mut order = order;
+        order.payload.price.amount = 2.5;
+        let result = price_order(&listing, &order, &best_fit(PricePolicy::Exact)).unwrap();
+
+        assert_eq!(result.order.total.price_amount, 15.0);
+        assert_eq!(result.order.price.amount, 5.0);
+        assert_eq!(result.order.price.quantity_amount, 500.0);
+        assert_eq!(result.order.price.quantity_unit, "g");
+
+        // Only the 10 kg tier is left, larger than the 1.5 kg ordered.
+        listing.prices.drain(..2);
+        let err = price_order(&listing, &order, &best_fit(PricePolicy::Exact))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::NoMatchingTier { .. })
+        ));
+    }
+
+    #[test]
+    fn price_order_rejects_unaccepted_currency() {
+        let mut listing = listing();
+        listing.accepted_currencies = vec!["EUR".into()];
+
+        let err = price_order(&listing, &order(2), &OrderContext::now())
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            err,
+            JobRequestOrderError::Unsatisfiable(OrderUnsatisfiable::CurrencyNotAccepted {
+                ref currency,
+                ref accepted,
+            }) if currency == "USD" && accepted == &["EUR"]
+        ));
+    }
+
+    #[test]
+    fn price_order_enforces_order_count_bounds() {
+        let mut listing = listing();
+        listing.min_order = Some(2);
+        listing.max_order = Some(4);
+        let priced = |count| price_order(&listing, &order(count), &OrderContext::now());
+
+        assert!(matches!(
+            priced(1),
+            Err(JobRequestOrderError::Unsatisfiable(
+                OrderUnsatisfiable::BelowMinimumOrder { count: 1.0, min: 2 }
+            ))
+        ));
+        assert!(priced(2).is_ok());
+        assert!(priced(4).is_ok());
+        assert!(matches!(
+            priced(5),
+            Err(JobRequestOrderError::Unsatisfiable(
+                OrderUnsatisfiable::AboveMaximumOrder { count: 5.0, max: 4 }
+            ))
+        ));
+    }
+
+    #[test]
+    fn price_order_checks_stock() {
+        let mut listing = listing();
+        listing.stock = Some(EventClassifiedStock {
+            amount: 1.5,
+            unit: Unit::Mass(MassUnit::Kg),
+        });
+        let priced = |listing: &EventClassified, count| {
+            price_order(listing, &order(count), &OrderContext::now())
+        };
+
+        assert!(priced(&listing, 3).is_ok());
+        assert!(matches!(
+            priced(&listing, 4),
+            Err(JobRequestOrderError::Unsatisfiable(
+                OrderUnsatisfiable::OutOfStock
+            ))
+        ));
+
+        listing.stock = Some(EventClassifiedStock {
+            amount: 10.0,
+            unit: Unit::Count,
+        });
+        assert!(matches!(
+            priced(&listing, 1),
+            Err(JobRequestOrderError::Unsatisfiable(
+                OrderUnsatisfiable::StockUnitMismatch { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn price_order_reports_structured_reasons() {
+        let unsatisfiable = |listing: &EventClassified, order: &OrderSpec| {
+            let Err(JobRequestOrderError::Unsatisfiable(reason)) =
+                price_order(listing, order, &OrderContext::now())
+            else {
+                panic!("expected an unsatisfiable order");
+            };
+            reason
+        };
+
+        let mut per_lb = order(1);
+        per_lb.payload.price.quantity_unit = "lb".into();
+        let reason = unsatisfiable(&listing(), &per_lb);
+        assert!(matches!(
+            &reason,
+            OrderUnsatisfiable::NoMatchingTier { quantity_amount, quantity_unit, currency }
+                if *quantity_amount == 1.0 && quantity_unit == "lb" && currency == "USD"
+        ));
+        assert_eq!(
+            serde_json::to_value(&reason).unwrap()["reason"],
+            "no_matching_tier"
+        );
+
+        let mut overpriced = order(1);
+        overpriced.payload.price.amount = 13.0;
+        let reason = unsatisfiable(&listing(), &overpriced);
+        assert!(matches!(
+            reason,
+            OrderUnsatisfiable::PriceMismatch { expected, got } if expected == 12.0 && got == 13.0
+        ));
+        let json = serde_json::to_value(&reason).unwrap();
+        assert_eq!(json["reason"], "price_mismatch");
+        assert_eq!(json["expected"], 12.0);
+        assert_eq!(json["got"], 13.0);
+
+        let mut expired = listing();
+        expired.expires_at = Some(Timestamp::from(1));
+        let reason = unsatisfiable(&expired, &order(1));
+        assert!(matches!(
+            reason,
+            OrderUnsatisfiable::ListingExpired { expires_at } if expires_at == Timestamp::from(1)
+        ));
+        assert_eq!(
+            serde_json::to_value(&reason).unwrap()["reason"],
+            "listing_expired"
+        );
+    }
+
+    #[test]
+    fn unit_amount_convert_within_dimension() {
+        let (kg, g) = (Unit::Mass(MassUnit::Kg), Unit::Mass(MassUnit::G));
+
+        assert_eq!(unit_amount_convert(1.5, &kg, &g), Some(1500.0));
+        assert_eq!(
+            unit_amount_convert(3.0, &Unit::Count, &Unit::Count),
+            Some(3.0)
+        );
+        assert_eq!(unit_amount_convert(3.0, &Unit::Count, &g), None);
+    }
+
+    #[test]
+    fn price_order_matches_tier_in_other_mass_unit() {
+        let mut order = order(3);
+        order.payload.price.quantity_amount = 1000.0;
+        order.payload.price.quantity_unit = "g".into();
+
+        let result = price_order(&listing(), &order, &OrderContext::now()).unwrap();
+
+        assert_eq!(result.order.subtotal.price_amount, 18.0);
+    }
+
+    #[test]
+    fn price_order_reports_tier_unit() {
+        let mut listing = listing();
+        listing.prices = vec![EventClassifiedPrice {
+            amount: 10.0,
+            currency: "USD".into(),
+            quantity_amount: 1.0,
+            quantity_unit: Unit::Mass(MassUnit::Lb),
+        }];
+        listing.quantities = vec![EventClassifiedQuantity {
+            amount: 1.0,
+            unit: Unit::Mass(MassUnit::Kg),
+            label: "bag".into(),
+        }];
+        let order: OrderSpec = serde_json::from_value(json!({
+            "quantity": { "amount": 1.0, "unit": "kg", "label": "bag", "count": 2 },
+            "price": {
+                "amount": 10.0,
+                "currency": "USD",
+                "quantity_amount": 0.45359237,
+                "quantity_unit": "kg",
+            },
+        }))
+        .unwrap();
+
+        let result = price_order(&listing, &order, &OrderContext::now()).unwrap();
+
+        assert_eq!(result.order.price.amount, 10.0);
+        assert_eq!(result.order.price.quantity_amount, 1.0);
+        assert_eq!(result.order.price.quantity_unit, "lb");
+        assert_eq!(result.order.subtotal.price_amount, 44.09);
+        assert_eq!(result.order.subtotal.quantity_unit, "kg");
+    }
+
+    #[test]
+    fn round_amount_half_boundaries() {
+        assert_eq!(round_amount(2.5, RoundingMode::HalfUp, 0), 3.0);
+        assert_eq!(round_amount(2.5, RoundingMode::HalfEven, 0), 2.0);
+        assert_eq!(round_amount(3.5, RoundingMode::HalfEven, 0), 4.0);
+        assert_eq!(round_amount(-2.5, RoundingMode::HalfUp, 0), -3.0);
+        assert_eq!(round_amount(0.125, RoundingMode::HalfUp, 2), 0.13);
+        assert_eq!(round_amount(0.125, RoundingMode::HalfEven, 2), 0.12);
+    }
+
+    #[test]
+    fn price_order_uses_context_rounding() {
+        let mut listing = listing();
+        listing.prices[0].amount = 12.25;
+        let mut order = order(1);
+        order.payload.price.amount = 12.25;
+        let ctx = |rounding| OrderContext {
+            rounding,
+            ..OrderContext::now()
+        };
+
+        let half_up = price_order(&listing, &order, &ctx(RoundingMode::HalfUp)).unwrap();
+        let half_even = price_order(&listing, &order, &ctx(RoundingMode::HalfEven)).unwrap();
+
+        assert_eq!(half_up.order.subtotal.price_amount, 6.13);
+        assert_eq!(half_even.order.subtotal.price_amount, 6.12);
+    }
+
+    fn fractional_order(count: f64) -> OrderSpec {
+        let mut order = order(1);
+        order.fractional_count = Some(count);
+        order
+    }
+
+    #[test]
+    fn price_order_fractional_count() {
+        let result = price_order(&listing(), &fractional_order(1.5), &OrderContext::now()).unwrap();
+
+        assert_eq!(result.order.subtotal.quantity_amount, 750.0);
+        assert_eq!(result.order.subtotal.price_amount, 9.0);
+    }
+
+    #[test]
+    fn fractional_count_reads_integers() {
+        let mut value = json!({
+            "quantity": { "amount": 500.0, "unit": "g", "label": "bag", "count": 1 },
+            "price": {
+                "amount": 12.0,
+                "currency": "USD",
+                "quantity_amount": 1.0,
+                "quantity_unit": "kg",
+            },
+        });
+        let order: OrderSpec = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(order.fractional_count, None);
+        assert_eq!(order.count().unwrap(), 1.0);
+
+        // The fractional count wins over a disagreeing integer count.
+        value["fractional_count"] = json!(2);
+        let order: OrderSpec = serde_json::from_value(value).unwrap();
+        assert_eq!(order.fractional_count, Some(2.0));
+        assert_eq!(order.count().unwrap(), 2.0);
+    }
+
+    #[test]
+    fn fractional_count_rejects_invalid() {
+        for count in [f64::NAN, f64::INFINITY, -1.0, 0.0] {
+            assert!(matches!(
+                price_order(&listing(), &fractional_order(count), &OrderContext::now()),
+                Err(JobRequestOrderError::InvalidOrder(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn package_key_canonical() {
+        assert_eq!(package_key(500.0, "g", "bag"), "500-g-bag");
+        assert_eq!(package_key(1.5, "KG", " Bag"), "1.5-kg- bag");
+        assert_eq!(product_key_canonical(" 500-G-Bag "), "500-g-bag");
+    }
+
+    #[test]
+    fn quantity_discount_matches_product_key_case_insensitively() {
+        let mut listing = listing();
+        listing.discounts.push(EventClassifiedDiscount::Quantity {
+            product_key: " 500-G-Bag ".into(),
+            min_count: 2,
+            discount_per_unit: 1.0,
+            currency: "USD".into(),
+            valid_from: None,
+            valid_until: None,
+        });
+        let mut order = order(3);
+        order.payload.quantity.label = "BAG".into();
+
+        let result = price_order(&listing, &order, &OrderContext::now()).unwrap();
+
+        assert_eq!(result.order.discounts[0].discount_amount, 3.0);
+        assert_eq!(result.order.total.price_amount, 15.0);
+    }
+
+    #[test]
+    fn quantity_discount_min_count_uses_fractional_count() {
+        let mut listing = listing();
+        listing.discounts.push(EventClassifiedDiscount::Quantity {
+            product_key: "500-g-bag".into(),
+            min_count: 2,
+            discount_per_unit: 1.0,
+            currency: "USD".into(),
+            valid_from: None,
+            valid_until: None,
+        });
+        let priced = |count| price_order(&listing, &fractional_order(count), &OrderContext::now());
+
+        assert!(priced(1.5).unwrap().order.discounts.is_empty());
+
+        let result = priced(2.5).unwrap();
+        assert_eq!(result.order.discounts[0].discount_amount, 2.5);
+        assert_eq!(result.order.total.price_amount, 12.5);
+    }
+
+    #[test]
+    fn price_order_bounds_fractional_count() {
+        let mut listing = listing();
+        listing.min_order = Some(2);
+
+        assert!(matches!(
+            price_order(&listing, &fractional_order(1.5), &OrderContext::now()),
+            Err(JobRequestOrderError::Unsatisfiable(
+                OrderUnsatisfiable::BelowMinimumOrder { min: 2, .. }
+            ))
+        ));
+        assert!(price_order(&listing, &fractional_order(2.0), &OrderContext::now()).is_ok());
+    }
+
+    const PACKAGE_GRAMS: [f64; 3] = [250.0, 500.0, 1000.0];
+
+    fn discount_strategy() -> impl Strategy<Value = EventClassifiedDiscount> {
+        prop_oneof![
+            (0.0..500.0f64, 0.0..300.0f64, any::<bool>()).prop_map(
+                |(threshold, value, is_percent)| {
+                    EventClassifiedDiscount::Subtotal {
+                        threshold,
+                        currency: "USD".into(),
+                        value,
+                        is_percent,
+                        valid_from: None,
+                        valid_until: None,
+                    }
+                }
+            ),
+            (0.0..5.0f64, 0.0..300.0f64, any::<bool>()).prop_map(
+                |(threshold, discount_per_unit, is_percent)| EventClassifiedDiscount::Mass {
+                    discount_unit: "kg".into(),
+                    threshold,
+                    threshold_unit: "kg".into(),
+                    discount_per_unit,
+                    currency: "USD".into(),
+                    is_percent,
+                    valid_from: None,
+                    valid_until: None,
+                }
+            ),
+            (
+                prop::sample::select(PACKAGE_GRAMS.to_vec()),
+                0u32..20,
+                0.0..50.0f64,
+            )
+                .prop_map(|(grams, min_count, discount_per_unit)| {
+                    EventClassifiedDiscount::Quantity {
+                        product_key: package_key(grams, "g", "bag"),
+                        min_count,
+                        discount_per_unit,
+                        currency: "USD".into(),
+                        valid_from: None,
+                        valid_until: None,
+                    }
+                }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn price_order_total_within_subtotal(
+            tier_amount in 0.01..1_000.0f64,
+            tier_kg in 0.1..10.0f64,
+            grams in prop::sample::select(PACKAGE_GRAMS.to_vec()),
+            count in 1u32..100,
+            discounts in prop::collection::vec(discount_strategy(), 0..5),
+            half_even in any::<bool>(),
+        ) {
+            let mut listing = listing();
+            listing.prices = vec![EventClassifiedPrice {
+                amount: tier_amount,
+                currency: "USD".into(),
+                quantity_amount: tier_kg,
+                quantity_unit: Unit::Mass(MassUnit::Kg),
+            }];
+            listing.quantities = vec![EventClassifiedQuantity {
+                amount: grams,
+                unit: Unit::Mass(MassUnit::G),
+                label: "bag".into(),
+            }];
+            listing.discounts = discounts;
+            let order: OrderSpec = serde_json::from_value(json!({
+                "quantity": { "amount": grams, "unit": "g", "label": "bag", "count": count },
+                "price": {
+                    "amount": tier_amount,
+                    "currency": "USD",
+                    "quantity_amount": tier_kg,
+                    "quantity_unit": "kg",
+                },
+            }))
+            .unwrap();
+            let ctx = OrderContext {
+                rounding: if half_even {
+                    RoundingMode::HalfEven
+                } else {
+                    RoundingMode::HalfUp
+                },
+                ..OrderContext::now()
+            };
+
+            let result = price_order(&listing, &order, &ctx).unwrap();
+
+            let (total, subtotal) = (
+                result.order.total.price_amount,
+                result.order.subtotal.price_amount,
+            );
+            prop_assert!(total >= 0.0, "total {total} below zero");
+            prop_assert!(total <= subtotal, "total {total} above subtotal {subtotal}");
+            let discounted: f64 = result.order.discounts.iter().map(|d| d.discount_amount).sum();
+            prop_assert!(
+                (discounted - (subtotal - total)).abs() < 0.01,
+                "discounts {discounted} do not account for {subtotal} - {total}"
+            );
+        }
+    }
+}
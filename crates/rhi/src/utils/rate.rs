@@ -0,0 +1,124 @@
+use std::{collections::HashMap, sync::Arc};
+
+use futures::future::BoxFuture;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FiatRateError {
+    #[error("No msat rate for currency {0}")]
+    UnknownCurrency(String),
+
+    #[error("Invalid msat rate {1} for currency {0}")]
+    InvalidRate(String, f64),
+}
+
+pub trait FiatRateProvider {
+    /// Millisats per unit of `currency`.
+    fn rate<'a>(&'a self, currency: &'a str) -> BoxFuture<'a, Result<f64, FiatRateError>>;
+}
+
+/// The provider handlers price orders with, shared by every profile.
+pub type SharedFiatRateProvider = Arc<dyn FiatRateProvider + Send + Sync>;
+
+#[derive(Debug, Clone, Default)]
+pub struct StaticFiatRateProvider {
+    rates: HashMap<String, f64>,
+}
+
+impl StaticFiatRateProvider {
+    pub fn new(rates: &HashMap<String, f64>) -> Self {
+        Self {
+            rates: rates
+                .iter()
+                .map(|(currency, rate)| (currency.to_uppercase(), *rate))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+}
+
+impl FiatRateProvider for StaticFiatRateProvider {
+    fn rate<'a>(&'a self, currency: &'a str) -> BoxFuture<'a, Result<f64, FiatRateError>> {
+        Box::pin(async move {
+            let rate = *self
+                .rates
+                .get(&currency.to_uppercase())
+                .ok_or_else(|| FiatRateError::UnknownCurrency(currency.to_string()))?;
+
+            if !rate.is_finite() || rate < 0.0 {
+                return Err(FiatRateError::InvalidRate(currency.to_string(), rate));
+            }
+
+            Ok(rate)
+        })
+    }
+}
+
+pub async fn fiat_amount_msat<P: FiatRateProvider + ?Sized>(
+    provider: &P,
+    amount: f64,
+    currency: &str,
+) -> Result<u64, FiatRateError> {
+    let rate = provider.rate(currency).await?;
+    Ok((amount * rate).round().max(0.0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(rate: f64) -> StaticFiatRateProvider {
+        StaticFiatRateProvider::new(&HashMap::from([("usd".to_string(), rate)]))
+    }
+
+    #[tokio::test]
+    async fn static_rate_ignores_currency_case() {
+        let provider = provider(1_500_000.0);
+
+        assert_eq!(provider.rate("USD").await.unwrap(), 1_500_000.0);
+        assert_eq!(provider.rate("Usd").await.unwrap(), 1_500_000.0);
+    }
+
+    #[tokio::test]
+    async fn static_rate_errors() {
+        assert!(matches!(
+            provider(1_500_000.0).rate("EUR").await,
+            Err(FiatRateError::UnknownCurrency(_))
+        ));
+        assert!(matches!(
+            provider(-1.0).rate("USD").await,
+            Err(FiatRateError::InvalidRate(_, _))
+        ));
+        assert!(matches!(
+            provider(f64::NAN).rate("USD").await,
+            Err(FiatRateError::InvalidRate(_, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fiat_amount_msat_rounds() {
+        let provider = provider(1_500_000.0);
+
+        assert_eq!(
+            fiat_amount_msat(&provider, 12.345, "USD").await.unwrap(),
+            18_517_500
+        );
+        assert_eq!(
+            fiat_amount_msat(&provider, 0.0000003, "USD").await.unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn shared_provider_prices_amounts() {
+        let shared: SharedFiatRateProvider = Arc::new(provider(1_500_000.0));
+
+        assert_eq!(
+            fiat_amount_msat(shared.as_ref(), 2.0, "usd").await.unwrap(),
+            3_000_000
+        );
+    }
+}
@@ -1,6 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::{fmt, str::FromStr};
 use thiserror::Error;
+use typeshare::typeshare;
+
+pub const GRAMS_PER_KG: f64 = 1000.0;
+pub const GRAMS_PER_OZ: f64 = 28.349523125;
+pub const GRAMS_PER_LB: f64 = 453.59237;
+
+/// Relative error bound for a round trip through `convert_mass` between any two units.
+pub const MASS_CONVERSION_EPSILON: f64 = 1e-12;
 
 #[derive(Debug, Error)]
 pub enum MassUnitError {
@@ -11,6 +19,7 @@ pub enum MassUnitError {
     InvalidAmount(f64),
 }
 
+#[typeshare]
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum MassUnit {
@@ -24,9 +33,9 @@ impl MassUnit {
     pub fn to_grams(&self) -> f64 {
         match self {
             MassUnit::G => 1.0,
-            MassUnit::Kg => 1000.0,
-            MassUnit::Oz => 28.3495,
-            MassUnit::Lb => 453.592,
+            MassUnit::Kg => GRAMS_PER_KG,
+            MassUnit::Oz => GRAMS_PER_OZ,
+            MassUnit::Lb => GRAMS_PER_LB,
         }
     }
 
@@ -35,14 +44,7 @@ impl MassUnit {
             return Err(MassUnitError::InvalidAmount(amount));
         }
 
-        let factor = match self {
-            MassUnit::G => 1.0,
-            MassUnit::Kg => 1000.0,
-            MassUnit::Oz => 28.3495,
-            MassUnit::Lb => 453.592,
-        };
-
-        Ok(amount * factor)
+        Ok(amount * self.to_grams())
     }
 }
 
@@ -72,7 +74,113 @@ impl FromStr for MassUnit {
     }
 }
 
+#[typeshare(serialized_as = "String")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Unit {
+    Mass(MassUnit),
+    Count,
+}
+
+impl Unit {
+    pub fn as_mass(&self) -> Option<&MassUnit> {
+        match self {
+            Unit::Mass(unit) => Some(unit),
+            Unit::Count => None,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Mass(unit) => write!(f, "{unit}"),
+            Unit::Count => write!(f, "each"),
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = MassUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "each" | "ea" | "piece" | "pc" | "unit" => Ok(Unit::Count),
+            other => Ok(Unit::Mass(other.parse()?)),
+        }
+    }
+}
+
+impl TryFrom<String> for Unit {
+    type Error = MassUnitError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Unit> for String {
+    fn from(unit: Unit) -> Self {
+        unit.to_string()
+    }
+}
+
 pub fn convert_mass(amount: f64, from_unit: &MassUnit, to_unit: &MassUnit) -> f64 {
     let amount_g = amount * from_unit.to_grams();
     amount_g / to_unit.to_grams()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNITS: [MassUnit; 4] = [MassUnit::G, MassUnit::Kg, MassUnit::Oz, MassUnit::Lb];
+    const AMOUNTS: [f64; 6] = [0.001, 0.5, 1.0, 12.345, 1_000.0, 987_654.321];
+
+    fn assert_within_epsilon(actual: f64, expected: f64) {
+        let error = (actual - expected).abs() / expected.abs();
+        assert!(
+            error <= MASS_CONVERSION_EPSILON,
+            "{actual} differs from {expected} by {error}"
+        );
+    }
+
+    fn round_trip(amount: f64, from: &MassUnit, via: &MassUnit) -> f64 {
+        convert_mass(convert_mass(amount, from, via), via, from)
+    }
+
+    #[test]
+    fn round_trip_g_lb_g() {
+        for amount in AMOUNTS {
+            assert_within_epsilon(round_trip(amount, &MassUnit::G, &MassUnit::Lb), amount);
+        }
+    }
+
+    #[test]
+    fn round_trip_kg_oz_kg() {
+        for amount in AMOUNTS {
+            assert_within_epsilon(round_trip(amount, &MassUnit::Kg, &MassUnit::Oz), amount);
+        }
+    }
+
+    #[test]
+    fn round_trip_all_units() {
+        for from in &UNITS {
+            for via in &UNITS {
+                for amount in AMOUNTS {
+                    assert_within_epsilon(round_trip(amount, from, via), amount);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn conversion_factors_exact() {
+        assert_within_epsilon(convert_mass(1.0, &MassUnit::Lb, &MassUnit::G), 453.59237);
+        assert_within_epsilon(convert_mass(16.0, &MassUnit::Oz, &MassUnit::Lb), 1.0);
+        assert_within_epsilon(
+            convert_mass(1.0, &MassUnit::Kg, &MassUnit::Lb),
+            2.204_622_621_848_776,
+        );
+    }
+}